@@ -0,0 +1,177 @@
+//! Runs the MQTT side of the air filter on the host against a real broker, with the fan
+//! simulated by the run logic alone (it switches as soon as it is told to).
+//!
+//! Start a broker (e.g. `mosquitto -v`) and run this with its address:
+//!
+//! ```sh
+//! cargo run --example mqtt -- 127.0.0.1:1883
+//! ```
+//!
+//! Then watch and control it with:
+//!
+//! ```sh
+//! mosquitto_sub -v -t 'ms-air-filter/#'
+//! mosquitto_pub -t ms-air-filter/host/command -m 'start 1 high'
+//! ```
+
+// Most of the library's dependencies are not used directly
+#![allow(unused_crate_dependencies)]
+
+use embassy_futures::block_on;
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+use ms_air_filter_core::{
+    config::Config,
+    fan::FanStatus,
+    mqtt::{
+        discovery::{Discovery, Entity},
+        telemetry::{parse_command, Telemetry, Topics, AVAILABILITY, COMMAND, OFFLINE, ONLINE},
+        Client, Connect, Error, Will,
+    },
+    run_logic::Triggers,
+    runtime::RuntimeMeter,
+};
+use std::{
+    io::{self, Read as _, Write as _},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// A blocking `std` TCP stream, which is fine for a single task run with `block_on`.
+struct Stream(TcpStream);
+
+#[derive(Debug)]
+struct IoError(io::Error);
+
+impl embedded_io_async::Error for IoError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl ErrorType for Stream {
+    type Error = IoError;
+}
+
+impl Read for Stream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        self.0.read(buf).map_err(IoError)
+    }
+}
+
+impl Write for Stream {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        self.0.write(buf).map_err(IoError)
+    }
+
+    async fn flush(&mut self) -> Result<(), IoError> {
+        self.0.flush().map_err(IoError)
+    }
+}
+
+fn main() {
+    let broker = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:1883".into());
+
+    let stream = TcpStream::connect(&broker).expect("failed to connect to the broker");
+    // Receiving gives up every second so that the run logic can be ticked
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    block_on(run(Stream(stream)));
+}
+
+async fn run(stream: Stream) {
+    let config = Config::DEFAULT;
+    let topics = Topics::new("host");
+
+    let availability = topics.get(AVAILABILITY);
+
+    let mut client = Client::new(stream);
+    client
+        .connect(&Connect {
+            client_id: topics.prefix(),
+            keep_alive_secs: config.mqtt_keep_alive_secs(),
+            will: Some(Will {
+                topic: &availability,
+                message: OFFLINE.as_bytes(),
+                retain: true,
+            }),
+            username: None,
+            password: None,
+        })
+        .await
+        .expect("broker refused the connection");
+    client.subscribe(&topics.get(COMMAND)).await.unwrap();
+    client
+        .publish(&availability, ONLINE.as_bytes(), true)
+        .await
+        .unwrap();
+    println!("Connected as {}", topics.prefix());
+
+    // Shows up in Home Assistant, if it uses the same broker
+    let discovery = Discovery::new(&topics, "host", env!("CARGO_PKG_VERSION"));
+    for entity in Entity::FIXED.iter() {
+        client
+            .publish(
+                &discovery.topic(entity),
+                discovery.config(entity).as_bytes(),
+                true,
+            )
+            .await
+            .unwrap();
+    }
+
+    let mut triggers = Triggers::default();
+    let meter = RuntimeMeter::new();
+    let mut last: Option<Telemetry> = None;
+    let mut last_tick = Instant::now();
+    let mut last_publish_all = Instant::now();
+
+    loop {
+        while last_tick.elapsed() >= Duration::from_secs(1) {
+            triggers.button.handle_tick(&config);
+            last_tick += Duration::from_secs(1);
+        }
+
+        // Everything is published again every interval, which also keeps the connection open
+        if last_publish_all.elapsed().as_secs() >= config.mqtt_interval.as_secs() {
+            last = None;
+            last_publish_all = Instant::now();
+        }
+
+        let state = triggers.resolve();
+        let fan = FanStatus::applied(&state.fan_command());
+        let telemetry = Telemetry::new(&state, &fan, &meter, &config);
+        for (name, value) in telemetry.changes(last.as_ref()) {
+            println!("{name}: {value}");
+            client
+                .publish(&topics.get(name), value.as_bytes(), true)
+                .await
+                .unwrap();
+        }
+        last = Some(telemetry);
+
+        let command = match client.receive().await {
+            Ok(message) => parse_command(message.payload),
+            Err(Error::Io(IoError(e)))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => panic!("connection lost: {e:?}"),
+        };
+
+        match command {
+            Ok(command) => {
+                println!("Command: {command:?}");
+                triggers.button.handle_command(command, &config);
+            }
+            Err(e) => println!("Invalid command: {e:?}"),
+        }
+    }
+}
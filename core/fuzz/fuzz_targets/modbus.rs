@@ -0,0 +1,68 @@
+//! Feeds arbitrary frames to the Modbus slave.
+//!
+//! ```sh
+//! cargo +nightly fuzz run modbus
+//! ```
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use embassy_time::Instant;
+use libfuzzer_sys::fuzz_target;
+use ms_air_filter_core::{
+    config::Config,
+    fan::FanStatus,
+    modbus::{
+        frame::{crc, MAX_FRAME_SIZE},
+        handle,
+        registers::Status,
+    },
+    run_logic::State,
+    runtime::RuntimeMeter,
+    temperature::TemperatureReadings,
+};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    unit: u8,
+    /// Almost every arbitrary frame fails the CRC check, so it is usually put right
+    fix_crc: bool,
+    frame: Vec<u8>,
+    temperatures: Vec<(u64, f32)>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut frame = input.frame;
+    if input.fix_crc && frame.len() >= 2 {
+        let len = frame.len() - 2;
+        let checksum = crc(&frame[..len]).to_le_bytes();
+        frame[len..].copy_from_slice(&checksum);
+    }
+
+    let mut temperatures = TemperatureReadings::default();
+    for (address, temperature) in input.temperatures {
+        temperatures.record(address, temperature, Instant::from_secs(0));
+    }
+
+    let config = Config::DEFAULT;
+    let status = Status {
+        state: &State::default(),
+        fan: &FanStatus::Idle,
+        meter: &RuntimeMeter::new(),
+        temperatures: &temperatures,
+        config: &config,
+    };
+
+    let mut reply = [0; MAX_FRAME_SIZE];
+    let (len, changes) = handle(input.unit, &frame, &status, &mut reply);
+
+    if let Some(len) = len {
+        // Every reply is a complete frame from this unit
+        let (data, checksum) = reply[..len].split_at(len - 2);
+        assert_eq!(data[0], input.unit);
+        assert_eq!(crc(data).to_le_bytes(), checksum);
+    }
+    if let Some(config) = changes.config {
+        assert!(config.is_valid());
+    }
+});
@@ -0,0 +1,475 @@
+use crate::{clock::Clock, config::Config};
+use embassy_time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub push_duration: ButtonPushDuration,
+    /// The number of pushes in quick succession this push is part of, including this one (e.g. 2
+    /// for the second push of a double click)
+    pub clicks: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Button {
+    Demand,
+    Speed,
+    /// Both buttons pushed together
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonPushDuration {
+    Short,
+    Long,
+    /// The button is still being held, this repeats until it is released (which is then reported
+    /// as a short or long push as usual)
+    Held,
+}
+
+/// Time after a button is released before another press is recognised.
+const RELEASE_LOCKOUT: Duration = Duration::from_millis(250);
+
+/// The state of a single button.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum ButtonState {
+    Pressed {
+        at: Instant,
+        /// Pushes that came just before this one
+        clicks: u8,
+        /// The number of times this push has repeated while held
+        repeats: u32,
+        /// Pushed together with the other button, so nothing is reported for this button alone
+        chorded: bool,
+    },
+    Released {
+        at: Instant,
+        /// Pushes in quick succession up to this release
+        clicks: u8,
+    },
+}
+
+impl ButtonState {
+    /// Processes a change in button input (or time passing), returning the type of push and the
+    /// number of clicks it is part of.
+    fn update(
+        &mut self,
+        pressed: bool,
+        now: Instant,
+        config: &Config,
+    ) -> Option<(ButtonPushDuration, u8)> {
+        match self {
+            ButtonState::Pressed {
+                at,
+                clicks,
+                repeats,
+                chorded,
+            } => {
+                let since = now - *at;
+                let clicks = clicks.saturating_add(1);
+                let chorded = *chorded;
+
+                if pressed {
+                    if since >= repeat_at(config, *repeats) {
+                        *repeats += 1;
+                        return (!chorded).then_some((ButtonPushDuration::Held, clicks));
+                    }
+                } else if since >= config.long_push_threshold {
+                    // A long push ends a run of clicks
+                    *self = Self::Released { at: now, clicks: 0 };
+                    return (!chorded).then_some((ButtonPushDuration::Long, clicks));
+                } else if since >= config.push_threshold {
+                    *self = Self::Released { at: now, clicks };
+                    return (!chorded).then_some((ButtonPushDuration::Short, clicks));
+                }
+            }
+            ButtonState::Released { at, clicks } => {
+                let since = now - *at;
+                if pressed && since >= RELEASE_LOCKOUT {
+                    *self = Self::Pressed {
+                        at: now,
+                        clicks: if since <= config.double_click_window {
+                            *clicks
+                        } else {
+                            0
+                        },
+                        repeats: 0,
+                        chorded: false,
+                    };
+                }
+            }
+        }
+
+        None
+    }
+
+    fn next_deadline(&self, config: &Config) -> Option<Instant> {
+        match self {
+            ButtonState::Pressed {
+                at,
+                repeats,
+                chorded: false,
+                ..
+            } => Some(*at + repeat_at(config, *repeats)),
+            _ => None,
+        }
+    }
+}
+
+/// How long after being pushed a button that is still held repeats for the `repeats + 1`th time.
+fn repeat_at(config: &Config, repeats: u32) -> Duration {
+    config.hold_repeat_delay + config.hold_repeat_interval * repeats
+}
+
+/// Both buttons being held together.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Chord {
+    at: Instant,
+    repeats: u32,
+}
+
+/// Turns the inputs from both buttons into [`ButtonEvent`]s.
+///
+/// Short and long pushes are reported when a button is released. A button that is held also
+/// repeats while it is held, [`Buttons::next_deadline`] gives the time that
+/// [`Buttons::update`] needs calling by for the repeats to be reported on time.
+///
+/// Pushes are not delayed to find out if they are part of a double click, the first push of a
+/// double click is reported as usual and the second reports two clicks.
+///
+/// Pushing both buttons at (almost) the same time is reported as [`Button::Both`] instead of
+/// anything for either button.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Buttons {
+    demand: ButtonState,
+    speed: ButtonState,
+    chord: Option<Chord>,
+}
+
+impl Buttons {
+    pub fn new(clock: &impl Clock) -> Self {
+        Self {
+            demand: ButtonState::Released {
+                at: clock.now(),
+                clicks: 0,
+            },
+            speed: ButtonState::Released {
+                at: clock.now(),
+                clicks: 0,
+            },
+            chord: None,
+        }
+    }
+
+    /// Processes a change in button input, or the passing of [`Buttons::next_deadline`],
+    /// returning what the buttons have done.
+    pub fn update(
+        &mut self,
+        demand_pressed: bool,
+        speed_pressed: bool,
+        clock: &impl Clock,
+        config: &Config,
+    ) -> heapless::Vec<ButtonEvent, 2> {
+        let now = clock.now();
+        let mut events = heapless::Vec::new();
+
+        for (button, state, pressed) in [
+            (Button::Demand, &mut self.demand, demand_pressed),
+            (Button::Speed, &mut self.speed, speed_pressed),
+        ] {
+            if let Some((push_duration, clicks)) = state.update(pressed, now, config) {
+                let _ = events.push(ButtonEvent {
+                    button,
+                    push_duration,
+                    clicks,
+                });
+            }
+        }
+
+        // Both buttons have just been pushed, and neither has been held for long enough to
+        // have already been reported
+        if let (
+            ButtonState::Pressed {
+                at: demand_at,
+                repeats: 0,
+                chorded: demand_chorded @ false,
+                ..
+            },
+            ButtonState::Pressed {
+                at: speed_at,
+                repeats: 0,
+                chorded: speed_chorded @ false,
+                ..
+            },
+        ) = (&mut self.demand, &mut self.speed)
+        {
+            let first = (*demand_at).min(*speed_at);
+            let last = (*demand_at).max(*speed_at);
+
+            if last - first <= config.chord_window {
+                *demand_chorded = true;
+                *speed_chorded = true;
+                self.chord = Some(Chord {
+                    at: first,
+                    repeats: 0,
+                });
+            }
+        }
+
+        if let Some(chord) = &mut self.chord {
+            let since = now - chord.at;
+
+            // The chord ends as soon as either button is released, the other button is ignored
+            // until it is released too
+            let push_duration = match (&self.demand, &self.speed) {
+                (ButtonState::Pressed { .. }, ButtonState::Pressed { .. }) => {
+                    if since >= repeat_at(config, chord.repeats) {
+                        chord.repeats += 1;
+                        Some(ButtonPushDuration::Held)
+                    } else {
+                        None
+                    }
+                }
+                _ => {
+                    self.chord = None;
+                    Some(if since >= config.long_push_threshold {
+                        ButtonPushDuration::Long
+                    } else {
+                        ButtonPushDuration::Short
+                    })
+                }
+            };
+
+            if let Some(push_duration) = push_duration {
+                let _ = events.push(ButtonEvent {
+                    button: Button::Both,
+                    push_duration,
+                    clicks: 1,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// The time by which [`Buttons::update`] should be called if the button inputs do not
+    /// change before then, or `None` if it only needs calling when they do.
+    pub fn next_deadline(&self, config: &Config) -> Option<Instant> {
+        match &self.chord {
+            Some(chord) => Some(chord.at + repeat_at(config, chord.repeats)),
+            None => match (
+                self.demand.next_deadline(config),
+                self.speed.next_deadline(config),
+            ) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::test::MockClock;
+
+    fn event(button: Button, push_duration: ButtonPushDuration, clicks: u8) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            push_duration,
+            clicks,
+        }
+    }
+
+    /// Changes the inputs after `millis`, returning the events that result.
+    fn update(
+        buttons: &mut Buttons,
+        clock: &MockClock,
+        millis: u64,
+        demand: bool,
+        speed: bool,
+    ) -> heapless::Vec<ButtonEvent, 2> {
+        clock.advance(Duration::from_millis(millis));
+        buttons.update(demand, speed, clock, &Config::DEFAULT)
+    }
+
+    #[test]
+    fn short_push() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        assert_eq!(update(&mut buttons, &clock, 1000, true, false), []);
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [event(Button::Demand, ButtonPushDuration::Short, 1)]
+        );
+    }
+
+    #[test]
+    fn long_push() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        assert_eq!(update(&mut buttons, &clock, 1000, false, true), []);
+        assert_eq!(
+            update(&mut buttons, &clock, 3000, false, false),
+            [event(Button::Speed, ButtonPushDuration::Long, 1)]
+        );
+    }
+
+    #[test]
+    fn bounce_is_ignored() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        assert_eq!(update(&mut buttons, &clock, 1000, true, false), []);
+
+        // Released too quickly to count as a push
+        assert_eq!(update(&mut buttons, &clock, 10, false, false), []);
+
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [event(Button::Demand, ButtonPushDuration::Short, 1)]
+        );
+
+        // Pressed again too soon after being released
+        assert_eq!(update(&mut buttons, &clock, 10, true, false), []);
+        assert_eq!(update(&mut buttons, &clock, 100, false, false), []);
+    }
+
+    #[test]
+    fn double_click() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        for clicks in 1..=3 {
+            update(&mut buttons, &clock, 400, true, false);
+            assert_eq!(
+                update(&mut buttons, &clock, 100, false, false),
+                [event(Button::Demand, ButtonPushDuration::Short, clicks)]
+            );
+        }
+
+        // Too long after the last click
+        update(&mut buttons, &clock, 600, true, false);
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [event(Button::Demand, ButtonPushDuration::Short, 1)]
+        );
+
+        // A long push ends a run of clicks
+        update(&mut buttons, &clock, 400, true, false);
+        update(&mut buttons, &clock, 3000, true, false);
+        assert_eq!(
+            update(&mut buttons, &clock, 0, false, false).last(),
+            Some(&event(Button::Demand, ButtonPushDuration::Long, 2))
+        );
+        update(&mut buttons, &clock, 400, true, false);
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [event(Button::Demand, ButtonPushDuration::Short, 1)]
+        );
+    }
+
+    #[test]
+    fn clicks_are_counted_for_each_button() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        update(&mut buttons, &clock, 1000, true, false);
+        update(&mut buttons, &clock, 100, false, false);
+
+        update(&mut buttons, &clock, 300, false, true);
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [event(Button::Speed, ButtonPushDuration::Short, 1)]
+        );
+    }
+
+    #[test]
+    fn held_button_repeats() {
+        let clock = MockClock::default();
+        let config = Config::DEFAULT;
+        let mut buttons = Buttons::new(&clock);
+
+        assert_eq!(buttons.next_deadline(&config), None);
+
+        update(&mut buttons, &clock, 1000, false, true);
+        assert_eq!(
+            buttons.next_deadline(&config),
+            Some(clock.now() + config.hold_repeat_delay)
+        );
+
+        assert_eq!(update(&mut buttons, &clock, 999, false, true), []);
+        for _ in 0..3 {
+            assert_eq!(
+                update(&mut buttons, &clock, 1, false, true),
+                [event(Button::Speed, ButtonPushDuration::Held, 1)]
+            );
+            assert_eq!(
+                buttons.next_deadline(&config),
+                Some(clock.now() + config.hold_repeat_interval)
+            );
+            clock.advance(config.hold_repeat_interval - Duration::from_millis(1));
+        }
+
+        // Released before a long push
+        assert_eq!(
+            update(&mut buttons, &clock, 0, false, false),
+            [event(Button::Speed, ButtonPushDuration::Short, 1)]
+        );
+        assert_eq!(buttons.next_deadline(&config), None);
+    }
+
+    #[test]
+    fn both_buttons_together() {
+        let clock = MockClock::default();
+        let config = Config::DEFAULT;
+        let mut buttons = Buttons::new(&clock);
+
+        assert_eq!(update(&mut buttons, &clock, 1000, true, false), []);
+        assert_eq!(update(&mut buttons, &clock, 100, true, true), []);
+
+        // Releasing either button ends the chord, the other one does nothing
+        assert_eq!(
+            update(&mut buttons, &clock, 200, true, false),
+            [event(Button::Both, ButtonPushDuration::Short, 1)]
+        );
+        assert_eq!(buttons.next_deadline(&config), None);
+        assert_eq!(update(&mut buttons, &clock, 100, false, false), []);
+
+        // And held for long enough to repeat
+        update(&mut buttons, &clock, 1000, true, true);
+        assert_eq!(
+            update(&mut buttons, &clock, 1000, true, true),
+            [event(Button::Both, ButtonPushDuration::Held, 1)]
+        );
+        assert_eq!(
+            update(&mut buttons, &clock, 2000, false, false),
+            [event(Button::Both, ButtonPushDuration::Long, 1)]
+        );
+    }
+
+    #[test]
+    fn buttons_pushed_apart_are_not_together() {
+        let clock = MockClock::default();
+        let mut buttons = Buttons::new(&clock);
+
+        update(&mut buttons, &clock, 1000, true, false);
+        update(&mut buttons, &clock, 200, true, true);
+
+        assert_eq!(
+            update(&mut buttons, &clock, 100, false, false),
+            [
+                event(Button::Demand, ButtonPushDuration::Short, 1),
+                event(Button::Speed, ButtonPushDuration::Short, 1),
+            ]
+        );
+    }
+}
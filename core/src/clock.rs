@@ -0,0 +1,41 @@
+use embassy_time::Instant;
+
+/// A source of the current time.
+///
+/// Anything that needs to know the time takes one of these rather than calling
+/// [`Instant::now`] directly so that it can be tested without a time driver.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use core::cell::Cell;
+    use embassy_time::Duration;
+
+    /// A clock that only moves when told to.
+    pub(crate) struct MockClock {
+        now: Cell<Instant>,
+    }
+
+    impl Default for MockClock {
+        fn default() -> Self {
+            Self {
+                now: Cell::new(Instant::from_ticks(0)),
+            }
+        }
+    }
+
+    impl MockClock {
+        pub(crate) fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use crate::{
+    encoding::{
+        decode_interlock_action, decode_speed, encode_interlock_action, encode_speed, Reader,
+        Writer,
+    },
+    fan::FanSpeed,
+    run_logic::InterlockAction,
+};
+use core::{fmt, net::Ipv4Addr};
+use embassy_time::Duration;
+
+/// Incremented whenever the layout produced by [`Config::encode`] changes.
+pub const CONFIG_VERSION: u16 = 2;
+
+const SECS_PER_HOUR: u64 = 60 * 60;
+
+/// Longer than any setting needs to be (the most hours that [`Config::is_valid`] allows), and
+/// short enough that adding it to the time can never overflow.
+const MAX_DURATION: Duration = Duration::from_secs(u16::MAX as u64 * SECS_PER_HOUR);
+
+/// What the DS18B20 can measure, temperatures outside of this (or NaN) would never be reached.
+const SENSOR_RANGE_C: core::ops::RangeInclusive<f32> = -55.0..=125.0;
+
+/// The size of buffer needed by [`Config::encode`].
+pub const ENCODED_SIZE: usize = 128;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    UnknownKey,
+    InvalidValue,
+    /// The configuration as a whole does not make sense (e.g. thresholds the wrong way around)
+    Invalid,
+}
+
+impl ConfigError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::UnknownKey => "unknown key",
+            Self::InvalidValue => "invalid value",
+            Self::Invalid => "invalid configuration",
+        }
+    }
+}
+
+/// The names of all configuration values.
+/// The unit of each value is given by its name.
+pub const KEYS: &[&str] = &[
+    "run_minutes",
+    "run_increment_minutes",
+    "max_run_minutes",
+    "purge_minutes",
+    "continuous_max_hours",
+    "start_speed",
+    "push_threshold_ms",
+    "long_push_threshold_ms",
+    "double_click_ms",
+    "chord_ms",
+    "hold_repeat_delay_ms",
+    "hold_repeat_interval_ms",
+    "temperature_poll_secs",
+    "temperature_on_c",
+    "temperature_off_c",
+    "temperature_speed",
+    "interlock_sensor",
+    "interlock_limit_c",
+    "interlock_action",
+    "backlight_idle_percent",
+    "backlight_running_percent",
+    "contactor_switch_delay_ms",
+    "contactor_pull_in_ms",
+    "contactor_dead_time_ms",
+    "contactor_feedback_ms",
+    "minimum_speed_hold_ms",
+    "filter_service_hours",
+    "mqtt_broker",
+    "mqtt_port",
+    "mqtt_interval_secs",
+    "modbus_address",
+    "modbus_baud",
+];
+
+/// A single configuration value, as it is presented to users.
+pub enum ConfigValue {
+    Number(u64),
+    Temperature(f32),
+    Speed(FanSpeed),
+    /// A temperature sensor, shown as its address in hexadecimal
+    Address(u64),
+    Interlock(InterlockAction),
+    /// An IPv4 address, shown in dotted decimal
+    Ip([u8; 4]),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(v) => write!(f, "{v}"),
+            Self::Temperature(v) => write!(f, "{v:.1}"),
+            Self::Speed(v) => f.write_str(v.name()),
+            Self::Address(v) => write!(f, "{v:016x}"),
+            Self::Interlock(v) => f.write_str(v.name()),
+            Self::Ip(v) => write!(f, "{}", Ipv4Addr::from(*v)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// How long the fan runs for after being started with the buttons
+    pub run_duration: Duration,
+    /// How much each push of the demand button adds to the run time while the fan is running,
+    /// zero to start the run time again from [`Config::run_duration`] instead
+    pub run_increment: Duration,
+    /// The longest the run time can be increased to with the buttons
+    pub max_run_duration: Duration,
+    /// How long the fan keeps running at low speed to clear the air after being stopped, zero to
+    /// stop straight away
+    pub purge_duration: Duration,
+    /// The longest the fan runs for in continuous mode before stopping anyway, zero to run until
+    /// stopped
+    pub continuous_max_duration: Duration,
+    /// The speed the fan starts at when started with the buttons
+    pub start_speed: FanSpeed,
+
+    /// Minimum time a button must be held for to count as a push
+    pub push_threshold: Duration,
+    /// Minimum time a button must be held for to count as a long push
+    pub long_push_threshold: Duration,
+    /// Maximum time between releasing a button and pushing it again for the pushes to count as a
+    /// double click
+    pub double_click_window: Duration,
+    /// Maximum time between pushing each button for them to count as being pushed together
+    pub chord_window: Duration,
+    /// Time a button must be held for before it starts to repeat
+    pub hold_repeat_delay: Duration,
+    /// Time between repeats while a button is held
+    pub hold_repeat_interval: Duration,
+
+    pub temperature_poll_interval: Duration,
+    /// Start the fan when any sensor reaches this temperature (°C)
+    pub temperature_on_threshold: f32,
+    /// Stop the fan once all sensors have fallen below this temperature (°C)
+    pub temperature_off_threshold: f32,
+    /// The speed to run the fan at while the temperature is high
+    pub temperature_run_speed: FanSpeed,
+
+    /// The sensor watched by the overtemperature interlock, zero to disable the interlock
+    pub interlock_sensor: u64,
+    /// The interlock trips when the sensor reaches this temperature (°C)
+    pub interlock_limit: f32,
+    /// What the interlock does to the fan when it trips
+    pub interlock_action: InterlockAction,
+
+    pub backlight_idle_percent: u8,
+    pub backlight_running_percent: u8,
+
+    /// Time between opening the speed selection contactors and changing the contactor voltage
+    pub contactor_switch_delay: Duration,
+    /// Time the contactor voltage is kept at 24V for a contactor to pull in
+    pub contactor_pull_in_time: Duration,
+    /// Minimum time between opening one speed selection contactor and closing another, for the
+    /// motor to coast down
+    pub contactor_dead_time: Duration,
+    /// Time allowed for the auxiliary contacts to confirm that the contactors have switched, zero
+    /// if they are not wired up
+    pub contactor_feedback_timeout: Duration,
+    /// Minimum time a fan speed is kept for before it can be changed again
+    pub minimum_speed_hold_time: Duration,
+
+    /// How long the filters last for when the fan is run at high speed
+    pub filter_service_interval: Duration,
+
+    /// The IPv4 address of the MQTT broker, all zeros to not use MQTT
+    pub mqtt_broker: [u8; 4],
+    pub mqtt_port: u16,
+    /// How often everything is published, even if it has not changed
+    pub mqtt_interval: Duration,
+
+    /// The Modbus unit address the controller answers to, zero to not use Modbus
+    pub modbus_address: u8,
+    pub modbus_baud: u32,
+}
+
+impl Config {
+    pub const DEFAULT: Self = Self {
+        run_duration: Duration::from_secs(60 * 20),
+        run_increment: Duration::from_secs(0),
+        max_run_duration: Duration::from_secs(60 * 240),
+        purge_duration: Duration::from_secs(0),
+        continuous_max_duration: Duration::from_secs(12 * SECS_PER_HOUR),
+        start_speed: FanSpeed::Low,
+        push_threshold: Duration::from_millis(75),
+        long_push_threshold: Duration::from_secs(3),
+        double_click_window: Duration::from_millis(500),
+        chord_window: Duration::from_millis(150),
+        hold_repeat_delay: Duration::from_secs(1),
+        hold_repeat_interval: Duration::from_millis(250),
+        temperature_poll_interval: Duration::from_secs(10),
+        temperature_on_threshold: 30.0,
+        temperature_off_threshold: 27.0,
+        temperature_run_speed: FanSpeed::Medium,
+        interlock_sensor: 0,
+        interlock_limit: 70.0,
+        interlock_action: InterlockAction::Stop,
+        backlight_idle_percent: 20,
+        backlight_running_percent: 100,
+        contactor_switch_delay: Duration::from_millis(10),
+        contactor_pull_in_time: Duration::from_millis(500),
+        contactor_dead_time: Duration::from_secs(2),
+        contactor_feedback_timeout: Duration::from_secs(0),
+        minimum_speed_hold_time: Duration::from_secs(1),
+        filter_service_interval: Duration::from_secs(500 * SECS_PER_HOUR),
+        mqtt_broker: [0; 4],
+        mqtt_port: 1883,
+        mqtt_interval: Duration::from_secs(60),
+        modbus_address: 0,
+        modbus_baud: 19200,
+    };
+
+    /// Checks that the configuration makes sense, values that are out of range could lead to
+    /// the fan not being controllable.
+    pub fn is_valid(&self) -> bool {
+        self.push_threshold < self.long_push_threshold
+            && self.chord_window < self.hold_repeat_delay
+            && self.hold_repeat_interval >= Duration::from_millis(50)
+            && self.run_duration >= Duration::from_secs(60)
+            && self.max_run_duration >= self.run_duration
+            && self.temperature_poll_interval >= Duration::from_secs(1)
+            && self.temperature_off_threshold < self.temperature_on_threshold
+            && SENSOR_RANGE_C.contains(&self.temperature_on_threshold)
+            && SENSOR_RANGE_C.contains(&self.temperature_off_threshold)
+            && SENSOR_RANGE_C.contains(&self.interlock_limit)
+            && self.backlight_idle_percent <= 100
+            && self.backlight_running_percent <= 100
+            && self.contactor_switch_delay <= Duration::from_secs(1)
+            && self.contactor_pull_in_time <= Duration::from_secs(5)
+            && self.contactor_dead_time <= Duration::from_secs(30)
+            && self.contactor_feedback_timeout <= Duration::from_secs(5)
+            && self.minimum_speed_hold_time <= Duration::from_secs(60)
+            && self.filter_service_interval >= Duration::from_secs(SECS_PER_HOUR)
+            && self.filter_service_interval.as_secs() / SECS_PER_HOUR <= u16::MAX as u64
+            && self.continuous_max_duration.as_secs() / SECS_PER_HOUR <= u16::MAX as u64
+            && self.mqtt_interval >= Duration::from_secs(5)
+            && self.mqtt_interval <= Duration::from_secs(SECS_PER_HOUR)
+            && self.modbus_address <= 247
+            && (1200..=115_200).contains(&self.modbus_baud)
+    }
+
+    /// The MQTT keep alive interval, long enough that pinging the broker every
+    /// [`Config::mqtt_interval`] keeps the connection open.
+    pub fn mqtt_keep_alive_secs(&self) -> u16 {
+        (self.mqtt_interval.as_secs() * 3 / 2) as u16
+    }
+
+    pub fn get_value(&self, key: &str) -> Result<ConfigValue, ConfigError> {
+        Ok(match key {
+            "run_minutes" => ConfigValue::Number(self.run_duration.as_secs() / 60),
+            "run_increment_minutes" => ConfigValue::Number(self.run_increment.as_secs() / 60),
+            "max_run_minutes" => ConfigValue::Number(self.max_run_duration.as_secs() / 60),
+            "purge_minutes" => ConfigValue::Number(self.purge_duration.as_secs() / 60),
+            "continuous_max_hours" => {
+                ConfigValue::Number(self.continuous_max_duration.as_secs() / SECS_PER_HOUR)
+            }
+            "start_speed" => ConfigValue::Speed(self.start_speed.clone()),
+            "push_threshold_ms" => ConfigValue::Number(self.push_threshold.as_millis()),
+            "long_push_threshold_ms" => ConfigValue::Number(self.long_push_threshold.as_millis()),
+            "double_click_ms" => ConfigValue::Number(self.double_click_window.as_millis()),
+            "chord_ms" => ConfigValue::Number(self.chord_window.as_millis()),
+            "hold_repeat_delay_ms" => ConfigValue::Number(self.hold_repeat_delay.as_millis()),
+            "hold_repeat_interval_ms" => ConfigValue::Number(self.hold_repeat_interval.as_millis()),
+            "temperature_poll_secs" => {
+                ConfigValue::Number(self.temperature_poll_interval.as_secs())
+            }
+            "temperature_on_c" => ConfigValue::Temperature(self.temperature_on_threshold),
+            "temperature_off_c" => ConfigValue::Temperature(self.temperature_off_threshold),
+            "temperature_speed" => ConfigValue::Speed(self.temperature_run_speed.clone()),
+            "interlock_sensor" => ConfigValue::Address(self.interlock_sensor),
+            "interlock_limit_c" => ConfigValue::Temperature(self.interlock_limit),
+            "interlock_action" => ConfigValue::Interlock(self.interlock_action),
+            "backlight_idle_percent" => ConfigValue::Number(self.backlight_idle_percent.into()),
+            "backlight_running_percent" => {
+                ConfigValue::Number(self.backlight_running_percent.into())
+            }
+            "contactor_switch_delay_ms" => {
+                ConfigValue::Number(self.contactor_switch_delay.as_millis())
+            }
+            "contactor_pull_in_ms" => ConfigValue::Number(self.contactor_pull_in_time.as_millis()),
+            "contactor_dead_time_ms" => ConfigValue::Number(self.contactor_dead_time.as_millis()),
+            "contactor_feedback_ms" => {
+                ConfigValue::Number(self.contactor_feedback_timeout.as_millis())
+            }
+            "minimum_speed_hold_ms" => {
+                ConfigValue::Number(self.minimum_speed_hold_time.as_millis())
+            }
+            "filter_service_hours" => {
+                ConfigValue::Number(self.filter_service_interval.as_secs() / SECS_PER_HOUR)
+            }
+            "mqtt_broker" => ConfigValue::Ip(self.mqtt_broker),
+            "mqtt_port" => ConfigValue::Number(self.mqtt_port.into()),
+            "mqtt_interval_secs" => ConfigValue::Number(self.mqtt_interval.as_secs()),
+            "modbus_address" => ConfigValue::Number(self.modbus_address.into()),
+            "modbus_baud" => ConfigValue::Number(self.modbus_baud.into()),
+            _ => return Err(ConfigError::UnknownKey),
+        })
+    }
+
+    /// Sets a single value from its textual representation.
+    /// The configuration as a whole is not checked, see [`Config::is_valid`].
+    pub fn set_value(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        fn parse<T: core::str::FromStr>(value: &str) -> Result<T, ConfigError> {
+            value.parse().map_err(|_| ConfigError::InvalidValue)
+        }
+
+        // The type of the existing value says how to parse the new one
+        let value = match self.get_value(key)? {
+            ConfigValue::Number(_) => ConfigValue::Number(parse(value)?),
+            ConfigValue::Temperature(_) => ConfigValue::Temperature(parse(value)?),
+            ConfigValue::Speed(_) => ConfigValue::Speed(parse(value)?),
+            ConfigValue::Address(_) => ConfigValue::Address(
+                u64::from_str_radix(value, 16).map_err(|_| ConfigError::InvalidValue)?,
+            ),
+            ConfigValue::Interlock(_) => ConfigValue::Interlock(parse(value)?),
+            ConfigValue::Ip(_) => ConfigValue::Ip(parse::<Ipv4Addr>(value)?.octets()),
+        };
+        self.set(key, value)
+    }
+
+    /// Sets a single value.
+    /// The configuration as a whole is not checked, see [`Config::is_valid`].
+    pub fn set(&mut self, key: &str, value: ConfigValue) -> Result<(), ConfigError> {
+        use ConfigValue::{Address, Interlock, Ip, Number, Speed, Temperature};
+
+        fn percent(value: u64) -> Result<u8, ConfigError> {
+            value.try_into().map_err(|_| ConfigError::InvalidValue)
+        }
+
+        fn duration(value: u64, unit: Duration) -> Result<Duration, ConfigError> {
+            value
+                .checked_mul(unit.as_ticks())
+                .map(Duration::from_ticks)
+                .filter(|d| *d <= MAX_DURATION)
+                .ok_or(ConfigError::InvalidValue)
+        }
+        let minutes = |v| duration(v, Duration::from_secs(60));
+        let hours = |v| duration(v, Duration::from_secs(SECS_PER_HOUR));
+        let secs = |v| duration(v, Duration::from_secs(1));
+        let millis = |v| duration(v, Duration::from_millis(1));
+
+        match (key, value) {
+            ("run_minutes", Number(v)) => self.run_duration = minutes(v)?,
+            ("run_increment_minutes", Number(v)) => self.run_increment = minutes(v)?,
+            ("max_run_minutes", Number(v)) => self.max_run_duration = minutes(v)?,
+            ("purge_minutes", Number(v)) => self.purge_duration = minutes(v)?,
+            ("continuous_max_hours", Number(v)) => self.continuous_max_duration = hours(v)?,
+            ("start_speed", Speed(v)) => self.start_speed = v,
+            ("push_threshold_ms", Number(v)) => self.push_threshold = millis(v)?,
+            ("long_push_threshold_ms", Number(v)) => self.long_push_threshold = millis(v)?,
+            ("double_click_ms", Number(v)) => self.double_click_window = millis(v)?,
+            ("chord_ms", Number(v)) => self.chord_window = millis(v)?,
+            ("hold_repeat_delay_ms", Number(v)) => self.hold_repeat_delay = millis(v)?,
+            ("hold_repeat_interval_ms", Number(v)) => self.hold_repeat_interval = millis(v)?,
+            ("temperature_poll_secs", Number(v)) => self.temperature_poll_interval = secs(v)?,
+            ("temperature_on_c", Temperature(v)) => self.temperature_on_threshold = v,
+            ("temperature_off_c", Temperature(v)) => self.temperature_off_threshold = v,
+            ("temperature_speed", Speed(v)) => self.temperature_run_speed = v,
+            ("interlock_sensor", Address(v)) => self.interlock_sensor = v,
+            ("interlock_limit_c", Temperature(v)) => self.interlock_limit = v,
+            ("interlock_action", Interlock(v)) => self.interlock_action = v,
+            ("backlight_idle_percent", Number(v)) => self.backlight_idle_percent = percent(v)?,
+            ("backlight_running_percent", Number(v)) => {
+                self.backlight_running_percent = percent(v)?
+            }
+            ("contactor_switch_delay_ms", Number(v)) => self.contactor_switch_delay = millis(v)?,
+            ("contactor_pull_in_ms", Number(v)) => self.contactor_pull_in_time = millis(v)?,
+            ("contactor_dead_time_ms", Number(v)) => self.contactor_dead_time = millis(v)?,
+            ("contactor_feedback_ms", Number(v)) => self.contactor_feedback_timeout = millis(v)?,
+            ("minimum_speed_hold_ms", Number(v)) => self.minimum_speed_hold_time = millis(v)?,
+            ("filter_service_hours", Number(v)) => self.filter_service_interval = hours(v)?,
+            ("mqtt_broker", Ip(v)) => self.mqtt_broker = v,
+            ("mqtt_port", Number(v)) => {
+                self.mqtt_port = v.try_into().map_err(|_| ConfigError::InvalidValue)?
+            }
+            ("mqtt_interval_secs", Number(v)) => self.mqtt_interval = secs(v)?,
+            ("modbus_address", Number(v)) => {
+                self.modbus_address = v.try_into().map_err(|_| ConfigError::InvalidValue)?
+            }
+            ("modbus_baud", Number(v)) => {
+                self.modbus_baud = v.try_into().map_err(|_| ConfigError::InvalidValue)?
+            }
+            (key, _) if KEYS.contains(&key) => return Err(ConfigError::InvalidValue),
+            _ => return Err(ConfigError::UnknownKey),
+        }
+
+        Ok(())
+    }
+
+    /// Serialises the configuration into `buf`, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8; ENCODED_SIZE]) -> usize {
+        let mut w = Writer::new(buf);
+        w.duration(self.run_duration);
+        w.duration(self.push_threshold);
+        w.duration(self.long_push_threshold);
+        w.duration(self.temperature_poll_interval);
+        w.f32(self.temperature_on_threshold);
+        w.f32(self.temperature_off_threshold);
+        w.u8(encode_speed(&self.temperature_run_speed));
+        w.u8(self.backlight_idle_percent);
+        w.u8(self.backlight_running_percent);
+        w.duration(self.contactor_switch_delay);
+        w.duration(self.contactor_pull_in_time);
+        w.duration(self.minimum_speed_hold_time);
+        // Added in version 2
+        w.u16((self.filter_service_interval.as_secs() / SECS_PER_HOUR) as u16);
+        w.u8(encode_speed(&self.start_speed));
+        w.duration(self.double_click_window);
+        w.duration(self.chord_window);
+        w.duration(self.hold_repeat_delay);
+        w.duration(self.hold_repeat_interval);
+        w.duration(self.run_increment);
+        w.duration(self.max_run_duration);
+        w.duration(self.purge_duration);
+        w.u16((self.continuous_max_duration.as_secs() / SECS_PER_HOUR) as u16);
+        w.u64(self.interlock_sensor);
+        w.f32(self.interlock_limit);
+        w.u8(encode_interlock_action(self.interlock_action));
+        w.duration(self.contactor_dead_time);
+        w.duration(self.contactor_feedback_timeout);
+        w.u32(u32::from_be_bytes(self.mqtt_broker));
+        w.u16(self.mqtt_port);
+        w.duration(self.mqtt_interval);
+        w.u8(self.modbus_address);
+        w.u32(self.modbus_baud);
+        w.position()
+    }
+
+    /// Deserialises a configuration previously produced by [`Config::encode`].
+    ///
+    /// Configurations stored by older versions are also accepted, with anything added since then
+    /// set to its default.
+    pub fn decode(version: u16, buf: &[u8]) -> Option<Self> {
+        if version > CONFIG_VERSION {
+            return None;
+        }
+
+        let mut r = Reader::new(buf);
+        let mut config = Self {
+            run_duration: r.duration()?,
+            push_threshold: r.duration()?,
+            long_push_threshold: r.duration()?,
+            temperature_poll_interval: r.duration()?,
+            temperature_on_threshold: r.f32()?,
+            temperature_off_threshold: r.f32()?,
+            temperature_run_speed: decode_speed(r.u8()?)?,
+            backlight_idle_percent: r.u8()?,
+            backlight_running_percent: r.u8()?,
+            contactor_switch_delay: r.duration()?,
+            contactor_pull_in_time: r.duration()?,
+            minimum_speed_hold_time: r.duration()?,
+            ..Self::DEFAULT
+        };
+
+        if version >= 2 {
+            config.filter_service_interval = Duration::from_secs(r.u16()? as u64 * SECS_PER_HOUR);
+            config.start_speed = decode_speed(r.u8()?)?;
+            config.double_click_window = r.duration()?;
+            config.chord_window = r.duration()?;
+            config.hold_repeat_delay = r.duration()?;
+            config.hold_repeat_interval = r.duration()?;
+            config.run_increment = r.duration()?;
+            config.max_run_duration = r.duration()?;
+            config.purge_duration = r.duration()?;
+            config.continuous_max_duration = Duration::from_secs(r.u16()? as u64 * SECS_PER_HOUR);
+            config.interlock_sensor = r.u64()?;
+            config.interlock_limit = r.f32()?;
+            config.interlock_action = decode_interlock_action(r.u8()?)?;
+            config.contactor_dead_time = r.duration()?;
+            config.contactor_feedback_timeout = r.duration()?;
+            config.mqtt_broker = r.u32()?.to_be_bytes();
+            config.mqtt_port = r.u16()?;
+            config.mqtt_interval = r.duration()?;
+            config.modbus_address = r.u8()?;
+            config.modbus_baud = r.u32()?;
+        }
+
+        r.is_empty().then_some(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert!(Config::DEFAULT.is_valid());
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut config = Config::DEFAULT;
+        config.run_duration = Duration::from_secs(60 * 45);
+        config.temperature_on_threshold = 35.5;
+        config.temperature_run_speed = FanSpeed::High;
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = config.encode(&mut buf);
+
+        assert_eq!(Config::decode(CONFIG_VERSION, &buf[..len]), Some(config));
+    }
+
+    /// Encodes `config` as version 1 did, which had none of the fields added since.
+    fn encode_version_1(config: &Config) -> std::vec::Vec<u8> {
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let mut w = Writer::new(&mut buf);
+        w.duration(config.run_duration);
+        w.duration(config.push_threshold);
+        w.duration(config.long_push_threshold);
+        w.duration(config.temperature_poll_interval);
+        w.f32(config.temperature_on_threshold);
+        w.f32(config.temperature_off_threshold);
+        w.u8(encode_speed(&config.temperature_run_speed));
+        w.u8(config.backlight_idle_percent);
+        w.u8(config.backlight_running_percent);
+        w.duration(config.contactor_switch_delay);
+        w.duration(config.contactor_pull_in_time);
+        w.duration(config.minimum_speed_hold_time);
+        let len = w.position();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn decode_version_1() {
+        let mut config = Config::DEFAULT;
+        config.run_duration = Duration::from_secs(60 * 45);
+        config.temperature_run_speed = FanSpeed::High;
+
+        // Anything added since version 1 is left at its default
+        let mut newer = config.clone();
+        newer.start_speed = FanSpeed::High;
+        newer.mqtt_broker = [192, 168, 1, 10];
+        let buf = encode_version_1(&newer);
+
+        assert_eq!(Config::decode(1, &buf), Some(config));
+        assert_eq!(Config::decode(CONFIG_VERSION, &buf), None);
+    }
+
+    #[test]
+    fn decode_rejects_wrong_length_or_version() {
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = Config::DEFAULT.encode(&mut buf);
+
+        assert_eq!(Config::decode(CONFIG_VERSION, &buf[..len - 1]), None);
+        assert_eq!(Config::decode(CONFIG_VERSION, &buf[..len + 1]), None);
+        assert_eq!(Config::decode(1, &buf[..len]), None);
+        assert_eq!(Config::decode(CONFIG_VERSION + 1, &buf[..len]), None);
+    }
+
+    #[test]
+    fn every_key_can_be_read_and_written() {
+        let config = Config::DEFAULT;
+
+        for key in KEYS {
+            let value = config.get_value(key).unwrap();
+
+            let mut value_str = heapless::String::<16>::new();
+            fmt::write(&mut value_str, format_args!("{value}")).unwrap();
+
+            let mut new_config = Config::DEFAULT;
+            new_config.set_value(key, &value_str).unwrap();
+            assert_eq!(new_config, config, "{key}");
+        }
+    }
+
+    #[test]
+    fn set_value() {
+        let mut config = Config::DEFAULT;
+
+        config.set_value("run_minutes", "30").unwrap();
+        assert_eq!(config.run_duration, Duration::from_secs(30 * 60));
+
+        config.set_value("temperature_speed", "high").unwrap();
+        assert_eq!(config.temperature_run_speed, FanSpeed::High);
+
+        config
+            .set_value("interlock_sensor", "28ff000000000001")
+            .unwrap();
+        assert_eq!(config.interlock_sensor, 0x28ff_0000_0000_0001);
+        config.set_value("interlock_action", "boost").unwrap();
+        assert_eq!(config.interlock_action, InterlockAction::Boost);
+
+        config.set_value("mqtt_broker", "192.168.1.10").unwrap();
+        assert_eq!(config.mqtt_broker, [192, 168, 1, 10]);
+        assert_eq!(
+            config.set_value("mqtt_broker", "192.168.1"),
+            Err(ConfigError::InvalidValue)
+        );
+        assert_eq!(
+            config.set_value("mqtt_port", "70000"),
+            Err(ConfigError::InvalidValue)
+        );
+
+        config.set_value("modbus_address", "247").unwrap();
+        assert!(config.is_valid());
+        config.set_value("modbus_address", "248").unwrap();
+        assert!(!config.is_valid());
+        assert_eq!(
+            config.set_value("modbus_address", "256"),
+            Err(ConfigError::InvalidValue)
+        );
+        config.set_value("modbus_address", "1").unwrap();
+
+        config.set_value("backlight_idle_percent", "50").unwrap();
+        assert_eq!(config.backlight_idle_percent, 50);
+        assert_eq!(
+            config.set_value("backlight_idle_percent", "300"),
+            Err(ConfigError::InvalidValue)
+        );
+
+        assert_eq!(config.set_value("nope", "1"), Err(ConfigError::UnknownKey));
+        assert_eq!(
+            config.set_value("run_minutes", "lots"),
+            Err(ConfigError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn set_checks_value_type() {
+        let mut config = Config::DEFAULT;
+
+        config
+            .set("start_speed", ConfigValue::Speed(FanSpeed::Medium))
+            .unwrap();
+        assert_eq!(config.start_speed, FanSpeed::Medium);
+
+        assert_eq!(
+            config.set("start_speed", ConfigValue::Number(2)),
+            Err(ConfigError::InvalidValue)
+        );
+        assert_eq!(
+            config.set("nope", ConfigValue::Number(2)),
+            Err(ConfigError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn set_rejects_durations_too_long_to_represent() {
+        let mut config = Config::DEFAULT;
+
+        for (key, value) in [
+            ("run_minutes", 99_999_999_999_999),
+            ("filter_service_hours", u64::MAX / SECS_PER_HOUR),
+            ("push_threshold_ms", u64::MAX),
+            ("temperature_poll_secs", 99_999_999_999_999_999),
+        ] {
+            assert_eq!(
+                config.set(key, ConfigValue::Number(value)),
+                Err(ConfigError::InvalidValue),
+                "{key}"
+            );
+        }
+        assert_eq!(config, Config::DEFAULT);
+
+        config
+            .set("continuous_max_hours", ConfigValue::Number(u16::MAX.into()))
+            .unwrap();
+        assert_eq!(
+            config.set(
+                "continuous_max_hours",
+                ConfigValue::Number(u16::MAX as u64 + 1)
+            ),
+            Err(ConfigError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn maximum_run_time_shorter_than_run_time_is_invalid() {
+        let mut config = Config::DEFAULT;
+        config.max_run_duration = config.run_duration - Duration::from_secs(60);
+        assert!(!config.is_valid());
+    }
+
+    #[test]
+    fn thresholds_the_wrong_way_around_are_invalid() {
+        let mut config = Config::DEFAULT;
+        config.temperature_off_threshold = config.temperature_on_threshold + 1.0;
+        assert!(!config.is_valid());
+    }
+
+    #[test]
+    fn contactor_timings_out_of_range_are_invalid() {
+        for (key, value) in [
+            ("contactor_switch_delay_ms", "1001"),
+            ("contactor_pull_in_ms", "5001"),
+            ("contactor_dead_time_ms", "30001"),
+            ("contactor_feedback_ms", "5001"),
+            ("minimum_speed_hold_ms", "60001"),
+        ] {
+            let mut config = Config::DEFAULT;
+            config.set_value(key, value).unwrap();
+            assert!(!config.is_valid(), "{key} = {value}");
+        }
+    }
+
+    #[test]
+    fn temperatures_out_of_range_are_invalid() {
+        for (key, value) in [
+            ("interlock_limit_c", "nan"),
+            ("interlock_limit_c", "inf"),
+            ("interlock_limit_c", "126"),
+            ("temperature_on_c", "nan"),
+            ("temperature_on_c", "inf"),
+            ("temperature_off_c", "-inf"),
+            ("temperature_off_c", "-56"),
+        ] {
+            let mut config = Config::DEFAULT;
+            config.set_value(key, value).unwrap();
+            assert!(!config.is_valid(), "{key} = {value}");
+        }
+
+        let mut config = Config::DEFAULT;
+        config.set_value("interlock_limit_c", "125").unwrap();
+        assert!(config.is_valid());
+    }
+}
@@ -0,0 +1,384 @@
+//! Parsing of serial console command lines.
+
+use crate::{fan::FanSpeed, run_logic::ControlCommand, schedule::ScheduleEntry, time::DateTime};
+use embassy_time::Duration;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command<'a> {
+    Help,
+    Status,
+    Start {
+        minutes: Option<u32>,
+        speed: Option<FanSpeed>,
+    },
+    Stop,
+    Speed(FanSpeed),
+    Temperatures,
+    ConfigGet(Option<&'a str>),
+    ConfigSet {
+        key: &'a str,
+        value: &'a str,
+    },
+    Filter,
+    FilterReset,
+    Time,
+    TimeSet(DateTime),
+    Schedule,
+    ScheduleAdd(ScheduleEntry),
+    /// Removes an entry, numbered from 1 as they are listed
+    ScheduleRemove(usize),
+    ScheduleClear,
+    Version,
+}
+
+impl Command<'_> {
+    /// The request to control the fan made by this command, if it is one.
+    pub fn control_command(&self) -> Option<ControlCommand> {
+        match self {
+            Self::Start { minutes, speed } => Some(ControlCommand::Start {
+                duration: minutes.map(|m| Duration::from_secs(m as u64 * 60)),
+                speed: speed.clone(),
+            }),
+            Self::Stop => Some(ControlCommand::Stop),
+            Self::Speed(speed) => Some(ControlCommand::SetSpeed(speed.clone())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    TooManyArguments,
+}
+
+impl ParseError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty command",
+            Self::UnknownCommand => "unknown command (try \"help\")",
+            Self::MissingArgument => "missing argument",
+            Self::InvalidArgument => "invalid argument",
+            Self::TooManyArguments => "too many arguments",
+        }
+    }
+}
+
+pub const HELP: &str = "\
+status                    show fan state
+start [minutes] [speed]   start the fan (speed: low, mid, high)
+stop                      stop the fan
+speed <low|mid|high>      change speed while running
+temps                     show temperature sensor readings
+config get [key]          show configuration
+config set <key> <value>  change configuration
+filter                    show fan run time and filter life
+filter reset              record that the filters have been changed
+time                      show the date and time
+time set <date> <time>    set the clock (e.g. time set 2024-10-17 19:30)
+schedule                  show the weekly schedule
+schedule add <days> <start-end> <speed>
+                          run the fan at these times (e.g. mon-fri 18:00-22:00 high)
+schedule remove <n>       remove an entry from the schedule
+schedule clear            remove every entry from the schedule
+version                   show firmware version";
+
+pub fn parse(line: &str) -> Result<Command<'_>, ParseError> {
+    let mut args = line.split_whitespace();
+
+    let command = match args.next().ok_or(ParseError::Empty)? {
+        "help" | "?" => Command::Help,
+        "status" => Command::Status,
+        "start" => {
+            let mut minutes = None;
+            let mut speed = None;
+
+            // Both arguments are optional, so a lone argument may be either
+            for arg in args.by_ref().take(2) {
+                if let Ok(s) = arg.parse::<FanSpeed>() {
+                    if speed.is_some() {
+                        return Err(ParseError::InvalidArgument);
+                    }
+                    speed = Some(s);
+                } else if minutes.is_none() && speed.is_none() {
+                    minutes = Some(parse_minutes(arg)?);
+                } else {
+                    return Err(ParseError::InvalidArgument);
+                }
+            }
+
+            Command::Start { minutes, speed }
+        }
+        "stop" => Command::Stop,
+        "speed" => Command::Speed(parse_speed(args.next())?),
+        "temps" => Command::Temperatures,
+        "config" => match args.next().ok_or(ParseError::MissingArgument)? {
+            "get" => Command::ConfigGet(args.next()),
+            "set" => Command::ConfigSet {
+                key: args.next().ok_or(ParseError::MissingArgument)?,
+                value: args.next().ok_or(ParseError::MissingArgument)?,
+            },
+            _ => return Err(ParseError::InvalidArgument),
+        },
+        "filter" => match args.next() {
+            None => Command::Filter,
+            Some("reset") => Command::FilterReset,
+            Some(_) => return Err(ParseError::InvalidArgument),
+        },
+        "time" => match args.next() {
+            None => Command::Time,
+            Some("set") => Command::TimeSet(
+                DateTime::parse(
+                    args.next().ok_or(ParseError::MissingArgument)?,
+                    args.next().ok_or(ParseError::MissingArgument)?,
+                )
+                .ok_or(ParseError::InvalidArgument)?,
+            ),
+            Some(_) => return Err(ParseError::InvalidArgument),
+        },
+        "schedule" => match args.next() {
+            None => Command::Schedule,
+            Some("add") => Command::ScheduleAdd(
+                ScheduleEntry::from_fields(
+                    args.next().ok_or(ParseError::MissingArgument)?,
+                    args.next().ok_or(ParseError::MissingArgument)?,
+                    args.next().ok_or(ParseError::MissingArgument)?,
+                )
+                .map_err(|_| ParseError::InvalidArgument)?,
+            ),
+            Some("remove") => match args.next().ok_or(ParseError::MissingArgument)?.parse() {
+                Ok(0) | Err(_) => return Err(ParseError::InvalidArgument),
+                Ok(n) => Command::ScheduleRemove(n),
+            },
+            Some("clear") => Command::ScheduleClear,
+            Some(_) => return Err(ParseError::InvalidArgument),
+        },
+        "version" => Command::Version,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+
+    if args.next().is_some() {
+        Err(ParseError::TooManyArguments)
+    } else {
+        Ok(command)
+    }
+}
+
+fn parse_minutes(arg: &str) -> Result<u32, ParseError> {
+    match arg.parse() {
+        Ok(0) | Err(_) => Err(ParseError::InvalidArgument),
+        Ok(minutes) => Ok(minutes),
+    }
+}
+
+fn parse_speed(arg: Option<&str>) -> Result<FanSpeed, ParseError> {
+    arg.ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidArgument)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simple_commands() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("?"), Ok(Command::Help));
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(parse("stop"), Ok(Command::Stop));
+        assert_eq!(parse("temps"), Ok(Command::Temperatures));
+        assert_eq!(parse("version"), Ok(Command::Version));
+    }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        assert_eq!(parse("  status  "), Ok(Command::Status));
+        assert_eq!(
+            parse("config   set  run_minutes\t30"),
+            Ok(Command::ConfigSet {
+                key: "run_minutes",
+                value: "30"
+            })
+        );
+    }
+
+    #[test]
+    fn start() {
+        assert_eq!(
+            parse("start"),
+            Ok(Command::Start {
+                minutes: None,
+                speed: None
+            })
+        );
+        assert_eq!(
+            parse("start 45"),
+            Ok(Command::Start {
+                minutes: Some(45),
+                speed: None
+            })
+        );
+        assert_eq!(
+            parse("start high"),
+            Ok(Command::Start {
+                minutes: None,
+                speed: Some(FanSpeed::High)
+            })
+        );
+        assert_eq!(
+            parse("start 10 mid"),
+            Ok(Command::Start {
+                minutes: Some(10),
+                speed: Some(FanSpeed::Medium)
+            })
+        );
+    }
+
+    #[test]
+    fn start_invalid() {
+        assert_eq!(parse("start 0"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("start -5"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("start fast"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("start high 10"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("start low high"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("start 10 low 5"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn speed() {
+        assert_eq!(parse("speed low"), Ok(Command::Speed(FanSpeed::Low)));
+        assert_eq!(parse("speed mid"), Ok(Command::Speed(FanSpeed::Medium)));
+        assert_eq!(parse("speed high"), Ok(Command::Speed(FanSpeed::High)));
+        assert_eq!(parse("speed"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("speed max"), Err(ParseError::InvalidArgument));
+    }
+
+    #[test]
+    fn control_commands() {
+        assert_eq!(
+            parse("start 30 high").unwrap().control_command(),
+            Some(ControlCommand::Start {
+                duration: Some(Duration::from_secs(30 * 60)),
+                speed: Some(FanSpeed::High)
+            })
+        );
+        assert_eq!(
+            parse("stop").unwrap().control_command(),
+            Some(ControlCommand::Stop)
+        );
+        assert_eq!(
+            parse("speed mid").unwrap().control_command(),
+            Some(ControlCommand::SetSpeed(FanSpeed::Medium))
+        );
+        assert_eq!(parse("status").unwrap().control_command(), None);
+    }
+
+    #[test]
+    fn config() {
+        assert_eq!(parse("config get"), Ok(Command::ConfigGet(None)));
+        assert_eq!(
+            parse("config get run_minutes"),
+            Ok(Command::ConfigGet(Some("run_minutes")))
+        );
+        assert_eq!(
+            parse("config set temperature_speed high"),
+            Ok(Command::ConfigSet {
+                key: "temperature_speed",
+                value: "high"
+            })
+        );
+
+        assert_eq!(parse("config"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("config set"), Err(ParseError::MissingArgument));
+        assert_eq!(
+            parse("config set run_minutes"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(parse("config delete"), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            parse("config get run_minutes purge_minutes"),
+            Err(ParseError::TooManyArguments)
+        );
+        assert_eq!(
+            parse("config set run_minutes 30 40"),
+            Err(ParseError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(parse("filter"), Ok(Command::Filter));
+        assert_eq!(parse("filter reset"), Ok(Command::FilterReset));
+        assert_eq!(parse("filter replace"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("filter reset now"), Err(ParseError::TooManyArguments));
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(parse("time"), Ok(Command::Time));
+        assert_eq!(
+            parse("time set 2024-10-17 19:30"),
+            Ok(Command::TimeSet(DateTime {
+                year: 2024,
+                month: 10,
+                day: 17,
+                hour: 19,
+                minute: 30,
+                second: 0,
+            }))
+        );
+        assert_eq!(
+            parse("time set 2024-10-17"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(
+            parse("time set 2024-10-32 19:30"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(parse("time zone"), Err(ParseError::InvalidArgument));
+    }
+
+    #[test]
+    fn schedule() {
+        assert_eq!(parse("schedule"), Ok(Command::Schedule));
+        assert_eq!(
+            parse("schedule add mon-fri 18:00-22:00 high"),
+            Ok(Command::ScheduleAdd(
+                "mon-fri 18:00-22:00 high".parse().unwrap()
+            ))
+        );
+        assert_eq!(parse("schedule remove 2"), Ok(Command::ScheduleRemove(2)));
+        assert_eq!(parse("schedule clear"), Ok(Command::ScheduleClear));
+
+        assert_eq!(
+            parse("schedule add mon-fri 18:00-22:00"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(
+            parse("schedule add mon-fri 22:00 high"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse("schedule add mon-fri 18:00-22:00 high low"),
+            Err(ParseError::TooManyArguments)
+        );
+        assert_eq!(parse("schedule remove 0"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("schedule remove"), Err(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+        assert_eq!(parse("launch"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("stop now"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("version 2"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("START"), Err(ParseError::UnknownCommand));
+    }
+}
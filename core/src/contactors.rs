@@ -0,0 +1,233 @@
+//! Rules for switching the fan speed selection contactors.
+//!
+//! Each speed is a separate winding of the motor, so closing two speed selection contactors at
+//! once shorts the supply across the windings. Switching straight from one winding to another
+//! while the motor is still spinning is also hard on it, so the motor is given time to coast
+//! down first.
+
+use crate::{config::Config, fan::FanSpeed};
+use core::fmt;
+use embassy_time::Duration;
+use embedded_hal::digital::OutputPin;
+
+const SPEEDS: [FanSpeed; 3] = [FanSpeed::Low, FanSpeed::Medium, FanSpeed::High];
+
+/// The outputs that drive the speed selection contactor coils.
+///
+/// The pins are owned here and can only be driven by selecting a speed, so there is no way to
+/// drive a second coil while one is already driven.
+#[derive(Debug)]
+pub struct SpeedOutputs<P> {
+    low: P,
+    medium: P,
+    high: P,
+    selected: Option<FanSpeed>,
+}
+
+impl<P: OutputPin> SpeedOutputs<P> {
+    /// Takes the outputs, with none of them driven.
+    pub fn new(low: P, medium: P, high: P) -> Result<Self, P::Error> {
+        let mut outputs = Self {
+            low,
+            medium,
+            high,
+            selected: None,
+        };
+        outputs.select(None)?;
+        Ok(outputs)
+    }
+
+    /// The speed whose output is driven.
+    pub fn selected(&self) -> Option<&FanSpeed> {
+        self.selected.as_ref()
+    }
+
+    /// Drives the output for `speed` and no other, or none at all.
+    ///
+    /// Everything else is released before the output for `speed` is driven.
+    pub fn select(&mut self, speed: Option<FanSpeed>) -> Result<(), P::Error> {
+        self.selected = None;
+        for other in SPEEDS.iter().filter(|s| Some(*s) != speed.as_ref()) {
+            self.output(other).set_low()?;
+        }
+
+        if let Some(speed) = &speed {
+            self.output(speed).set_high()?;
+        }
+        self.selected = speed;
+        Ok(())
+    }
+
+    fn output(&mut self, speed: &FanSpeed) -> &mut P {
+        match speed {
+            FanSpeed::Low => &mut self.low,
+            FanSpeed::Medium => &mut self.medium,
+            FanSpeed::High => &mut self.high,
+        }
+    }
+}
+
+/// How much longer to wait before closing a speed selection contactor, so that the motor has had
+/// [`Config::contactor_dead_time`] to coast down since the last one was opened.
+///
+/// `since_opened` is `None` if no contactor has been closed since power on.
+pub fn dead_time_remaining(since_opened: Option<Duration>, config: &Config) -> Duration {
+    since_opened
+        .and_then(|t| config.contactor_dead_time.checked_sub(t))
+        .unwrap_or(Duration::from_secs(0))
+}
+
+/// Whether the auxiliary contact of each speed selection contactor is closed.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AuxContacts {
+    pub low: bool,
+    pub medium: bool,
+    pub high: bool,
+}
+
+impl AuxContacts {
+    fn closed(&self, speed: &FanSpeed) -> bool {
+        match speed {
+            FanSpeed::Low => self.low,
+            FanSpeed::Medium => self.medium,
+            FanSpeed::High => self.high,
+        }
+    }
+
+    /// Checks that the contactors are in the state they have been told to be in.
+    ///
+    /// A contactor that is stuck closed is reported ahead of one that has not closed, as that is
+    /// the more dangerous of the two.
+    pub fn check(&self, selected: Option<&FanSpeed>) -> Result<(), ContactorFault> {
+        let stuck = SPEEDS
+            .iter()
+            .find(|speed| Some(*speed) != selected && self.closed(speed));
+        if let Some(speed) = stuck {
+            return Err(ContactorFault::DidNotOpen(speed.clone()));
+        }
+
+        match selected {
+            Some(speed) if !self.closed(speed) => Err(ContactorFault::DidNotClose(speed.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A speed selection contactor that has not done what it was told to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ContactorFault {
+    /// The contactor did not pull in, e.g. a failed coil or a blown fuse
+    DidNotClose(FanSpeed),
+    /// The contactor did not drop out, e.g. welded contacts
+    DidNotOpen(FanSpeed),
+}
+
+impl fmt::Display for ContactorFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DidNotClose(speed) => write!(f, "{} contactor did not close", speed.name()),
+            Self::DidNotOpen(speed) => write!(f, "{} contactor did not open", speed.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::{cell::RefCell, convert::Infallible};
+    use std::rc::Rc;
+
+    /// An output that records its level in a set shared with the others.
+    struct Pin {
+        index: usize,
+        levels: Rc<RefCell<[bool; 3]>>,
+    }
+
+    impl embedded_hal::digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.levels.borrow_mut()[self.index] = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            let mut levels = self.levels.borrow_mut();
+            levels[self.index] = true;
+            assert!(
+                levels.iter().filter(|high| **high).count() <= 1,
+                "two outputs driven at once: {levels:?}"
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn at_most_one_output_is_driven() {
+        let levels = Rc::new(RefCell::new([true; 3]));
+        let pin = |index| Pin {
+            index,
+            levels: levels.clone(),
+        };
+
+        let mut outputs = SpeedOutputs::new(pin(0), pin(1), pin(2)).unwrap();
+        assert_eq!(*levels.borrow(), [false, false, false]);
+        assert_eq!(outputs.selected(), None);
+
+        for (speed, expected) in [
+            (Some(FanSpeed::Low), [true, false, false]),
+            (Some(FanSpeed::High), [false, false, true]),
+            (Some(FanSpeed::Medium), [false, true, false]),
+            (None, [false, false, false]),
+        ] {
+            outputs.select(speed.clone()).unwrap();
+            assert_eq!(*levels.borrow(), expected);
+            assert_eq!(outputs.selected(), speed.as_ref());
+        }
+    }
+
+    #[test]
+    fn dead_time() {
+        let mut config = Config::DEFAULT;
+        config.contactor_dead_time = Duration::from_secs(2);
+
+        assert_eq!(dead_time_remaining(None, &config), Duration::from_secs(0));
+        assert_eq!(
+            dead_time_remaining(Some(Duration::from_millis(500)), &config),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            dead_time_remaining(Some(Duration::from_secs(10)), &config),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn check_aux_contacts() {
+        let open = AuxContacts::default();
+        let medium = AuxContacts {
+            medium: true,
+            ..Default::default()
+        };
+
+        assert_eq!(open.check(None), Ok(()));
+        assert_eq!(medium.check(Some(&FanSpeed::Medium)), Ok(()));
+
+        assert_eq!(
+            open.check(Some(&FanSpeed::Medium)),
+            Err(ContactorFault::DidNotClose(FanSpeed::Medium))
+        );
+        assert_eq!(
+            medium.check(None),
+            Err(ContactorFault::DidNotOpen(FanSpeed::Medium))
+        );
+        assert_eq!(
+            medium.check(Some(&FanSpeed::High)),
+            Err(ContactorFault::DidNotOpen(FanSpeed::Medium))
+        );
+    }
+}
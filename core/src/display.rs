@@ -0,0 +1,187 @@
+use crate::run_logic::{Reason, State};
+use embassy_time::Duration;
+
+/// How long one pulse of the backlight takes when drawing attention to the display.
+pub const BACKLIGHT_PULSE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Which areas of the main screen need to be drawn again after the state changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MainScreenRedraw {
+    /// The fan command and the reason for it
+    pub command: bool,
+    /// The remaining run time (shown differently while purging or running continuously), and any
+    /// change just made to it
+    pub time: bool,
+    /// The banner across the top, which also warns that the clock has not been set
+    pub banner: bool,
+}
+
+impl MainScreenRedraw {
+    /// Compares the state currently on screen (if any) with a new one.
+    pub fn between(old: Option<&State>, new: &State) -> Self {
+        match old {
+            Some(old) => Self {
+                command: old.fan_command() != new.fan_command() || old.reason() != new.reason(),
+                time: old.time_remaining() != new.time_remaining()
+                    || old.run_time_change() != new.run_time_change()
+                    || purging(old) != purging(new)
+                    || old.continuous() != new.continuous(),
+                banner: old.clock_not_set() != new.clock_not_set(),
+            },
+            None => Self {
+                command: true,
+                time: true,
+                banner: true,
+            },
+        }
+    }
+}
+
+fn purging(state: &State) -> bool {
+    state.reason() == Some(Reason::Purge)
+}
+
+/// The backlight brightness `elapsed` into pulsing, which fades from `percent` down to a quarter
+/// of it and back up again once every [`BACKLIGHT_PULSE_PERIOD`].
+pub fn backlight_pulse(percent: u8, elapsed: Duration) -> u8 {
+    let period = BACKLIGHT_PULSE_PERIOD.as_ticks();
+    let half_period = period / 2;
+
+    let phase = elapsed.as_ticks() % period;
+    let dimming = if phase < half_period {
+        phase
+    } else {
+        period - phase
+    };
+
+    let depth = percent as u64 * 3 / 4;
+    (percent as u64 - depth * dimming / half_period) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buttons::{Button, ButtonEvent, ButtonPushDuration},
+        config::Config,
+        run_logic::Triggers,
+    };
+
+    #[test]
+    fn first_state_draws_everything() {
+        let state = Triggers::default().resolve();
+        assert_eq!(
+            MainScreenRedraw::between(None, &state),
+            MainScreenRedraw {
+                command: true,
+                time: true,
+                banner: true,
+            }
+        );
+    }
+
+    #[test]
+    fn only_changed_areas_are_redrawn() {
+        let config = Config::DEFAULT;
+        let mut triggers = Triggers::default();
+        let stopped = triggers.resolve();
+
+        assert_eq!(
+            MainScreenRedraw::between(Some(&stopped), &stopped),
+            MainScreenRedraw::default()
+        );
+
+        triggers.button.handle_button(
+            ButtonEvent {
+                button: Button::Demand,
+                push_duration: ButtonPushDuration::Short,
+                clicks: 1,
+            },
+            &config,
+        );
+        let started = triggers.resolve();
+        assert_eq!(
+            MainScreenRedraw::between(Some(&stopped), &started),
+            MainScreenRedraw {
+                command: true,
+                time: true,
+                banner: false,
+            }
+        );
+
+        triggers.button.handle_tick(&config);
+        let ticked = triggers.resolve();
+        assert_eq!(
+            MainScreenRedraw::between(Some(&started), &ticked),
+            MainScreenRedraw {
+                command: false,
+                time: true,
+                banner: false,
+            }
+        );
+    }
+
+    #[test]
+    fn continuous_mode_redraws_time() {
+        let mut triggers = Triggers::default();
+        let stopped = triggers.resolve();
+
+        triggers.button.handle_button(
+            ButtonEvent {
+                button: Button::Both,
+                push_duration: ButtonPushDuration::Short,
+                clicks: 1,
+            },
+            &Config::DEFAULT,
+        );
+        let continuous = triggers.resolve();
+
+        // There is no time remaining either way
+        assert_eq!(
+            MainScreenRedraw::between(Some(&stopped), &continuous),
+            MainScreenRedraw {
+                command: true,
+                time: true,
+                banner: false,
+            }
+        );
+    }
+
+    #[test]
+    fn clock_not_set_redraws_banner() {
+        let mut schedule = crate::schedule::Schedule::default();
+        schedule
+            .add("daily 08:00-09:00 low".parse().unwrap())
+            .unwrap();
+        let mut triggers = Triggers::default();
+        let stopped = triggers.resolve();
+
+        triggers.schedule.handle_time(None, &schedule);
+        assert_eq!(
+            MainScreenRedraw::between(Some(&stopped), &triggers.resolve()),
+            MainScreenRedraw {
+                command: false,
+                time: false,
+                banner: true,
+            }
+        );
+    }
+
+    #[test]
+    fn backlight_pulses() {
+        let quarter = BACKLIGHT_PULSE_PERIOD / 4;
+
+        assert_eq!(backlight_pulse(100, Duration::from_ticks(0)), 100);
+        assert_eq!(backlight_pulse(100, quarter), 63);
+        assert_eq!(backlight_pulse(100, quarter * 2), 25);
+        assert_eq!(backlight_pulse(100, quarter * 3), 63);
+        assert_eq!(backlight_pulse(100, quarter * 4), 100);
+        assert_eq!(
+            backlight_pulse(100, BACKLIGHT_PULSE_PERIOD * 7 + quarter * 2),
+            25
+        );
+
+        assert_eq!(backlight_pulse(20, quarter * 2), 5);
+        assert_eq!(backlight_pulse(0, quarter), 0);
+    }
+}
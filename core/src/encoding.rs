@@ -0,0 +1,128 @@
+//! Little endian serialisation of values that are stored in flash.
+
+use crate::{fan::FanSpeed, run_logic::InterlockAction};
+use embassy_time::Duration;
+
+pub(crate) struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The number of bytes written so far.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Durations are stored in milliseconds
+    pub(crate) fn duration(&mut self, value: Duration) {
+        self.u32(value.as_millis() as u32);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Whether everything has been read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.buf.get(self.pos..self.pos + N)?.try_into().ok()?;
+        self.pos += N;
+        Some(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|b| b[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    pub(crate) fn f32(&mut self) -> Option<f32> {
+        self.bytes().map(f32::from_le_bytes)
+    }
+
+    pub(crate) fn duration(&mut self) -> Option<Duration> {
+        self.u32().map(|ms| Duration::from_millis(ms as u64))
+    }
+}
+
+pub(crate) fn encode_speed(speed: &FanSpeed) -> u8 {
+    match speed {
+        FanSpeed::Low => 0,
+        FanSpeed::Medium => 1,
+        FanSpeed::High => 2,
+    }
+}
+
+pub(crate) fn decode_speed(value: u8) -> Option<FanSpeed> {
+    match value {
+        0 => Some(FanSpeed::Low),
+        1 => Some(FanSpeed::Medium),
+        2 => Some(FanSpeed::High),
+        _ => None,
+    }
+}
+
+pub(crate) fn encode_interlock_action(action: InterlockAction) -> u8 {
+    match action {
+        InterlockAction::Stop => 0,
+        InterlockAction::Boost => 1,
+    }
+}
+
+pub(crate) fn decode_interlock_action(value: u8) -> Option<InterlockAction> {
+    match value {
+        0 => Some(InterlockAction::Stop),
+        1 => Some(InterlockAction::Boost),
+        _ => None,
+    }
+}
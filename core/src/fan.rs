@@ -0,0 +1,316 @@
+use crate::contactors::ContactorFault;
+use core::{fmt, str::FromStr};
+
+/// Commands are ordered by how much airflow they demand (i.e. `Stop` is the lowest and
+/// `Run(FanSpeed::High)` is the highest).
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FanCommand {
+    Stop,
+    Run(FanSpeed),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FanSpeed {
+    Low,
+    Medium,
+    High,
+}
+
+impl FanSpeed {
+    pub fn cycle(&mut self) {
+        *self = match self {
+            Self::Low => Self::Medium,
+            Self::Medium => Self::High,
+            Self::High => Self::Low,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "mid",
+            Self::High => "high",
+        }
+    }
+}
+
+impl FromStr for FanSpeed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Self::Low),
+            "mid" | "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What the fan is actually doing, as reported by whatever switches the contactors (which takes
+/// a while after being given a [`FanCommand`]).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FanStatus {
+    #[default]
+    Idle,
+    /// The contactors are being switched to carry out a command
+    Switching(FanCommand),
+    Running(FanSpeed),
+    /// A contactor did not switch as it should have, the fan has been stopped
+    Fault(ContactorFault),
+}
+
+impl FanStatus {
+    /// The status once `command` has been carried out.
+    pub fn applied(command: &FanCommand) -> Self {
+        match command {
+            FanCommand::Stop => Self::Idle,
+            FanCommand::Run(speed) => Self::Running(speed.clone()),
+        }
+    }
+
+    /// The speed the fan is known to be running at, `None` while it is stopped or the contactors
+    /// are being switched.
+    pub fn running_speed(&self) -> Option<&FanSpeed> {
+        match self {
+            Self::Running(speed) => Some(speed),
+            _ => None,
+        }
+    }
+}
+
+/// Brings the fan to the newest [`FanCommand`] it has been given.
+///
+/// Only the newest command is ever carried out, anything that was superseded while the contactors
+/// were being switched is skipped.
+///
+/// A command that ends in a contactor fault is not tried again until something else has been
+/// asked for, even if the same command is sent again.
+#[derive(Debug)]
+pub struct FanController {
+    /// The command that was last carried out successfully
+    applied: FanCommand,
+    /// The command that last ended in a contactor fault, while it is still being asked for
+    faulted: Option<FanCommand>,
+}
+
+impl Default for FanController {
+    fn default() -> Self {
+        Self {
+            applied: FanCommand::Stop,
+            faulted: None,
+        }
+    }
+}
+
+impl FanController {
+    /// The command to carry out to bring the fan to `latest`, or `None` if it is already there or
+    /// `latest` is the command that faulted.
+    pub fn pending(&mut self, latest: &FanCommand) -> Option<FanCommand> {
+        if self.faulted.as_ref() == Some(latest) {
+            return None;
+        }
+        self.faulted = None;
+
+        (*latest != self.applied).then(|| latest.clone())
+    }
+
+    /// Records the outcome of carrying out `command`, giving the status of the fan afterwards.
+    pub fn switched(
+        &mut self,
+        command: FanCommand,
+        result: Result<(), ContactorFault>,
+    ) -> FanStatus {
+        match result {
+            Ok(()) => {
+                let status = FanStatus::applied(&command);
+                self.applied = command;
+                status
+            }
+            Err(fault) => {
+                // Stays off until something else is asked for, which tries the contactors again
+                self.applied = FanCommand::Stop;
+                self.faulted = Some(command);
+                FanStatus::Fault(fault)
+            }
+        }
+    }
+}
+
+impl fmt::Display for FanStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Idle => f.write_str("off"),
+            Self::Switching(FanCommand::Stop) => f.write_str("switching off"),
+            Self::Switching(FanCommand::Run(speed)) => write!(f, "switching to {}", speed.name()),
+            Self::Running(speed) => f.write_str(speed.name()),
+            Self::Fault(fault) => write!(f, "fault, {fault}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch::Watch};
+
+    const COMMANDS: [FanCommand; 4] = [
+        FanCommand::Stop,
+        FanCommand::Run(FanSpeed::Low),
+        FanCommand::Run(FanSpeed::Medium),
+        FanCommand::Run(FanSpeed::High),
+    ];
+
+    #[test]
+    fn cycle() {
+        let mut speed = FanSpeed::Low;
+        speed.cycle();
+        assert_eq!(speed, FanSpeed::Medium);
+        speed.cycle();
+        assert_eq!(speed, FanSpeed::High);
+        speed.cycle();
+        assert_eq!(speed, FanSpeed::Low);
+    }
+
+    #[test]
+    fn command_ordering() {
+        assert!(FanCommand::Stop < FanCommand::Run(FanSpeed::Low));
+        assert!(FanCommand::Run(FanSpeed::Low) < FanCommand::Run(FanSpeed::Medium));
+        assert!(FanCommand::Run(FanSpeed::Medium) < FanCommand::Run(FanSpeed::High));
+    }
+
+    #[test]
+    fn name_round_trip() {
+        for speed in [FanSpeed::Low, FanSpeed::Medium, FanSpeed::High] {
+            assert_eq!(speed.name().parse(), Ok(speed));
+        }
+    }
+
+    #[test]
+    fn status() {
+        assert_eq!(FanStatus::applied(&FanCommand::Stop), FanStatus::Idle);
+        assert_eq!(
+            FanStatus::applied(&FanCommand::Run(FanSpeed::Medium)),
+            FanStatus::Running(FanSpeed::Medium)
+        );
+
+        assert_eq!(
+            FanStatus::Running(FanSpeed::High).running_speed(),
+            Some(&FanSpeed::High)
+        );
+        assert_eq!(
+            FanStatus::Switching(FanCommand::Run(FanSpeed::High)).running_speed(),
+            None
+        );
+        assert_eq!(
+            FanStatus::Fault(ContactorFault::DidNotClose(FanSpeed::High)).running_speed(),
+            None
+        );
+    }
+
+    #[test]
+    fn controller() {
+        let mut fan = FanController::default();
+        assert_eq!(fan.pending(&FanCommand::Stop), None);
+
+        let cmd = fan.pending(&FanCommand::Run(FanSpeed::Low)).unwrap();
+        assert_eq!(cmd, FanCommand::Run(FanSpeed::Low));
+        assert_eq!(fan.switched(cmd, Ok(())), FanStatus::Running(FanSpeed::Low));
+        assert_eq!(fan.pending(&FanCommand::Run(FanSpeed::Low)), None);
+
+        let fault = ContactorFault::DidNotClose(FanSpeed::High);
+        let cmd = fan.pending(&FanCommand::Run(FanSpeed::High)).unwrap();
+        assert_eq!(
+            fan.switched(cmd, Err(fault.clone())),
+            FanStatus::Fault(fault)
+        );
+
+        // The same command again does not try the contactors again
+        assert_eq!(fan.pending(&FanCommand::Run(FanSpeed::High)), None);
+
+        // Asking for something else first does
+        assert_eq!(fan.pending(&FanCommand::Stop), None);
+        assert_eq!(
+            fan.pending(&FanCommand::Run(FanSpeed::High)),
+            Some(FanCommand::Run(FanSpeed::High))
+        );
+    }
+
+    #[test]
+    fn fault_is_not_retried_when_the_command_is_sent_again() {
+        let commands: Watch<NoopRawMutex, FanCommand, 1> = Watch::new();
+        let mut rx = commands.receiver().unwrap();
+        let tx = commands.sender();
+        let mut fan = FanController::default();
+
+        let fault = ContactorFault::DidNotClose(FanSpeed::Medium);
+        tx.send(FanCommand::Run(FanSpeed::Medium));
+        let cmd = fan.pending(&rx.try_changed().unwrap()).unwrap();
+        assert_eq!(
+            fan.switched(cmd, Err(fault.clone())),
+            FanStatus::Fault(fault)
+        );
+
+        // Sending the same value again still wakes the fan task
+        tx.send(FanCommand::Run(FanSpeed::Medium));
+        let latest = rx.try_changed().unwrap();
+        assert_eq!(fan.pending(&latest), None);
+
+        tx.send(FanCommand::Run(FanSpeed::Low));
+        let latest = rx.try_changed().unwrap();
+        assert_eq!(fan.pending(&latest), Some(FanCommand::Run(FanSpeed::Low)));
+    }
+
+    /// Plays out the fan task taking commands from a latest value channel, with `burst` more
+    /// commands arriving every time it is busy switching the contactors.
+    fn rapid_commands(burst: usize) {
+        let commands: Watch<NoopRawMutex, FanCommand, 1> = Watch::new();
+        let mut rx = commands.receiver().unwrap();
+        let tx = commands.sender();
+
+        let mut fan = FanController::default();
+        let mut status = FanStatus::Idle;
+        let mut sent = COMMANDS.iter().cycle().skip(1).take(13);
+        let mut last_sent = None;
+        let mut switches = 0;
+
+        loop {
+            for cmd in sent.by_ref().take(burst.max(1)) {
+                tx.send(cmd.clone());
+                last_sent = Some(cmd.clone());
+            }
+
+            let Some(latest) = rx.try_changed() else {
+                break;
+            };
+            if let Some(cmd) = fan.pending(&latest) {
+                // More commands arrive while the contactors are being switched
+                for cmd in sent.by_ref().take(burst) {
+                    tx.send(cmd.clone());
+                    last_sent = Some(cmd.clone());
+                }
+
+                status = fan.switched(cmd, Ok(()));
+                switches += 1;
+            }
+        }
+
+        assert_eq!(status, FanStatus::applied(&last_sent.unwrap()));
+        assert_eq!(rx.try_changed(), None);
+
+        // Superseded commands are skipped rather than being worked through one at a time
+        if burst > 1 {
+            assert!(switches < 13);
+        }
+    }
+
+    #[test]
+    fn ends_in_last_commanded_state() {
+        for burst in 0..=14 {
+            rapid_commands(burst);
+        }
+    }
+}
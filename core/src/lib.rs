@@ -0,0 +1,23 @@
+//! The hardware independent parts of the air filter controller.
+//!
+//! Everything in here can be built and tested on the host, the firmware crate wires it up to the
+//! RP2040 peripherals and embassy tasks.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod buttons;
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod contactors;
+pub mod display;
+mod encoding;
+pub mod fan;
+pub mod modbus;
+pub mod mqtt;
+pub mod run_logic;
+pub mod runtime;
+pub mod schedule;
+pub mod settings;
+pub mod temperature;
+pub mod time;
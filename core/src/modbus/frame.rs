@@ -0,0 +1,356 @@
+//! Encoding and decoding of Modbus RTU frames.
+//!
+//! Only the functions needed to read and write registers are supported.
+
+/// The largest frame allowed by the Modbus RTU specification.
+pub const MAX_FRAME_SIZE: usize = 256;
+
+/// The unit address that every slave acts on writes to, without replying.
+pub const BROADCAST: u8 = 0;
+
+/// The most registers that can be read with one request.
+pub const MAX_READ: u16 = 125;
+
+/// The most registers that can be written with one request.
+pub const MAX_WRITE: u16 = 123;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Set in the function code of a reply to say that it is an exception.
+const EXCEPTION: u8 = 0x80;
+
+/// Why a request could not be carried out, sent back to the master in place of a reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Exception {
+    IllegalFunction = 0x01,
+    IllegalDataAddress = 0x02,
+    IllegalDataValue = 0x03,
+}
+
+/// Register values as they are sent, two bytes each, most significant first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Values<'a>(&'a [u8]);
+
+impl Values<'_> {
+    pub fn len(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.0
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Request<'a> {
+    ReadHoldingRegisters { address: u16, count: u16 },
+    ReadInputRegisters { address: u16, count: u16 },
+    WriteSingleRegister { address: u16, value: u16 },
+    WriteMultipleRegisters { address: u16, values: Values<'a> },
+}
+
+/// A frame received from the master.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame<'a> {
+    /// The address of the unit the frame is for
+    pub unit: u8,
+    pub function: u8,
+    /// The request, or why it was not understood
+    pub request: Result<Request<'a>, Exception>,
+}
+
+/// The Modbus CRC-16 of `data`.
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc = 0xffff_u16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn u16_at(buf: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([buf[i], buf[i + 1]])
+}
+
+/// Decodes a complete frame (as delimited by a silent interval on the line).
+///
+/// Frames that are too short or fail the CRC check are `None`, these must be ignored rather
+/// than replied to.
+pub fn decode(frame: &[u8]) -> Option<Frame<'_>> {
+    if frame.len() < 4 || frame.len() > MAX_FRAME_SIZE {
+        return None;
+    }
+
+    let (data, checksum) = frame.split_at(frame.len() - 2);
+    if crc(data) != u16::from_le_bytes([checksum[0], checksum[1]]) {
+        return None;
+    }
+
+    let (unit, function, pdu) = (data[0], data[1], &data[2..]);
+    let request = match function {
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            if pdu.len() != 4 {
+                Err(Exception::IllegalDataValue)
+            } else {
+                let (address, count) = (u16_at(pdu, 0), u16_at(pdu, 2));
+                if !(1..=MAX_READ).contains(&count) {
+                    Err(Exception::IllegalDataValue)
+                } else if function == READ_HOLDING_REGISTERS {
+                    Ok(Request::ReadHoldingRegisters { address, count })
+                } else {
+                    Ok(Request::ReadInputRegisters { address, count })
+                }
+            }
+        }
+        WRITE_SINGLE_REGISTER => {
+            if pdu.len() != 4 {
+                Err(Exception::IllegalDataValue)
+            } else {
+                Ok(Request::WriteSingleRegister {
+                    address: u16_at(pdu, 0),
+                    value: u16_at(pdu, 2),
+                })
+            }
+        }
+        WRITE_MULTIPLE_REGISTERS => {
+            if pdu.len() < 5 {
+                Err(Exception::IllegalDataValue)
+            } else {
+                let (address, count, byte_count) = (u16_at(pdu, 0), u16_at(pdu, 2), pdu[4]);
+                let values = &pdu[5..];
+                if !(1..=MAX_WRITE).contains(&count)
+                    || byte_count as usize != 2 * count as usize
+                    || values.len() != byte_count as usize
+                {
+                    Err(Exception::IllegalDataValue)
+                } else {
+                    Ok(Request::WriteMultipleRegisters {
+                        address,
+                        values: Values(values),
+                    })
+                }
+            }
+        }
+        _ => Err(Exception::IllegalFunction),
+    };
+
+    Some(Frame {
+        unit,
+        function,
+        request,
+    })
+}
+
+/// Appends the CRC to the `len` bytes of a reply in `buf`, returning the length of the frame.
+fn finish(buf: &mut [u8; MAX_FRAME_SIZE], len: usize) -> usize {
+    let crc = crc(&buf[..len]).to_le_bytes();
+    buf[len..len + 2].copy_from_slice(&crc);
+    len + 2
+}
+
+/// Encodes the reply to a read into `buf`, returning its length.
+///
+/// At most [`MAX_READ`] values are included.
+pub fn encode_read_reply(
+    buf: &mut [u8; MAX_FRAME_SIZE],
+    unit: u8,
+    function: u8,
+    values: impl IntoIterator<Item = u16>,
+) -> usize {
+    buf[0] = unit;
+    buf[1] = function;
+
+    let mut len = 3;
+    for value in values.into_iter().take(MAX_READ as usize) {
+        buf[len..len + 2].copy_from_slice(&value.to_be_bytes());
+        len += 2;
+    }
+    buf[2] = (len - 3) as u8;
+
+    finish(buf, len)
+}
+
+/// Encodes the reply to a write into `buf`, returning its length.
+///
+/// The reply repeats the address along with the value written (for a single register) or the
+/// number of registers written (for multiple registers).
+pub fn encode_write_reply(
+    buf: &mut [u8; MAX_FRAME_SIZE],
+    unit: u8,
+    function: u8,
+    address: u16,
+    value_or_count: u16,
+) -> usize {
+    buf[0] = unit;
+    buf[1] = function;
+    buf[2..4].copy_from_slice(&address.to_be_bytes());
+    buf[4..6].copy_from_slice(&value_or_count.to_be_bytes());
+    finish(buf, 6)
+}
+
+/// Encodes an exception reply into `buf`, returning its length.
+pub fn encode_exception(
+    buf: &mut [u8; MAX_FRAME_SIZE],
+    unit: u8,
+    function: u8,
+    exception: Exception,
+) -> usize {
+    buf[0] = unit;
+    buf[1] = function | EXCEPTION;
+    buf[2] = exception as u8;
+    finish(buf, 3)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Adds the CRC to a frame.
+    fn frame(data: &[u8]) -> std::vec::Vec<u8> {
+        let mut frame = data.to_vec();
+        frame.extend_from_slice(&crc(data).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn crc_matches_specification() {
+        // Read holding registers 0x006b to 0x006d of unit 0x11, from the Modbus specification
+        assert_eq!(
+            frame(&[0x11, 0x03, 0x00, 0x6b, 0x00, 0x03]),
+            [0x11, 0x03, 0x00, 0x6b, 0x00, 0x03, 0x76, 0x87]
+        );
+    }
+
+    #[test]
+    fn decode_reads() {
+        assert_eq!(
+            decode(&frame(&[0x11, 0x03, 0x00, 0x6b, 0x00, 0x03])),
+            Some(Frame {
+                unit: 0x11,
+                function: 0x03,
+                request: Ok(Request::ReadHoldingRegisters {
+                    address: 0x6b,
+                    count: 3
+                }),
+            })
+        );
+        assert_eq!(
+            decode(&frame(&[0x01, 0x04, 0x00, 0x08, 0x00, 0x01]))
+                .unwrap()
+                .request,
+            Ok(Request::ReadInputRegisters {
+                address: 8,
+                count: 1
+            })
+        );
+
+        for request in [
+            &[0x01, 0x04, 0x00, 0x08, 0x00, 0x00][..],
+            &[0x01, 0x04, 0x00, 0x08, 0x00, 0x7e],
+            &[0x01, 0x04, 0x00, 0x08, 0x00],
+        ] {
+            assert_eq!(
+                decode(&frame(request)).unwrap().request,
+                Err(Exception::IllegalDataValue)
+            );
+        }
+    }
+
+    #[test]
+    fn decode_writes() {
+        assert_eq!(
+            decode(&frame(&[0x01, 0x06, 0x00, 0x01, 0x00, 0x03]))
+                .unwrap()
+                .request,
+            Ok(Request::WriteSingleRegister {
+                address: 1,
+                value: 3
+            })
+        );
+
+        let request = frame(&[
+            0x01, 0x10, 0x00, 0x00, 0x00, 0x02, 0x04, 0x00, 0x0a, 0x01, 0x02,
+        ]);
+        let Ok(Request::WriteMultipleRegisters { address, values }) =
+            decode(&request).unwrap().request
+        else {
+            panic!("not decoded");
+        };
+        assert_eq!(address, 0);
+        assert_eq!(
+            values.iter().collect::<std::vec::Vec<_>>(),
+            [0x000a, 0x0102]
+        );
+
+        // The byte count does not agree with the number of registers
+        assert_eq!(
+            decode(&frame(&[
+                0x01, 0x10, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x0a
+            ]))
+            .unwrap()
+            .request,
+            Err(Exception::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn decode_rejects() {
+        // Corrupt
+        let mut request = frame(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        request[3] ^= 0x01;
+        assert_eq!(decode(&request), None);
+
+        // Too short to be anything
+        assert_eq!(decode(&[0x01, 0x03, 0x00]), None);
+
+        assert_eq!(
+            decode(&frame(&[0x01, 0x2b, 0x0e, 0x01, 0x00]))
+                .unwrap()
+                .request,
+            Err(Exception::IllegalFunction)
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let mut buf = [0; MAX_FRAME_SIZE];
+
+        // The reply to the request from the specification
+        let len = encode_read_reply(&mut buf, 0x11, 0x03, [0x022b, 0x0000, 0x0064]);
+        assert_eq!(
+            &buf[..len],
+            frame(&[0x11, 0x03, 0x06, 0x02, 0x2b, 0x00, 0x00, 0x00, 0x64])
+        );
+
+        let len = encode_write_reply(&mut buf, 0x01, 0x06, 0x0001, 0x0003);
+        assert_eq!(&buf[..len], frame(&[0x01, 0x06, 0x00, 0x01, 0x00, 0x03]));
+
+        let len = encode_exception(&mut buf, 0x0a, 0x01, Exception::IllegalDataAddress);
+        assert_eq!(&buf[..len], frame(&[0x0a, 0x81, 0x02]));
+
+        // Too many values are cut short rather than overflowing the frame
+        let len = encode_read_reply(&mut buf, 0x01, 0x04, core::iter::repeat(0xffff));
+        assert_eq!(len, 3 + 2 * MAX_READ as usize + 2);
+        assert_eq!(buf[2], 250);
+    }
+}
@@ -0,0 +1,199 @@
+//! A Modbus RTU slave, so that the air filter can be monitored and controlled by a building
+//! management system over RS-485.
+//!
+//! Nothing in here knows about the UART, the firmware passes in each frame as it is received
+//! (delimited by a silent interval on the line) and sends back whatever reply is made.
+//! The registers are described in [`registers`].
+
+pub mod frame;
+pub mod registers;
+
+use frame::{
+    decode, encode_exception, encode_read_reply, encode_write_reply, Exception, Request, BROADCAST,
+    MAX_FRAME_SIZE, MAX_READ,
+};
+use registers::{write, Changes, Status};
+
+fn read(
+    address: u16,
+    count: u16,
+    register: impl Fn(u16) -> Result<u16, Exception>,
+) -> Result<heapless::Vec<u16, { MAX_READ as usize }>, Exception> {
+    let mut values = heapless::Vec::new();
+    for i in 0..count {
+        let address = address
+            .checked_add(i)
+            .ok_or(Exception::IllegalDataAddress)?;
+        values
+            .push(register(address)?)
+            .map_err(|_| Exception::IllegalDataValue)?;
+    }
+    Ok(values)
+}
+
+/// Handles a frame received from the master, as the slave with address `unit`.
+///
+/// Returns the length of the reply written to `reply`, which is `None` when nothing must be
+/// sent (the frame was corrupt, for another unit or broadcast), and what the frame asks to be
+/// changed.
+pub fn handle(
+    unit: u8,
+    frame: &[u8],
+    status: &Status,
+    reply: &mut [u8; MAX_FRAME_SIZE],
+) -> (Option<usize>, Changes) {
+    let Some(frame) = decode(frame) else {
+        return (None, Changes::default());
+    };
+    let broadcast = frame.unit == BROADCAST;
+    if frame.unit != unit && !broadcast {
+        return (None, Changes::default());
+    }
+
+    let result = frame.request.and_then(|request| match request {
+        // Broadcasts can only be writes, as there is no reply
+        Request::ReadHoldingRegisters { .. } | Request::ReadInputRegisters { .. } if broadcast => {
+            Ok((0, Changes::default()))
+        }
+        Request::ReadHoldingRegisters { address, count } => {
+            let values = read(address, count, |a| status.holding_register(a))?;
+            Ok((
+                encode_read_reply(reply, unit, frame.function, values),
+                Changes::default(),
+            ))
+        }
+        Request::ReadInputRegisters { address, count } => {
+            let values = read(address, count, |a| status.input_register(a))?;
+            Ok((
+                encode_read_reply(reply, unit, frame.function, values),
+                Changes::default(),
+            ))
+        }
+        Request::WriteSingleRegister { address, value } => {
+            let changes = write(address, [value].into_iter(), status.config)?;
+            Ok((
+                encode_write_reply(reply, unit, frame.function, address, value),
+                changes,
+            ))
+        }
+        Request::WriteMultipleRegisters { address, values } => {
+            let changes = write(address, values.iter(), status.config)?;
+            Ok((
+                encode_write_reply(reply, unit, frame.function, address, values.len() as u16),
+                changes,
+            ))
+        }
+    });
+
+    match result {
+        _ if broadcast => (None, result.map(|(_, c)| c).unwrap_or_default()),
+        Ok((len, changes)) => (Some(len), changes),
+        Err(exception) => (
+            Some(encode_exception(reply, unit, frame.function, exception)),
+            Changes::default(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::Config, fan::FanStatus, run_logic::ControlCommand, run_logic::State,
+        runtime::RuntimeMeter, temperature::TemperatureReadings,
+    };
+    use frame::crc;
+
+    fn frame(data: &[u8]) -> std::vec::Vec<u8> {
+        let mut frame = data.to_vec();
+        frame.extend_from_slice(&crc(data).to_le_bytes());
+        frame
+    }
+
+    fn handle_idle(request: &[u8]) -> (Option<std::vec::Vec<u8>>, Changes) {
+        let config = Config::DEFAULT;
+        let status = Status {
+            state: &State::default(),
+            fan: &FanStatus::Idle,
+            meter: &RuntimeMeter::new(),
+            temperatures: &TemperatureReadings::default(),
+            config: &config,
+        };
+        let mut reply = [0; MAX_FRAME_SIZE];
+        let (len, changes) = handle(5, &frame(request), &status, &mut reply);
+        (len.map(|len| reply[..len].to_vec()), changes)
+    }
+
+    #[test]
+    fn reads() {
+        assert_eq!(
+            handle_idle(&[5, 0x03, 0, 100, 0, 2]),
+            (
+                Some(frame(&[5, 0x03, 4, 0, 20, 0, 240])),
+                Changes::default()
+            )
+        );
+        assert_eq!(
+            handle_idle(&[5, 0x04, 0, 5, 0, 1]),
+            (Some(frame(&[5, 0x04, 2, 0, 100])), Changes::default())
+        );
+    }
+
+    #[test]
+    fn writes() {
+        let (reply, changes) = handle_idle(&[5, 0x06, 0, 0, 0, 30]);
+        assert_eq!(reply, Some(frame(&[5, 0x06, 0, 0, 0, 30])));
+        assert!(matches!(
+            changes.command,
+            Some(ControlCommand::Start { speed: None, .. })
+        ));
+
+        let (reply, changes) = handle_idle(&[5, 0x10, 0, 108, 0, 2, 4, 0x02, 0xbc, 0x01, 0x90]);
+        assert_eq!(reply, Some(frame(&[5, 0x10, 0, 108, 0, 2])));
+        let config = changes.config.unwrap();
+        assert_eq!(config.interlock_limit, 70.0);
+        assert_eq!(config.filter_service_interval.as_secs(), 400 * 60 * 60);
+    }
+
+    #[test]
+    fn exceptions() {
+        assert_eq!(
+            handle_idle(&[5, 0x04, 0, 50, 0, 10]),
+            (Some(frame(&[5, 0x84, 0x02])), Changes::default())
+        );
+        assert_eq!(
+            handle_idle(&[5, 0x06, 0, 1, 0, 9]),
+            (Some(frame(&[5, 0x86, 0x03])), Changes::default())
+        );
+        assert_eq!(
+            handle_idle(&[5, 0x01, 0, 0, 0, 1]),
+            (Some(frame(&[5, 0x81, 0x01])), Changes::default())
+        );
+    }
+
+    #[test]
+    fn other_units_and_broadcasts() {
+        assert_eq!(
+            handle_idle(&[6, 0x03, 0, 0, 0, 1]),
+            (None, Changes::default())
+        );
+        assert_eq!(
+            handle_idle(&[0, 0x03, 0, 0, 0, 1]),
+            (None, Changes::default())
+        );
+        assert_eq!(
+            handle_idle(&[0, 0x06, 0, 0, 0, 0]),
+            (
+                None,
+                Changes {
+                    command: Some(ControlCommand::Stop),
+                    config: None
+                }
+            )
+        );
+        assert_eq!(
+            handle_idle(&[0, 0x06, 0, 1, 0, 9]),
+            (None, Changes::default())
+        );
+    }
+}
@@ -0,0 +1,518 @@
+//! The Modbus register map.
+//!
+//! Input registers (read only, function 4):
+//!
+//! | Register | Value |
+//! | --- | --- |
+//! | 0 | what the fan has been told to do: 0 off, 1 low, 2 mid, 3 high |
+//! | 1 | the speed the contactors have actually been switched to, 0 while off or switching |
+//! | 2 | why the fan is running: 0 not running, 1 button, 2 remote, 3 temperature, 4 schedule, 5 purge, 6 overtemperature |
+//! | 3 | run time remaining in seconds (at most 65535), 0 when not running for a set time |
+//! | 4 | fault bits: 0 interlock tripped, 1 contactor fault, 2 filters due, 3 filters overdue, 4 interlock sensor lost, 5 clock not set |
+//! | 5 | filter life remaining (%) |
+//! | 6 | run hours since the filters were changed |
+//! | 7 | total run hours |
+//! | 8 | number of temperature sensors |
+//! | 16-23 | temperature of each sensor in 0.1 °C (signed), 0x8000 when not read recently |
+//! | 24-55 | address of each sensor, four registers each, most significant first |
+//!
+//! Holding registers (functions 3, 6 and 16):
+//!
+//! | Register | Value |
+//! | --- | --- |
+//! | 0 | write to run the fan for that many minutes, 0 to stop it; reads the minutes remaining |
+//! | 1 | write to change the speed while running: 1 low, 2 mid, 3 high; reads 0 when stopped |
+//! | 100-109 | configuration, see [`CONFIG_KEYS`] |
+//!
+//! Writing registers 0 and 1 together starts the fan at that speed.
+//! Speeds in the configuration use the same numbers, temperatures are in 0.1 °C.
+//! Registers 9-15 read as zero, anything else that is not listed does not exist.
+
+use super::frame::Exception;
+use crate::{
+    config::{Config, ConfigValue},
+    fan::{FanCommand, FanSpeed, FanStatus},
+    run_logic::{ControlCommand, Reason, State},
+    runtime::{FilterStatus, RuntimeMeter},
+    temperature::{TemperatureReadings, MAX_SENSORS},
+};
+use embassy_time::Duration;
+
+/// Reported for a temperature that has not been read recently.
+pub const NO_TEMPERATURE: u16 = 0x8000;
+
+const FAN_COMMAND: u16 = 0;
+const APPLIED_SPEED: u16 = 1;
+const REASON: u16 = 2;
+const TIME_REMAINING: u16 = 3;
+const FAULTS: u16 = 4;
+const FILTER_LIFE: u16 = 5;
+const FILTER_HOURS: u16 = 6;
+const TOTAL_HOURS: u16 = 7;
+const SENSOR_COUNT: u16 = 8;
+const TEMPERATURES: u16 = 16;
+const SENSOR_ADDRESSES: u16 = TEMPERATURES + MAX_SENSORS as u16;
+const INPUT_COUNT: u16 = SENSOR_ADDRESSES + 4 * MAX_SENSORS as u16;
+
+const RUN_MINUTES: u16 = 0;
+const SPEED: u16 = 1;
+const CONFIG_START: u16 = 100;
+
+/// The configuration values in holding registers from 100, in order.
+pub const CONFIG_KEYS: [&str; 10] = [
+    "run_minutes",
+    "max_run_minutes",
+    "purge_minutes",
+    "continuous_max_hours",
+    "start_speed",
+    "temperature_on_c",
+    "temperature_off_c",
+    "temperature_speed",
+    "interlock_limit_c",
+    "filter_service_hours",
+];
+
+// Bits of the fault register
+pub const FAULT_INTERLOCK: u16 = 1 << 0;
+pub const FAULT_CONTACTOR: u16 = 1 << 1;
+pub const FAULT_FILTER_DUE: u16 = 1 << 2;
+pub const FAULT_FILTER_OVERDUE: u16 = 1 << 3;
+pub const FAULT_INTERLOCK_SENSOR: u16 = 1 << 4;
+pub const FAULT_CLOCK_NOT_SET: u16 = 1 << 5;
+
+fn speed_value(speed: &FanSpeed) -> u16 {
+    match speed {
+        FanSpeed::Low => 1,
+        FanSpeed::Medium => 2,
+        FanSpeed::High => 3,
+    }
+}
+
+fn speed_from_value(value: u16) -> Result<FanSpeed, Exception> {
+    match value {
+        1 => Ok(FanSpeed::Low),
+        2 => Ok(FanSpeed::Medium),
+        3 => Ok(FanSpeed::High),
+        _ => Err(Exception::IllegalDataValue),
+    }
+}
+
+fn reason_value(reason: Option<Reason>) -> u16 {
+    match reason {
+        None => 0,
+        Some(Reason::Button) => 1,
+        Some(Reason::Remote) => 2,
+        Some(Reason::Temperature) => 3,
+        Some(Reason::Schedule) => 4,
+        Some(Reason::Purge) => 5,
+        Some(Reason::Overtemperature) => 6,
+    }
+}
+
+/// A temperature in 0.1 °C, as a signed value.
+fn tenths(temperature: f32) -> u16 {
+    let tenths = temperature * 10.0;
+    // Rounded to the nearest, `as` truncates towards zero
+    (if tenths < 0.0 {
+        tenths - 0.5
+    } else {
+        tenths + 0.5
+    }) as i16 as u16
+}
+
+fn hours(duration: Duration) -> u16 {
+    (duration.as_secs() / (60 * 60)).min(u16::MAX as u64) as u16
+}
+
+fn config_key(address: u16) -> Result<&'static str, Exception> {
+    address
+        .checked_sub(CONFIG_START)
+        .and_then(|i| CONFIG_KEYS.get(i as usize))
+        .copied()
+        .ok_or(Exception::IllegalDataAddress)
+}
+
+/// Everything the registers are read from.
+#[derive(Debug, Clone, Copy)]
+pub struct Status<'a> {
+    pub state: &'a State,
+    pub fan: &'a FanStatus,
+    pub meter: &'a RuntimeMeter,
+    pub temperatures: &'a TemperatureReadings,
+    pub config: &'a Config,
+}
+
+impl Status<'_> {
+    /// The value of an input register.
+    pub fn input_register(&self, address: u16) -> Result<u16, Exception> {
+        let sensor = |first: u16, per_sensor: u16| {
+            self.temperatures
+                .iter()
+                .nth(((address - first) / per_sensor) as usize)
+        };
+
+        Ok(match address {
+            FAN_COMMAND => match self.state.fan_command() {
+                FanCommand::Stop => 0,
+                FanCommand::Run(speed) => speed_value(&speed),
+            },
+            APPLIED_SPEED => self.fan.running_speed().map_or(0, speed_value),
+            REASON => reason_value(self.state.reason()),
+            TIME_REMAINING => self
+                .state
+                .time_remaining()
+                .map_or(0, |t| t.as_secs().min(u16::MAX as u64) as u16),
+            FAULTS => {
+                let mut faults = 0;
+                if let Some(fault) = self.state.fault() {
+                    faults |= FAULT_INTERLOCK;
+                    if fault.sensor_lost {
+                        faults |= FAULT_INTERLOCK_SENSOR;
+                    }
+                }
+                if matches!(self.fan, FanStatus::Fault(_)) {
+                    faults |= FAULT_CONTACTOR;
+                }
+                if self.state.clock_not_set() {
+                    faults |= FAULT_CLOCK_NOT_SET;
+                }
+                match self
+                    .meter
+                    .filter_status(self.config.filter_service_interval)
+                {
+                    FilterStatus::Ok => {}
+                    FilterStatus::DueSoon => faults |= FAULT_FILTER_DUE,
+                    FilterStatus::Overdue => faults |= FAULT_FILTER_DUE | FAULT_FILTER_OVERDUE,
+                }
+                faults
+            }
+            FILTER_LIFE => self
+                .meter
+                .filter_life_remaining_percent(self.config.filter_service_interval)
+                .into(),
+            FILTER_HOURS => hours(self.meter.since_filter_change.total()),
+            TOTAL_HOURS => hours(self.meter.lifetime.total()),
+            SENSOR_COUNT => self.temperatures.iter().count() as u16,
+            TEMPERATURES..SENSOR_ADDRESSES => sensor(TEMPERATURES, 1)
+                .and_then(|s| s.fresh_temperature())
+                .map_or(NO_TEMPERATURE, tenths),
+            SENSOR_ADDRESSES..INPUT_COUNT => sensor(SENSOR_ADDRESSES, 4).map_or(0, |s| {
+                let word = (address - SENSOR_ADDRESSES) % 4;
+                (s.address >> (16 * (3 - word))) as u16
+            }),
+            // Reserved
+            0..INPUT_COUNT => 0,
+            _ => return Err(Exception::IllegalDataAddress),
+        })
+    }
+
+    /// The value of a holding register.
+    pub fn holding_register(&self, address: u16) -> Result<u16, Exception> {
+        Ok(match address {
+            RUN_MINUTES => self.state.time_remaining().map_or(0, |t| {
+                (t.as_secs().div_ceil(60)).min(u16::MAX as u64) as u16
+            }),
+            SPEED => match self.state.fan_command() {
+                FanCommand::Stop => 0,
+                FanCommand::Run(speed) => speed_value(&speed),
+            },
+            _ => match self.config.get_value(config_key(address)?) {
+                Ok(ConfigValue::Number(v)) => v.min(u16::MAX as u64) as u16,
+                Ok(ConfigValue::Temperature(t)) => tenths(t),
+                Ok(ConfigValue::Speed(speed)) => speed_value(&speed),
+                _ => return Err(Exception::IllegalDataAddress),
+            },
+        })
+    }
+}
+
+/// What a write to the holding registers asks for.
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Changes {
+    pub command: Option<ControlCommand>,
+    /// The new configuration, if anything in it has changed
+    pub config: Option<Config>,
+}
+
+/// Works out what writing `values` to the holding registers from `address` asks for.
+///
+/// Either every value can be written or the write is refused, in which case nothing changes.
+pub fn write(
+    address: u16,
+    values: impl Iterator<Item = u16>,
+    config: &Config,
+) -> Result<Changes, Exception> {
+    let mut run_minutes = None;
+    let mut speed = None;
+    let mut new_config = config.clone();
+
+    let mut next = Some(address);
+    for value in values {
+        let address = next.ok_or(Exception::IllegalDataAddress)?;
+        match address {
+            RUN_MINUTES => run_minutes = Some(value),
+            SPEED => speed = Some(speed_from_value(value)?),
+            _ => {
+                let key = config_key(address)?;
+                // The type of the existing value says how to interpret the new one
+                let value = match new_config.get_value(key) {
+                    Ok(ConfigValue::Number(_)) => ConfigValue::Number(value.into()),
+                    Ok(ConfigValue::Temperature(_)) => {
+                        ConfigValue::Temperature(value as i16 as f32 / 10.0)
+                    }
+                    Ok(ConfigValue::Speed(_)) => ConfigValue::Speed(speed_from_value(value)?),
+                    _ => return Err(Exception::IllegalDataAddress),
+                };
+                new_config
+                    .set(key, value)
+                    .map_err(|_| Exception::IllegalDataValue)?;
+            }
+        }
+        next = address.checked_add(1);
+    }
+
+    if !new_config.is_valid() {
+        return Err(Exception::IllegalDataValue);
+    }
+
+    let command = match (run_minutes, speed) {
+        (Some(0), _) => Some(ControlCommand::Stop),
+        (Some(minutes), speed) => Some(ControlCommand::Start {
+            duration: Some(Duration::from_secs(minutes as u64 * 60)),
+            speed,
+        }),
+        (None, Some(speed)) => Some(ControlCommand::SetSpeed(speed)),
+        (None, None) => None,
+    };
+
+    Ok(Changes {
+        command,
+        config: (new_config != *config).then_some(new_config),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{contactors::ContactorFault, run_logic::Triggers};
+    use embassy_time::Instant;
+
+    fn read_inputs(status: &Status, addresses: core::ops::Range<u16>) -> std::vec::Vec<u16> {
+        addresses
+            .map(|a| status.input_register(a).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn input_registers() {
+        let config = Config::DEFAULT;
+        let mut triggers = Triggers::default();
+        triggers.button.handle_command(
+            ControlCommand::Start {
+                duration: Some(Duration::from_secs(600)),
+                speed: Some(FanSpeed::High),
+            },
+            &config,
+        );
+        let state = triggers.resolve();
+
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::High, Duration::from_secs(460 * 60 * 60));
+        meter.filter_changed();
+        meter.add(&FanSpeed::High, Duration::from_secs(460 * 60 * 60));
+
+        let mut temperatures = TemperatureReadings::default();
+        temperatures.record(0x28ff_0102_0304_0506, -5.06, Instant::from_secs(1));
+        temperatures.record_error(0x28ff_0000_0000_0001);
+
+        let status = Status {
+            state: &state,
+            fan: &FanStatus::Running(FanSpeed::Medium),
+            meter: &meter,
+            temperatures: &temperatures,
+            config: &config,
+        };
+
+        assert_eq!(
+            read_inputs(&status, 0..10),
+            [3, 2, 2, 600, FAULT_FILTER_DUE, 8, 460, 920, 2, 0]
+        );
+        assert_eq!(
+            read_inputs(&status, 16..19),
+            [-51_i16 as u16, NO_TEMPERATURE, NO_TEMPERATURE]
+        );
+        assert_eq!(
+            read_inputs(&status, 24..32),
+            [0x28ff, 0x0102, 0x0304, 0x0506, 0x28ff, 0, 0, 1]
+        );
+        assert_eq!(read_inputs(&status, 32..56), [0; 24]);
+        assert_eq!(
+            status.input_register(56),
+            Err(Exception::IllegalDataAddress)
+        );
+    }
+
+    #[test]
+    fn faults() {
+        let config = Config::DEFAULT;
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::High, config.filter_service_interval);
+
+        let status = Status {
+            state: &State::default(),
+            fan: &FanStatus::Fault(ContactorFault::DidNotClose(FanSpeed::Low)),
+            meter: &meter,
+            temperatures: &TemperatureReadings::default(),
+            config: &config,
+        };
+        assert_eq!(
+            status.input_register(FAULTS),
+            Ok(FAULT_CONTACTOR | FAULT_FILTER_DUE | FAULT_FILTER_OVERDUE)
+        );
+        assert_eq!(status.input_register(FAN_COMMAND), Ok(0));
+        assert_eq!(status.input_register(REASON), Ok(0));
+    }
+
+    #[test]
+    fn lost_interlock_sensor() {
+        let mut config = Config::DEFAULT;
+        config.interlock_sensor = 1;
+        let mut triggers = Triggers::default();
+        for _ in 0..3 {
+            triggers.handle_readings(&TemperatureReadings::default(), &config);
+        }
+
+        let status = Status {
+            state: &triggers.resolve(),
+            fan: &FanStatus::Idle,
+            meter: &RuntimeMeter::new(),
+            temperatures: &TemperatureReadings::default(),
+            config: &config,
+        };
+        assert_eq!(
+            status.input_register(FAULTS),
+            Ok(FAULT_INTERLOCK | FAULT_INTERLOCK_SENSOR)
+        );
+    }
+
+    #[test]
+    fn clock_not_set() {
+        let mut schedule = crate::schedule::Schedule::default();
+        schedule
+            .add("daily 08:00-09:00 low".parse().unwrap())
+            .unwrap();
+        let mut triggers = Triggers::default();
+        triggers.schedule.handle_time(None, &schedule);
+
+        let status = Status {
+            state: &triggers.resolve(),
+            fan: &FanStatus::Idle,
+            meter: &RuntimeMeter::new(),
+            temperatures: &TemperatureReadings::default(),
+            config: &Config::DEFAULT,
+        };
+        assert_eq!(status.input_register(FAULTS), Ok(FAULT_CLOCK_NOT_SET));
+    }
+
+    #[test]
+    fn holding_registers() {
+        let mut config = Config::DEFAULT;
+        config.temperature_off_threshold = -2.5;
+
+        let status = Status {
+            state: &State::default(),
+            fan: &FanStatus::Idle,
+            meter: &RuntimeMeter::new(),
+            temperatures: &TemperatureReadings::default(),
+            config: &config,
+        };
+
+        assert_eq!(status.holding_register(RUN_MINUTES), Ok(0));
+        assert_eq!(status.holding_register(SPEED), Ok(0));
+        let values: std::vec::Vec<_> = (100..110)
+            .map(|a| status.holding_register(a).unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            [20, 240, 0, 12, 1, 300, -25_i16 as u16, 2, 700, 500]
+        );
+
+        for address in [2, 99, 110] {
+            assert_eq!(
+                status.holding_register(address),
+                Err(Exception::IllegalDataAddress)
+            );
+        }
+    }
+
+    #[test]
+    fn write_control() {
+        let config = Config::DEFAULT;
+
+        assert_eq!(
+            write(RUN_MINUTES, [0].into_iter(), &config),
+            Ok(Changes {
+                command: Some(ControlCommand::Stop),
+                config: None
+            })
+        );
+        assert_eq!(
+            write(RUN_MINUTES, [15, 3].into_iter(), &config),
+            Ok(Changes {
+                command: Some(ControlCommand::Start {
+                    duration: Some(Duration::from_secs(15 * 60)),
+                    speed: Some(FanSpeed::High)
+                }),
+                config: None
+            })
+        );
+        assert_eq!(
+            write(SPEED, [1].into_iter(), &config),
+            Ok(Changes {
+                command: Some(ControlCommand::SetSpeed(FanSpeed::Low)),
+                config: None
+            })
+        );
+        assert_eq!(
+            write(SPEED, [4].into_iter(), &config),
+            Err(Exception::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn write_config() {
+        let config = Config::DEFAULT;
+
+        let changes = write(104, [3, 350].into_iter(), &config).unwrap();
+        let new_config = changes.config.unwrap();
+        assert_eq!(new_config.start_speed, FanSpeed::High);
+        assert_eq!(new_config.temperature_on_threshold, 35.0);
+        assert_eq!(changes.command, None);
+
+        // Writing what is already there changes nothing
+        assert_eq!(
+            write(100, [20].into_iter(), &config),
+            Ok(Changes::default())
+        );
+
+        // The off temperature would be above the on temperature
+        assert_eq!(
+            write(106, [310].into_iter(), &config),
+            Err(Exception::IllegalDataValue)
+        );
+        // Running into registers that do not exist
+        assert_eq!(
+            write(109, [500, 0].into_iter(), &config),
+            Err(Exception::IllegalDataAddress)
+        );
+        assert_eq!(
+            write(1, [1, 20].into_iter(), &config),
+            Err(Exception::IllegalDataAddress)
+        );
+        assert_eq!(
+            write(u16::MAX, [0, 0].into_iter(), &config),
+            Err(Exception::IllegalDataAddress)
+        );
+    }
+}
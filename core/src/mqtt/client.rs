@@ -0,0 +1,305 @@
+use super::packet::{
+    decode, encode_connect, encode_publish_header, encode_subscribe, packet_length, Connect,
+    Packet, PacketError, Publish, DISCONNECT_REQUEST, PING_REQUEST,
+};
+use embedded_io_async::{Read, Write};
+
+/// The largest packet that can be received, anything larger is thrown away.
+pub const RX_BUFFER_SIZE: usize = 256;
+
+/// The largest packet that can be sent, not counting the payload of a PUBLISH.
+const TX_BUFFER_SIZE: usize = 256;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Io(E),
+    Packet(PacketError),
+    /// The broker refused the connection, with the return code from its CONNACK
+    Refused(u8),
+    /// The broker did not reply to CONNECT with a CONNACK
+    UnexpectedReply,
+    /// The broker closed the connection
+    Closed,
+}
+
+impl<E> From<PacketError> for Error<E> {
+    fn from(e: PacketError) -> Self {
+        Self::Packet(e)
+    }
+}
+
+/// An MQTT client that publishes and subscribes at QoS 0 over any byte stream (a TCP socket on
+/// the device, or a `std` TCP stream on the host).
+///
+/// Nothing is sent unless asked for, including keep alive pings (see [`Client::ping`]).
+pub struct Client<T> {
+    io: T,
+    tx: [u8; TX_BUFFER_SIZE],
+    rx: [u8; RX_BUFFER_SIZE],
+    /// The number of bytes received into `rx`
+    rx_len: usize,
+    /// The length of the packet at the start of `rx` that was last returned
+    consumed: usize,
+    /// How much more of a packet that is too large to receive is still to be thrown away
+    skip: usize,
+    next_packet_id: u16,
+}
+
+impl<T: Read + Write> Client<T> {
+    pub fn new(io: T) -> Self {
+        Self {
+            io,
+            tx: [0; TX_BUFFER_SIZE],
+            rx: [0; RX_BUFFER_SIZE],
+            rx_len: 0,
+            consumed: 0,
+            skip: 0,
+            next_packet_id: 1,
+        }
+    }
+
+    /// Connects to the broker, waiting for it to accept the connection.
+    pub async fn connect(&mut self, connect: &Connect<'_>) -> Result<(), Error<T::Error>> {
+        let len = encode_connect(&mut self.tx, connect)?;
+        self.send(len).await?;
+
+        self.next_packet().await?;
+        match decode(&self.rx[..self.rx_len])? {
+            Some((Packet::ConnAck { return_code: 0, .. }, _)) => Ok(()),
+            Some((Packet::ConnAck { return_code, .. }, _)) => Err(Error::Refused(return_code)),
+            _ => Err(Error::UnexpectedReply),
+        }
+    }
+
+    /// Subscribes to a topic filter.
+    ///
+    /// The broker's acknowledgement is not waited for, it is skipped over by [`Client::receive`].
+    pub async fn subscribe(&mut self, filter: &str) -> Result<(), Error<T::Error>> {
+        let packet_id = self.next_packet_id;
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+
+        let len = encode_subscribe(&mut self.tx, packet_id, filter)?;
+        self.send(len).await
+    }
+
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> Result<(), Error<T::Error>> {
+        let len = encode_publish_header(&mut self.tx, topic, payload.len(), retain)?;
+        self.io
+            .write_all(&self.tx[..len])
+            .await
+            .map_err(Error::Io)?;
+        self.io.write_all(payload).await.map_err(Error::Io)?;
+        self.io.flush().await.map_err(Error::Io)
+    }
+
+    /// Lets the broker know that the client is still there, this must be called more often than
+    /// the keep alive interval given when connecting.
+    pub async fn ping(&mut self) -> Result<(), Error<T::Error>> {
+        self.tx[..PING_REQUEST.len()].copy_from_slice(&PING_REQUEST);
+        self.send(PING_REQUEST.len()).await
+    }
+
+    /// Disconnects cleanly, so that the broker does not publish the will.
+    pub async fn disconnect(&mut self) -> Result<(), Error<T::Error>> {
+        self.tx[..DISCONNECT_REQUEST.len()].copy_from_slice(&DISCONNECT_REQUEST);
+        self.send(DISCONNECT_REQUEST.len()).await
+    }
+
+    /// Waits for the next message published to a topic that has been subscribed to.
+    ///
+    /// This can be safely cancelled (e.g. in a `select`), nothing that has been received is lost.
+    pub async fn receive(&mut self) -> Result<Publish<'_>, Error<T::Error>> {
+        loop {
+            self.next_packet().await?;
+            if let Some((Packet::Publish(_), _)) = decode(&self.rx[..self.rx_len])? {
+                break;
+            }
+        }
+
+        match decode(&self.rx[..self.rx_len])? {
+            Some((Packet::Publish(publish), _)) => Ok(publish),
+            _ => unreachable!(),
+        }
+    }
+
+    async fn send(&mut self, len: usize) -> Result<(), Error<T::Error>> {
+        self.io
+            .write_all(&self.tx[..len])
+            .await
+            .map_err(Error::Io)?;
+        self.io.flush().await.map_err(Error::Io)
+    }
+
+    /// Throws away the first `len` bytes that have been received.
+    fn discard(&mut self, len: usize) {
+        self.rx.copy_within(len..self.rx_len, 0);
+        self.rx_len -= len;
+    }
+
+    /// Waits until there is a complete packet at the start of `rx`.
+    async fn next_packet(&mut self) -> Result<(), Error<T::Error>> {
+        self.discard(self.consumed);
+        self.consumed = 0;
+
+        loop {
+            if self.skip > 0 {
+                let len = self.skip.min(self.rx_len);
+                self.discard(len);
+                self.skip -= len;
+            }
+
+            if self.skip == 0 {
+                match packet_length(&self.rx[..self.rx_len])? {
+                    Some(len) if len <= self.rx_len => {
+                        self.consumed = len;
+                        return Ok(());
+                    }
+                    Some(len) if len > RX_BUFFER_SIZE => {
+                        self.skip = len;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let n = self
+                .io
+                .read(&mut self.rx[self.rx_len..])
+                .await
+                .map_err(Error::Io)?;
+            if n == 0 {
+                return Err(Error::Closed);
+            }
+            self.rx_len += n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_io_async::{ErrorKind, ErrorType};
+
+    /// Plays back what the broker sends in chunks, recording what the client sends.
+    struct FakeBroker<'a> {
+        rx: &'a [&'a [u8]],
+        tx: std::vec::Vec<u8>,
+    }
+
+    impl ErrorType for FakeBroker<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl Read for FakeBroker<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let Some((chunk, rest)) = self.rx.split_first() else {
+                return Ok(0);
+            };
+            let len = chunk.len().min(buf.len());
+            buf[..len].copy_from_slice(&chunk[..len]);
+            assert_eq!(len, chunk.len(), "test chunks must fit in the buffer");
+            self.rx = rest;
+            Ok(len)
+        }
+    }
+
+    impl Write for FakeBroker<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.tx.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn connect() -> Connect<'static> {
+        Connect {
+            client_id: "c",
+            keep_alive_secs: 0,
+            will: None,
+            username: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn connect_and_receive() {
+        let broker = FakeBroker {
+            // A CONNACK, SUBACK and two messages, split up in awkward places
+            rx: &[
+                b"\x20",
+                b"\x02\x00\x00\x90\x03\x00\x01\x00\x30\x05\x00\x01a",
+                b"on\x30",
+                b"\x07\x00\x01bstop",
+            ],
+            tx: std::vec::Vec::new(),
+        };
+        let mut client = Client::new(broker);
+
+        block_on(async {
+            client.connect(&connect()).await.unwrap();
+            client.subscribe("#").await.unwrap();
+
+            let message = client.receive().await.unwrap();
+            assert_eq!((message.topic, message.payload), ("a", &b"on"[..]));
+            let message = client.receive().await.unwrap();
+            assert_eq!((message.topic, message.payload), ("b", &b"stop"[..]));
+
+            assert_eq!(client.receive().await, Err(Error::Closed));
+        });
+
+        assert_eq!(
+            &client.io.tx[..],
+            b"\x10\x0d\x00\x04MQTT\x04\x02\x00\x00\x00\x01c\x82\x06\x00\x01\x00\x01#\x00"
+        );
+    }
+
+    #[test]
+    fn refused() {
+        let broker = FakeBroker {
+            rx: &[b"\x20\x02\x00\x05"],
+            tx: std::vec::Vec::new(),
+        };
+        let mut client = Client::new(broker);
+        assert_eq!(block_on(client.connect(&connect())), Err(Error::Refused(5)));
+    }
+
+    #[test]
+    fn large_messages_are_skipped() {
+        let mut large = std::vec::Vec::from(&b"\x30\xa0\x02\x00\x01a"[..]);
+        large.resize(large.len() + 0x120 - 3, b'x');
+        let (start, end) = large.split_at(200);
+
+        let broker = FakeBroker {
+            rx: &[start, end, b"\x30\x04\x00\x01bc"],
+            tx: std::vec::Vec::new(),
+        };
+        let mut client = Client::new(broker);
+
+        block_on(async {
+            let message = client.receive().await.unwrap();
+            assert_eq!((message.topic, message.payload), ("b", &b"c"[..]));
+        });
+    }
+
+    #[test]
+    fn publish() {
+        let broker = FakeBroker {
+            rx: &[],
+            tx: std::vec::Vec::new(),
+        };
+        let mut client = Client::new(broker);
+
+        block_on(async {
+            client.publish("t", b"42", true).await.unwrap();
+            client.ping().await.unwrap();
+        });
+
+        assert_eq!(&client.io.tx[..], b"\x31\x05\x00\x01t42\xc0\x00");
+    }
+}
@@ -0,0 +1,279 @@
+//! Home Assistant MQTT discovery, so that the air filter shows up in Home Assistant without any
+//! configuration there.
+//!
+//! A retained config is published for each entity, below [`DISCOVERY_PREFIX`], which points
+//! Home Assistant at the topics the telemetry is published to (see [`super::telemetry`]).
+//! Every entity is shown as unavailable while the air filter is not connected.
+
+use super::telemetry::{Topics, AVAILABILITY, OFFLINE, ONLINE};
+use crate::fan::FanSpeed;
+use core::fmt::{self, Write};
+
+/// Where Home Assistant looks for configs (its default).
+pub const DISCOVERY_PREFIX: &str = "homeassistant";
+
+pub type ConfigTopic = heapless::String<96>;
+pub type ConfigPayload = heapless::String<1024>;
+
+/// Something the air filter exposes to Home Assistant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Entity {
+    /// The fan, which can be started and stopped, with each speed as a preset mode
+    Fan,
+    TimeRemaining,
+    FilterLife,
+    /// On when the filters are due for changing
+    FilterDue,
+    /// On when the interlock has tripped or a contactor has failed
+    Fault,
+    /// A DS18B20 temperature sensor, by address
+    Temperature(u64),
+}
+
+impl Entity {
+    /// Every entity other than the temperature sensors, which depend on what is connected.
+    pub const FIXED: [Entity; 5] = [
+        Entity::Fan,
+        Entity::TimeRemaining,
+        Entity::FilterLife,
+        Entity::FilterDue,
+        Entity::Fault,
+    ];
+
+    fn component(&self) -> &'static str {
+        match self {
+            Self::Fan => "fan",
+            Self::TimeRemaining | Self::FilterLife | Self::Temperature(_) => "sensor",
+            Self::FilterDue | Self::Fault => "binary_sensor",
+        }
+    }
+
+    fn object_id(&self) -> heapless::String<32> {
+        let mut id = heapless::String::new();
+        let _ = match self {
+            Self::Fan => id.write_str("fan"),
+            Self::TimeRemaining => id.write_str("remaining"),
+            Self::FilterLife => id.write_str("filter_life"),
+            Self::FilterDue => id.write_str("filter_due"),
+            Self::Fault => id.write_str("fault"),
+            Self::Temperature(address) => write!(id, "temperature_{address:016x}"),
+        };
+        id
+    }
+}
+
+// Templates are evaluated by Home Assistant to translate between its states and the telemetry
+
+const FAN: &str = concat!(
+    r#""name":null,"#,
+    r#""state_topic":"~/state","#,
+    r#""state_value_template":"{{ 'ON' if value == 'running' else 'OFF' }}","#,
+    r#""command_topic":"~/command","#,
+    r#""command_template":"{{ 'start' if value == 'ON' else 'stop' }}","#,
+    r#""preset_mode_state_topic":"~/speed","#,
+    r#""preset_mode_value_template":"{{ value if value != 'off' else 'None' }}","#,
+    r#""preset_mode_command_topic":"~/command","#,
+    // Choosing a speed starts the fan if it is not already running
+    r#""preset_mode_command_template":"#,
+    r#""{{ 'speed' if is_state(entity_id, 'on') else 'start' }} {{ value }}","#,
+);
+
+const TIME_REMAINING: &str = concat!(
+    r#""name":"Time remaining","#,
+    r#""state_topic":"~/remaining","#,
+    r#""device_class":"duration","unit_of_measurement":"s","#,
+    r#""value_template":"{{ value or None }}","#,
+);
+
+const FILTER_LIFE: &str = concat!(
+    r#""name":"Filter life","#,
+    r#""state_topic":"~/filter/life","#,
+    r#""unit_of_measurement":"%","state_class":"measurement","icon":"mdi:air-filter","#,
+);
+
+const FILTER_DUE: &str = concat!(
+    r#""name":"Filter change due","#,
+    r#""state_topic":"~/filter/status","#,
+    r#""device_class":"problem","#,
+    r#""value_template":"{{ 'OFF' if value == 'ok' else 'ON' }}","#,
+);
+
+const FAULT: &str = concat!(
+    r#""name":"Fault","#,
+    r#""state_topic":"~/fault","#,
+    r#""device_class":"problem","#,
+    r#""value_template":"{{ 'ON' if value else 'OFF' }}","#,
+);
+
+const TEMPERATURE: &str = concat!(
+    r#""device_class":"temperature","unit_of_measurement":"°C","state_class":"measurement","#,
+    r#""value_template":"{{ value or None }}","#,
+);
+
+/// The discovery configs for a single air filter.
+#[derive(Debug, Clone)]
+pub struct Discovery<'a> {
+    topics: &'a Topics,
+    device_id: &'a str,
+    version: &'a str,
+}
+
+impl<'a> Discovery<'a> {
+    /// `topics` must be for `device_id`, `version` is the firmware version.
+    pub fn new(topics: &'a Topics, device_id: &'a str, version: &'a str) -> Self {
+        Self {
+            topics,
+            device_id,
+            version,
+        }
+    }
+
+    /// The topic the config for an entity is published to.
+    pub fn topic(&self, entity: &Entity) -> ConfigTopic {
+        let mut topic = ConfigTopic::new();
+        let _ = write!(
+            topic,
+            "{DISCOVERY_PREFIX}/{}/ms_air_filter_{}/{}/config",
+            entity.component(),
+            self.device_id,
+            entity.object_id()
+        );
+        topic
+    }
+
+    /// The config for an entity, as JSON.
+    pub fn config(&self, entity: &Entity) -> ConfigPayload {
+        let mut json = ConfigPayload::new();
+        let _ = self.write_config(&mut json, entity);
+        json
+    }
+
+    fn write_config(&self, w: &mut ConfigPayload, entity: &Entity) -> fmt::Result {
+        // "~" is short for the prefix of every topic
+        write!(
+            w,
+            r#"{{"~":"{}","unique_id":"ms_air_filter_{}_{}","#,
+            self.topics.prefix(),
+            self.device_id,
+            entity.object_id()
+        )?;
+
+        match entity {
+            Entity::Fan => {
+                w.write_str(FAN)?;
+                w.write_str(r#""preset_modes":["#)?;
+                for (i, speed) in [FanSpeed::Low, FanSpeed::Medium, FanSpeed::High]
+                    .iter()
+                    .enumerate()
+                {
+                    let separator = if i > 0 { "," } else { "" };
+                    write!(w, r#"{separator}"{}""#, speed.name())?;
+                }
+                w.write_str("],")?;
+            }
+            Entity::TimeRemaining => w.write_str(TIME_REMAINING)?,
+            Entity::FilterLife => w.write_str(FILTER_LIFE)?,
+            Entity::FilterDue => w.write_str(FILTER_DUE)?,
+            Entity::Fault => w.write_str(FAULT)?,
+            Entity::Temperature(address) => {
+                write!(
+                    w,
+                    r#""name":"Temperature {address:016x}","state_topic":"~/temperature/{address:016x}","#
+                )?;
+                w.write_str(TEMPERATURE)?;
+            }
+        }
+
+        write!(
+            w,
+            r#""availability_topic":"~/{AVAILABILITY}","payload_available":"{ONLINE}","payload_not_available":"{OFFLINE}","#
+        )?;
+        write!(
+            w,
+            r#""device":{{"identifiers":["ms_air_filter_{id}"],"name":"Air filter {id}","manufacturer":"Maker Space Newcastle","model":"Air Filter Controller","sw_version":"{version}"}}}}"#,
+            id = self.device_id,
+            version = self.version
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Checks that quotes and brackets are balanced, which is as far as JSON is checked here.
+    fn balanced(json: &str) -> bool {
+        let mut depth = 0_i32;
+        let mut in_string = false;
+        for c in json.chars() {
+            match c {
+                '"' => in_string = !in_string,
+                '{' | '[' if !in_string => depth += 1,
+                '}' | ']' if !in_string => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                return false;
+            }
+        }
+        depth == 0 && !in_string
+    }
+
+    #[test]
+    fn topics() {
+        let topics = Topics::new("a1b2c3");
+        let discovery = Discovery::new(&topics, "a1b2c3", "v1");
+
+        assert_eq!(
+            discovery.topic(&Entity::Fan),
+            "homeassistant/fan/ms_air_filter_a1b2c3/fan/config"
+        );
+        assert_eq!(
+            discovery.topic(&Entity::FilterDue),
+            "homeassistant/binary_sensor/ms_air_filter_a1b2c3/filter_due/config"
+        );
+        assert_eq!(
+            discovery.topic(&Entity::Temperature(0x28ff_0000_0000_0001)),
+            "homeassistant/sensor/ms_air_filter_a1b2c3/temperature_28ff000000000001/config"
+        );
+    }
+
+    #[test]
+    fn config() {
+        let topics = Topics::new("a1b2c3");
+        let discovery = Discovery::new(&topics, "a1b2c3", "v1.2.3");
+
+        assert_eq!(
+            discovery.config(&Entity::Fault),
+            concat!(
+                r#"{"~":"ms-air-filter/a1b2c3","unique_id":"ms_air_filter_a1b2c3_fault","#,
+                r#""name":"Fault","state_topic":"~/fault","device_class":"problem","#,
+                r#""value_template":"{{ 'ON' if value else 'OFF' }}","#,
+                r#""availability_topic":"~/availability","#,
+                r#""payload_available":"online","payload_not_available":"offline","#,
+                r#""device":{"identifiers":["ms_air_filter_a1b2c3"],"name":"Air filter a1b2c3","#,
+                r#""manufacturer":"Maker Space Newcastle","model":"Air Filter Controller","#,
+                r#""sw_version":"v1.2.3"}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn every_config_fits() {
+        let topics = Topics::new("a1b2c3");
+        let discovery = Discovery::new(&topics, "a1b2c3", "v1.2.3-45-gabcdef0-dirty");
+
+        for entity in Entity::FIXED
+            .iter()
+            .chain([Entity::Temperature(u64::MAX)].iter())
+        {
+            let config = discovery.config(entity);
+            assert!(config.ends_with("}}"), "{entity:?} truncated");
+            assert!(balanced(&config), "{entity:?} is not valid: {config}");
+        }
+
+        let fan = discovery.config(&Entity::Fan);
+        assert!(fan.contains(r#""preset_modes":["low","mid","high"],"#));
+    }
+}
@@ -0,0 +1,13 @@
+//! A minimal MQTT client, and what the air filter publishes with it.
+//!
+//! The client works over any [`embedded_io_async`] byte stream, so the same code runs over a
+//! TCP socket on the device and over a `std` TCP stream on the host (see
+//! `examples/mqtt.rs`, which can be run against a local broker).
+
+mod client;
+pub mod discovery;
+pub mod packet;
+pub mod telemetry;
+
+pub use client::{Client, Error, RX_BUFFER_SIZE};
+pub use packet::{Connect, Publish, Will};
@@ -0,0 +1,459 @@
+//! Encoding and decoding of MQTT 3.1.1 packets.
+//!
+//! Only what is needed by a client that publishes and subscribes at QoS 0 is supported.
+
+/// The largest remaining length that can be encoded in a fixed header.
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// A complete PINGREQ packet.
+pub const PING_REQUEST: [u8; 2] = [PINGREQ << 4, 0];
+
+/// A complete DISCONNECT packet.
+pub const DISCONNECT_REQUEST: [u8; 2] = [DISCONNECT << 4, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PacketError {
+    /// The packet does not fit in the buffer it is being encoded into
+    BufferTooSmall,
+    /// The bytes received are not a valid packet
+    Malformed,
+}
+
+/// A message the broker publishes on the client's behalf if the client goes away without
+/// disconnecting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub message: &'a [u8],
+    pub retain: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    /// The longest the client will go without sending anything, zero to never time out
+    pub keep_alive_secs: u16,
+    pub will: Option<Will<'a>>,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+}
+
+/// A message published to a topic.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Publish<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+}
+
+/// A packet that can be sent to a client.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Packet<'a> {
+    ConnAck {
+        session_present: bool,
+        /// Zero if the connection was accepted
+        return_code: u8,
+    },
+    Publish(Publish<'a>),
+    SubAck {
+        packet_id: u16,
+        /// Whether the subscription was accepted
+        granted: bool,
+    },
+    PingResp,
+    /// Any other type of packet, none of which are expected by a QoS 0 client
+    Other(u8),
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), PacketError> {
+        let end = self.pos + bytes.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(PacketError::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<(), PacketError> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), PacketError> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Writes length prefixed binary data (a UTF-8 string is written the same way).
+    fn binary(&mut self, value: &[u8]) -> Result<(), PacketError> {
+        let len = u16::try_from(value.len()).map_err(|_| PacketError::BufferTooSmall)?;
+        self.u16(len)?;
+        self.bytes(value)
+    }
+
+    fn fixed_header(&mut self, first: u8, remaining: usize) -> Result<(), PacketError> {
+        if remaining > MAX_REMAINING_LENGTH {
+            return Err(PacketError::BufferTooSmall);
+        }
+
+        self.u8(first)?;
+        let mut remaining = remaining;
+        loop {
+            let byte = (remaining % 128) as u8;
+            remaining /= 128;
+            if remaining > 0 {
+                self.u8(byte | 0x80)?;
+            } else {
+                return self.u8(byte);
+            }
+        }
+    }
+}
+
+/// The length of a string or binary field, including its length prefix.
+fn binary_len(value: &[u8]) -> usize {
+    2 + value.len()
+}
+
+/// Encodes a CONNECT packet into `buf`, returning its length.
+///
+/// A clean session is always asked for, nothing is kept by the broker between connections.
+pub fn encode_connect(buf: &mut [u8], connect: &Connect<'_>) -> Result<usize, PacketError> {
+    const CLEAN_SESSION: u8 = 0x02;
+    const WILL: u8 = 0x04;
+    const WILL_RETAIN: u8 = 0x20;
+    const PASSWORD: u8 = 0x40;
+    const USERNAME: u8 = 0x80;
+
+    let mut flags = CLEAN_SESSION;
+    // Protocol name, level, flags and keep alive
+    let mut remaining = 10 + binary_len(connect.client_id.as_bytes());
+
+    if let Some(will) = &connect.will {
+        flags |= WILL;
+        if will.retain {
+            flags |= WILL_RETAIN;
+        }
+        remaining += binary_len(will.topic.as_bytes()) + binary_len(will.message);
+    }
+    if let Some(username) = connect.username {
+        flags |= USERNAME;
+        remaining += binary_len(username.as_bytes());
+    }
+    if let Some(password) = connect.password {
+        flags |= PASSWORD;
+        remaining += binary_len(password);
+    }
+
+    let mut w = Writer::new(buf);
+    w.fixed_header(CONNECT << 4, remaining)?;
+    w.binary(b"MQTT")?;
+    w.u8(4)?;
+    w.u8(flags)?;
+    w.u16(connect.keep_alive_secs)?;
+    w.binary(connect.client_id.as_bytes())?;
+    if let Some(will) = &connect.will {
+        w.binary(will.topic.as_bytes())?;
+        w.binary(will.message)?;
+    }
+    if let Some(username) = connect.username {
+        w.binary(username.as_bytes())?;
+    }
+    if let Some(password) = connect.password {
+        w.binary(password)?;
+    }
+    Ok(w.pos)
+}
+
+/// Encodes everything of a QoS 0 PUBLISH packet other than the payload into `buf`, returning its
+/// length.
+///
+/// The payload is sent straight after, so that it does not also have to fit in `buf`.
+pub fn encode_publish_header(
+    buf: &mut [u8],
+    topic: &str,
+    payload_len: usize,
+    retain: bool,
+) -> Result<usize, PacketError> {
+    let mut w = Writer::new(buf);
+    w.fixed_header(
+        (PUBLISH << 4) | retain as u8,
+        binary_len(topic.as_bytes()) + payload_len,
+    )?;
+    w.binary(topic.as_bytes())?;
+    Ok(w.pos)
+}
+
+/// Encodes a SUBSCRIBE packet for a single topic filter at QoS 0 into `buf`, returning its length.
+pub fn encode_subscribe(
+    buf: &mut [u8],
+    packet_id: u16,
+    filter: &str,
+) -> Result<usize, PacketError> {
+    let mut w = Writer::new(buf);
+    w.fixed_header(
+        (SUBSCRIBE << 4) | 0x02,
+        2 + binary_len(filter.as_bytes()) + 1,
+    )?;
+    w.u16(packet_id)?;
+    w.binary(filter.as_bytes())?;
+    w.u8(0)?;
+    Ok(w.pos)
+}
+
+/// The total length of the packet at the start of `buf`, or `None` if not enough of it has been
+/// received to tell yet.
+pub fn packet_length(buf: &[u8]) -> Result<Option<usize>, PacketError> {
+    let mut remaining = 0;
+    for (i, byte) in buf.iter().skip(1).take(4).enumerate() {
+        remaining |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(2 + i + remaining));
+        }
+    }
+
+    if buf.len() >= 5 {
+        // The length is only allowed to take up four bytes
+        Err(PacketError::Malformed)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decodes the packet at the start of `buf`, returning it along with its length, or `None` if
+/// not all of it has been received yet.
+pub fn decode(buf: &[u8]) -> Result<Option<(Packet<'_>, usize)>, PacketError> {
+    let Some(len) = packet_length(buf)? else {
+        return Ok(None);
+    };
+    let Some(packet) = buf.get(..len) else {
+        return Ok(None);
+    };
+
+    let first = packet[0];
+    let header_len = packet
+        .iter()
+        .skip(1)
+        .position(|b| b & 0x80 == 0)
+        .unwrap_or(0)
+        + 2;
+    let body = &packet[header_len..];
+
+    let packet = match first >> 4 {
+        CONNACK => match body {
+            [ack_flags @ (0 | 1), return_code] if first & 0x0f == 0 => Packet::ConnAck {
+                session_present: *ack_flags == 1,
+                return_code: *return_code,
+            },
+            _ => return Err(PacketError::Malformed),
+        },
+        PUBLISH => Packet::Publish(decode_publish(first, body)?),
+        SUBACK => match body {
+            [id_high, id_low, return_code, ..] if first & 0x0f == 0 => Packet::SubAck {
+                packet_id: u16::from_be_bytes([*id_high, *id_low]),
+                granted: *return_code & 0x80 == 0,
+            },
+            _ => return Err(PacketError::Malformed),
+        },
+        PINGRESP if body.is_empty() => Packet::PingResp,
+        PINGRESP => return Err(PacketError::Malformed),
+        other => Packet::Other(other),
+    };
+    Ok(Some((packet, len)))
+}
+
+fn decode_publish(first: u8, body: &[u8]) -> Result<Publish<'_>, PacketError> {
+    let qos = (first >> 1) & 0x03;
+    if qos == 3 {
+        return Err(PacketError::Malformed);
+    }
+
+    let (topic_len, body) = body
+        .split_first_chunk::<2>()
+        .ok_or(PacketError::Malformed)?;
+    let topic_len = u16::from_be_bytes(*topic_len) as usize;
+    if body.len() < topic_len {
+        return Err(PacketError::Malformed);
+    }
+    let (topic, body) = body.split_at(topic_len);
+    let topic = core::str::from_utf8(topic).map_err(|_| PacketError::Malformed)?;
+
+    // Only QoS 1 and 2 messages have a packet identifier
+    let payload = match qos {
+        0 => body,
+        _ => body.get(2..).ok_or(PacketError::Malformed)?,
+    };
+
+    Ok(Publish {
+        topic,
+        payload,
+        retain: first & 0x01 != 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn connect() {
+        let mut buf = [0_u8; 64];
+        let connect = Connect {
+            client_id: "filter",
+            keep_alive_secs: 60,
+            will: None,
+            username: None,
+            password: None,
+        };
+        let len = encode_connect(&mut buf, &connect).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x12\x00\x04MQTT\x04\x02\x00\x3c\x00\x06filter"
+        );
+    }
+
+    #[test]
+    fn connect_with_will_and_credentials() {
+        let mut buf = [0_u8; 64];
+        let connect = Connect {
+            client_id: "f",
+            keep_alive_secs: 10,
+            will: Some(Will {
+                topic: "t",
+                message: b"off",
+                retain: true,
+            }),
+            username: Some("u"),
+            password: Some(b"p"),
+        };
+        let len = encode_connect(&mut buf, &connect).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x1b\x00\x04MQTT\x04\xe6\x00\x0a\x00\x01f\x00\x01t\x00\x03off\x00\x01u\x00\x01p"
+        );
+
+        assert_eq!(
+            encode_connect(&mut buf[..len - 1], &connect),
+            Err(PacketError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn publish_round_trip() {
+        let mut buf = [0_u8; 256];
+        let payload = [b'x'; 150];
+        let len = encode_publish_header(&mut buf, "a/b", payload.len(), true).unwrap();
+
+        // The remaining length takes two bytes
+        assert_eq!(&buf[..len], b"\x31\x9b\x01\x00\x03a/b");
+
+        buf[len..len + payload.len()].copy_from_slice(&payload);
+        let total = len + payload.len();
+        assert_eq!(packet_length(&buf[..2]), Ok(None));
+        assert_eq!(packet_length(&buf[..3]), Ok(Some(total)));
+        assert_eq!(
+            decode(&buf[..total]),
+            Ok(Some((
+                Packet::Publish(Publish {
+                    topic: "a/b",
+                    payload: &payload,
+                    retain: true,
+                }),
+                total
+            )))
+        );
+        assert_eq!(decode(&buf[..total - 1]), Ok(None));
+    }
+
+    #[test]
+    fn publish_with_packet_id() {
+        // QoS 1, which the broker only sends if it was subscribed to at QoS 1
+        let buf = b"\x32\x08\x00\x01t\x12\x34abc";
+        assert_eq!(
+            decode(buf),
+            Ok(Some((
+                Packet::Publish(Publish {
+                    topic: "t",
+                    payload: b"abc",
+                    retain: false,
+                }),
+                10
+            )))
+        );
+    }
+
+    #[test]
+    fn subscribe() {
+        let mut buf = [0_u8; 32];
+        let len = encode_subscribe(&mut buf, 1, "a/#").unwrap();
+        assert_eq!(&buf[..len], b"\x82\x08\x00\x01\x00\x03a/#\x00");
+    }
+
+    #[test]
+    fn decode_replies() {
+        assert_eq!(
+            decode(b"\x20\x02\x00\x00"),
+            Ok(Some((
+                Packet::ConnAck {
+                    session_present: false,
+                    return_code: 0
+                },
+                4
+            )))
+        );
+        assert_eq!(
+            decode(b"\x20\x02\x00\x05"),
+            Ok(Some((
+                Packet::ConnAck {
+                    session_present: false,
+                    return_code: 5
+                },
+                4
+            )))
+        );
+        assert_eq!(
+            decode(b"\x90\x03\x00\x07\x80"),
+            Ok(Some((
+                Packet::SubAck {
+                    packet_id: 7,
+                    granted: false
+                },
+                5
+            )))
+        );
+        assert_eq!(decode(b"\xd0\x00"), Ok(Some((Packet::PingResp, 2))));
+        assert_eq!(decode(b"\x40\x02\x00\x01"), Ok(Some((Packet::Other(4), 4))));
+    }
+
+    #[test]
+    fn decode_malformed() {
+        assert_eq!(decode(b"\x20\x01\x00"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\xd0\x01\x00"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\x36\x03\x00\x01t"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\x30\x03\x00\x05t"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\x30\x03\x00\x01\xff"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\x30\xff\xff\xff\xff"), Err(PacketError::Malformed));
+        assert_eq!(decode(b"\x30\xff\xff\xff"), Ok(None));
+    }
+}
@@ -0,0 +1,362 @@
+//! What the air filter publishes over MQTT, and the commands it accepts.
+//!
+//! Every topic is below `ms-air-filter/<device id>`:
+//!
+//! - `state`: `running` or `stopped`
+//! - `speed`: what the fan has been told to do, `off`, `low`, `mid` or `high`
+//! - `applied`: what the fan is actually doing, e.g. `switching to high`
+//! - `reason`: why the fan is running, empty when it is not
+//! - `remaining`: seconds of run time left, empty when the fan is not running for a set time
+//! - `filter/hours`: hours of use since the filters were last changed
+//! - `filter/life`: percentage of the filters' life that is left
+//! - `filter/status`: `ok`, `due` or `overdue`
+//! - `fault`: what has gone wrong (including the clock not being set while there is a schedule),
+//!   empty when nothing has
+//! - `temperature/<sensor address>`: °C, empty when the sensor has not been read recently
+//! - `availability`: `online` while connected, `offline` (the will) otherwise
+//! - `command`: subscribed to, takes the same fan commands as the console
+//! - `command/result`: `ok` once a command has been passed on, otherwise why it was not
+
+use crate::{
+    config::Config,
+    console::{self, ParseError},
+    fan::{FanCommand, FanStatus},
+    run_logic::{ControlCommand, State},
+    runtime::RuntimeMeter,
+    temperature::SensorReading,
+};
+use core::fmt::Write;
+
+pub const TOPIC_ROOT: &str = "ms-air-filter";
+
+/// The topic that commands are received on.
+pub const COMMAND: &str = "command";
+
+/// The topic that the outcome of each command is published to (not retained), so that one which
+/// could not be carried out is not silently lost.
+pub const COMMAND_RESULT: &str = "command/result";
+pub const COMMAND_OK: &str = "ok";
+
+/// The topic that says whether the air filter is connected, [`ONLINE`] is published to it once
+/// connected and the broker publishes [`OFFLINE`] (the will) once the connection is lost.
+pub const AVAILABILITY: &str = "availability";
+pub const ONLINE: &str = "online";
+pub const OFFLINE: &str = "offline";
+
+pub type Topic = heapless::String<64>;
+pub type Payload = heapless::String<48>;
+
+/// The topics for a single air filter.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    prefix: heapless::String<32>,
+}
+
+impl Topics {
+    pub fn new(device_id: &str) -> Self {
+        let mut prefix = heapless::String::new();
+        let _ = write!(prefix, "{TOPIC_ROOT}/{device_id}");
+        Self { prefix }
+    }
+
+    /// The topic that every other one is below.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn get(&self, name: &str) -> Topic {
+        let mut topic = Topic::new();
+        let _ = write!(topic, "{}/{name}", self.prefix);
+        topic
+    }
+
+    pub fn temperature(&self, address: u64) -> Topic {
+        let mut topic = Topic::new();
+        let _ = write!(topic, "{}/temperature/{address:016x}", self.prefix);
+        topic
+    }
+}
+
+/// The values published about the air filter, each to its own topic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Telemetry {
+    pub state: &'static str,
+    pub speed: &'static str,
+    pub applied: Payload,
+    pub reason: &'static str,
+    pub remaining: Payload,
+    pub filter_hours: Payload,
+    pub filter_life: Payload,
+    pub filter_status: &'static str,
+    pub fault: Payload,
+}
+
+impl Telemetry {
+    pub fn new(state: &State, fan: &FanStatus, meter: &RuntimeMeter, config: &Config) -> Self {
+        let (running, speed) = match state.fan_command() {
+            FanCommand::Stop => ("stopped", "off"),
+            FanCommand::Run(speed) => ("running", speed.name()),
+        };
+
+        let mut applied = Payload::new();
+        let _ = write!(applied, "{fan}");
+
+        let mut remaining = Payload::new();
+        if let Some(t) = state.time_remaining() {
+            let _ = write!(remaining, "{}", t.as_secs());
+        }
+
+        let mut filter_hours = Payload::new();
+        let hours = meter.since_filter_change.total().as_secs() as f32 / 3600.0;
+        let _ = write!(filter_hours, "{hours:.1}");
+
+        let mut filter_life = Payload::new();
+        let _ = write!(
+            filter_life,
+            "{}",
+            meter.filter_life_remaining_percent(config.filter_service_interval)
+        );
+
+        let mut fault = Payload::new();
+        if let Some(f) = state.fault() {
+            let _ = write!(fault, "{f}");
+        } else if let FanStatus::Fault(f) = fan {
+            let _ = write!(fault, "{f}");
+        } else if state.clock_not_set() {
+            let _ = write!(fault, "clock not set");
+        }
+
+        Self {
+            state: running,
+            speed,
+            applied,
+            reason: state.reason().map_or("", |r| r.description()),
+            remaining,
+            filter_hours,
+            filter_life,
+            filter_status: meter.filter_status(config.filter_service_interval).name(),
+            fault,
+        }
+    }
+
+    /// Each value along with the name of the topic it is published to.
+    pub fn values(&self) -> [(&'static str, &str); 9] {
+        [
+            ("state", self.state),
+            ("speed", self.speed),
+            ("applied", &self.applied),
+            ("reason", self.reason),
+            ("remaining", &self.remaining),
+            ("filter/hours", &self.filter_hours),
+            ("filter/life", &self.filter_life),
+            ("filter/status", self.filter_status),
+            ("fault", &self.fault),
+        ]
+    }
+
+    /// The values that have changed since `last` was published, or all of them if nothing has
+    /// been published yet.
+    pub fn changes(&self, last: Option<&Telemetry>) -> heapless::Vec<(&'static str, &str), 9> {
+        let last = last.map(Telemetry::values);
+        self.values()
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (_, value))| last.is_none_or(|last| last[*i].1 != *value))
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+/// The payload published for a temperature sensor, empty when it has not been read recently.
+pub fn temperature_payload(sensor: &SensorReading) -> Payload {
+    let mut payload = Payload::new();
+    if let Some(t) = sensor.fresh_temperature() {
+        let _ = write!(payload, "{t:.1}");
+    }
+    payload
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandError {
+    Parse(ParseError),
+    /// A console command other than one that controls the fan
+    NotAllowed,
+    /// The run logic has not yet caught up with earlier commands
+    Busy,
+}
+
+impl CommandError {
+    /// What is published to [`COMMAND_RESULT`].
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Parse(e) => e.description(),
+            Self::NotAllowed => "not a fan command",
+            Self::Busy => "busy, try again",
+        }
+    }
+}
+
+/// Parses a message published to the [`COMMAND`] topic, which takes the same fan commands as the
+/// console (`start [minutes] [speed]`, `stop` and `speed <speed>`).
+pub fn parse_command(payload: &[u8]) -> Result<ControlCommand, CommandError> {
+    let line = core::str::from_utf8(payload)
+        .map_err(|_| CommandError::Parse(ParseError::InvalidArgument))?;
+    console::parse(line)
+        .map_err(CommandError::Parse)?
+        .control_command()
+        .ok_or(CommandError::NotAllowed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{contactors::ContactorFault, fan::FanSpeed, run_logic::Triggers};
+    use embassy_time::{Duration, Instant};
+
+    #[test]
+    fn topics() {
+        let topics = Topics::new("a1b2c3");
+        assert_eq!(topics.prefix(), "ms-air-filter/a1b2c3");
+        assert_eq!(topics.get(COMMAND), "ms-air-filter/a1b2c3/command");
+        assert_eq!(
+            topics.get(COMMAND_RESULT),
+            "ms-air-filter/a1b2c3/command/result"
+        );
+        assert_eq!(
+            topics.temperature(0x28ff_0000_0000_0001),
+            "ms-air-filter/a1b2c3/temperature/28ff000000000001"
+        );
+    }
+
+    #[test]
+    fn telemetry() {
+        let config = Config::DEFAULT;
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::Low, Duration::from_secs(90 * 60));
+
+        let stopped = Telemetry::new(&State::default(), &FanStatus::Idle, &meter, &config);
+        assert_eq!(
+            stopped.values(),
+            [
+                ("state", "stopped"),
+                ("speed", "off"),
+                ("applied", "off"),
+                ("reason", ""),
+                ("remaining", ""),
+                ("filter/hours", "1.5"),
+                ("filter/life", "100"),
+                ("filter/status", "ok"),
+                ("fault", ""),
+            ]
+        );
+        assert_eq!(stopped.changes(None).len(), 9);
+        assert!(stopped.changes(Some(&stopped)).is_empty());
+
+        let mut triggers = Triggers::default();
+        triggers.button.handle_command(
+            ControlCommand::Start {
+                duration: Some(Duration::from_secs(600)),
+                speed: Some(FanSpeed::High),
+            },
+            &config,
+        );
+        let running = Telemetry::new(
+            &triggers.resolve(),
+            &FanStatus::Switching(FanCommand::Run(FanSpeed::High)),
+            &meter,
+            &config,
+        );
+        assert_eq!(
+            &running.changes(Some(&stopped))[..],
+            [
+                ("state", "running"),
+                ("speed", "high"),
+                ("applied", "switching to high"),
+                ("reason", "Remote"),
+                ("remaining", "600"),
+            ]
+        );
+    }
+
+    #[test]
+    fn faults() {
+        let config = Config::DEFAULT;
+        let mut meter = RuntimeMeter::new();
+        let ok = Telemetry::new(&State::default(), &FanStatus::Idle, &meter, &config);
+
+        meter.add(&FanSpeed::High, config.filter_service_interval);
+        let fault = ContactorFault::DidNotOpen(FanSpeed::Low);
+        let faulted = Telemetry::new(&State::default(), &FanStatus::Fault(fault), &meter, &config);
+        assert_eq!(
+            &faulted.changes(Some(&ok))[..],
+            [
+                ("applied", "fault, low contactor did not open"),
+                ("filter/hours", "500.0"),
+                ("filter/life", "0"),
+                ("filter/status", "overdue"),
+                ("fault", "low contactor did not open"),
+            ]
+        );
+    }
+
+    #[test]
+    fn clock_not_set() {
+        let config = Config::DEFAULT;
+        let meter = RuntimeMeter::new();
+        let mut schedule = crate::schedule::Schedule::default();
+        schedule
+            .add("daily 08:00-09:00 low".parse().unwrap())
+            .unwrap();
+        let mut triggers = Triggers::default();
+        triggers.schedule.handle_time(None, &schedule);
+
+        let telemetry = Telemetry::new(&triggers.resolve(), &FanStatus::Idle, &meter, &config);
+        assert_eq!(telemetry.fault.as_str(), "clock not set");
+    }
+
+    #[test]
+    fn temperatures() {
+        let mut readings = crate::temperature::TemperatureReadings::default();
+        readings.record(1, 21.06, Instant::from_secs(1));
+        readings.record_error(2);
+
+        let payloads: std::vec::Vec<_> = readings.iter().map(temperature_payload).collect();
+        assert_eq!(payloads, ["21.1", ""]);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command(b"stop"), Ok(ControlCommand::Stop));
+        assert_eq!(
+            parse_command(b"start high\n"),
+            Ok(ControlCommand::Start {
+                duration: None,
+                speed: Some(FanSpeed::High)
+            })
+        );
+        assert_eq!(
+            parse_command(b"speed low"),
+            Ok(ControlCommand::SetSpeed(FanSpeed::Low))
+        );
+
+        assert_eq!(
+            parse_command(b"config set run_minutes 1"),
+            Err(CommandError::NotAllowed)
+        );
+        assert_eq!(
+            parse_command(b"speed fast"),
+            Err(CommandError::Parse(ParseError::InvalidArgument))
+        );
+        assert_eq!(
+            parse_command(b"\xff"),
+            Err(CommandError::Parse(ParseError::InvalidArgument))
+        );
+
+        assert_eq!(
+            CommandError::Parse(ParseError::InvalidArgument).description(),
+            "invalid argument"
+        );
+        assert_eq!(CommandError::Busy.description(), "busy, try again");
+    }
+}
@@ -0,0 +1,687 @@
+use super::{ControlCommand, Demand, Priority, Reason, RunTimeChange, Trigger};
+use crate::{
+    buttons::{Button, ButtonEvent, ButtonPushDuration},
+    config::Config,
+    fan::{FanCommand, FanSpeed},
+};
+use embassy_time::Duration;
+
+/// How many ticks a change to the run time made with the buttons is shown for.
+const CHANGE_SHOWN_FOR_TICKS: u8 = 3;
+
+/// Taking time off never leaves less than this, stopping is what a long push is for.
+const MINIMUM_RUN_TIME: Duration = Duration::from_secs(60);
+
+/// Runs the fan for a period of time when asked to by a person, either with the buttons or
+/// remotely.
+///
+/// Pushing both buttons together runs the fan continuously instead, until it is stopped or
+/// [`Config::continuous_max_duration`] has passed.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ManualButtonTrigger {
+    time_remaining: Option<Duration>,
+    requested_speed: FanSpeed,
+    /// Who last started (or renewed) the run
+    started_by: Reason,
+    /// How much the last push added to the run time, undone if that push turns out to be the
+    /// first of a double push
+    last_added: Duration,
+    /// The last change to the run time, and the number of ticks left to show it for
+    change: Option<(RunTimeChange, u8)>,
+    /// Time left running at low speed to clear the air after the run has stopped
+    purge_remaining: Option<Duration>,
+    /// How long the fan has been running in continuous mode, in which case there is no
+    /// `time_remaining`
+    continuous_for: Option<Duration>,
+}
+
+impl Default for ManualButtonTrigger {
+    fn default() -> Self {
+        Self {
+            time_remaining: None,
+            requested_speed: FanSpeed::Low,
+            started_by: Reason::Button,
+            last_added: Duration::from_secs(0),
+            change: None,
+            purge_remaining: None,
+            continuous_for: None,
+        }
+    }
+}
+
+impl Trigger for ManualButtonTrigger {
+    fn demand(&self) -> Option<Demand> {
+        if let Some(purge_remaining) = self.purge_remaining {
+            // Anything else wanting the fan to run takes over from the purge
+            return Some(Demand {
+                command: FanCommand::Run(FanSpeed::Low),
+                priority: Priority::Automatic,
+                reason: Reason::Purge,
+                time_remaining: Some(purge_remaining),
+            });
+        }
+
+        self.running().then(|| Demand {
+            command: FanCommand::Run(self.requested_speed.clone()),
+            priority: Priority::Manual,
+            reason: self.started_by,
+            time_remaining: self.time_remaining,
+        })
+    }
+}
+
+impl ManualButtonTrigger {
+    /// Counts down the remaining time, this must be called once per second.
+    pub fn handle_tick(&mut self, config: &Config) -> bool {
+        if let Some(purge_remaining) = self.purge_remaining {
+            match purge_remaining.checked_sub(Duration::from_secs(1)) {
+                Some(t) if t >= Duration::from_secs(1) => self.purge_remaining = Some(t),
+                _ => *self = Self::default(),
+            }
+            true
+        } else if let Some(continuous_for) = self.continuous_for {
+            let continuous_for = continuous_for + Duration::from_secs(1);
+            let limit = config.continuous_max_duration;
+
+            if limit > Duration::from_secs(0) && continuous_for >= limit {
+                self.stop(config);
+            } else {
+                self.continuous_for = Some(continuous_for);
+            }
+            true
+        } else if let Some(time_remaining) = self.time_remaining {
+            self.change = self
+                .change
+                .and_then(|(change, ticks)| (ticks > 1).then_some((change, ticks - 1)));
+
+            match time_remaining.checked_sub(Duration::from_secs(1)) {
+                Some(t) => {
+                    if t < Duration::from_secs(1) {
+                        self.stop(config);
+                    } else {
+                        self.time_remaining = Some(t);
+                    }
+                }
+                None => {
+                    self.stop(config);
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stops the run, purging first if that is configured.
+    ///
+    /// Stopping while purging cancels the purge.
+    fn stop(&mut self, config: &Config) -> bool {
+        if self.running() && config.purge_duration > Duration::from_secs(0) {
+            *self = Self {
+                purge_remaining: Some(config.purge_duration),
+                ..Self::default()
+            };
+            true
+        } else if self.running() || self.purge_remaining.is_some() {
+            *self = Self::default();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn handle_button(&mut self, event: ButtonEvent, config: &Config) -> bool {
+        match event {
+            // Start, or renew/add/subtract time
+            ButtonEvent {
+                button: Button::Demand,
+                push_duration: ButtonPushDuration::Short,
+                clicks,
+            } => {
+                self.purge_remaining = None;
+                let was_continuous = self.continuous_for.take().is_some();
+                match self.time_remaining {
+                    // Back to a timed run, at the same speed
+                    None if was_continuous => {
+                        self.time_remaining = Some(config.run_duration);
+                    }
+                    None => {
+                        self.requested_speed = config.start_speed.clone();
+                        self.time_remaining = Some(config.run_duration);
+                        self.last_added = Duration::from_secs(0);
+                    }
+                    Some(_) if config.run_increment == Duration::from_secs(0) => {
+                        self.time_remaining = Some(config.run_duration);
+                    }
+                    Some(remaining) if clicks < 2 => self.add_time(remaining, config),
+                    Some(remaining) => self.subtract_time(remaining, config),
+                }
+                self.started_by = Reason::Button;
+                true
+            }
+            // Run continuously
+            ButtonEvent {
+                button: Button::Both,
+                push_duration: ButtonPushDuration::Short,
+                ..
+            } => {
+                if self.continuous_for.is_some() {
+                    return false;
+                }
+                if !self.running() {
+                    self.requested_speed = config.start_speed.clone();
+                }
+                *self = Self {
+                    requested_speed: self.requested_speed.clone(),
+                    continuous_for: Some(Duration::from_secs(0)),
+                    ..Self::default()
+                };
+                true
+            }
+            // Stop, or cancel the purge
+            ButtonEvent {
+                button: Button::Demand,
+                push_duration: ButtonPushDuration::Long,
+                ..
+            } => self.stop(config),
+            // Cycle fan speed
+            ButtonEvent {
+                button: Button::Speed,
+                push_duration: ButtonPushDuration::Short,
+                ..
+            } => {
+                if self.running() {
+                    self.requested_speed.cycle();
+                    true
+                } else {
+                    false
+                }
+            }
+            // A long push of the speed button moves between screens on the display, so must
+            // never be used here
+            _ => false,
+        }
+    }
+
+    /// Adds one increment to the run time, up to the maximum.
+    fn add_time(&mut self, remaining: Duration, config: &Config) {
+        let time_remaining =
+            (remaining + config.run_increment).min(config.max_run_duration.max(remaining));
+
+        self.last_added = time_remaining - remaining;
+        self.time_remaining = Some(time_remaining);
+        self.change = Some((
+            RunTimeChange::Added(self.last_added),
+            CHANGE_SHOWN_FOR_TICKS,
+        ));
+    }
+
+    /// Takes one increment off the run time.
+    ///
+    /// The push before this one was the first of a double push, so what it added is taken off
+    /// too.
+    fn subtract_time(&mut self, remaining: Duration, config: &Config) {
+        let before = remaining
+            .checked_sub(self.last_added)
+            .unwrap_or(Duration::from_secs(0));
+        let time_remaining = before
+            .checked_sub(config.run_increment)
+            .unwrap_or(Duration::from_secs(0))
+            .max(remaining.min(MINIMUM_RUN_TIME));
+
+        self.last_added = Duration::from_secs(0);
+        self.time_remaining = Some(time_remaining);
+        self.change = Some((
+            RunTimeChange::Subtracted(
+                before
+                    .checked_sub(time_remaining)
+                    .unwrap_or(Duration::from_secs(0)),
+            ),
+            CHANGE_SHOWN_FOR_TICKS,
+        ));
+    }
+
+    fn running(&self) -> bool {
+        self.time_remaining.is_some() || self.continuous_for.is_some()
+    }
+
+    /// Whether the fan is running until it is stopped, rather than for a period of time.
+    pub fn continuous(&self) -> bool {
+        self.continuous_for.is_some()
+    }
+
+    /// The change just made to the run time with the buttons, if any.
+    pub fn run_time_change(&self) -> Option<RunTimeChange> {
+        self.change.map(|(change, _)| change)
+    }
+
+    pub fn handle_command(&mut self, command: ControlCommand, config: &Config) -> bool {
+        match command {
+            ControlCommand::Start { duration, speed } => {
+                self.purge_remaining = None;
+                if let Some(speed) = speed {
+                    self.requested_speed = speed;
+                } else if !self.running() {
+                    self.requested_speed = config.start_speed.clone();
+                }
+                self.continuous_for = None;
+                self.time_remaining = Some(duration.unwrap_or(config.run_duration));
+                self.started_by = Reason::Remote;
+                true
+            }
+            ControlCommand::Stop => self.stop(config),
+            ControlCommand::SetSpeed(speed) => {
+                if self.running() && self.requested_speed != speed {
+                    self.requested_speed = speed;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn push(button: Button, push_duration: ButtonPushDuration) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            push_duration,
+            clicks: 1,
+        }
+    }
+
+    fn started(config: &Config) -> ManualButtonTrigger {
+        let mut trigger = ManualButtonTrigger::default();
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), config));
+        trigger
+    }
+
+    #[test]
+    fn stopped_by_default() {
+        let trigger = ManualButtonTrigger::default();
+        assert_eq!(trigger.demand(), None);
+    }
+
+    #[test]
+    fn short_push_starts() {
+        let config = Config::DEFAULT;
+        let trigger = started(&config);
+
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::Low));
+        assert_eq!(demand.reason, Reason::Button);
+        assert_eq!(demand.time_remaining, Some(config.run_duration));
+    }
+
+    #[test]
+    fn timer_expires() {
+        let mut config = Config::DEFAULT;
+        config.run_duration = Duration::from_secs(5);
+        let mut trigger = started(&config);
+
+        for remaining in [4, 3, 2, 1] {
+            assert!(trigger.handle_tick(&config));
+            assert_eq!(
+                trigger.demand().unwrap().time_remaining,
+                Some(Duration::from_secs(remaining))
+            );
+        }
+
+        assert!(trigger.handle_tick(&config));
+        assert_eq!(trigger.demand(), None);
+
+        // Nothing changes once stopped
+        assert!(!trigger.handle_tick(&config));
+    }
+
+    #[test]
+    fn short_push_renews_timer() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+
+        for _ in 0..60 {
+            trigger.handle_tick(&config);
+        }
+        assert_eq!(
+            trigger.demand().unwrap().time_remaining,
+            Some(config.run_duration - Duration::from_secs(60))
+        );
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), &config));
+        assert_eq!(
+            trigger.demand().unwrap().time_remaining,
+            Some(config.run_duration)
+        );
+    }
+
+    fn demand_push(clicks: u8) -> ButtonEvent {
+        ButtonEvent {
+            button: Button::Demand,
+            push_duration: ButtonPushDuration::Short,
+            clicks,
+        }
+    }
+
+    fn increments_config() -> Config {
+        let mut config = Config::DEFAULT;
+        config.run_increment = Duration::from_secs(60 * 10);
+        config.max_run_duration = Duration::from_secs(60 * 45);
+        config
+    }
+
+    fn time_remaining(trigger: &ManualButtonTrigger) -> Option<u64> {
+        trigger
+            .demand()
+            .and_then(|demand| demand.time_remaining)
+            .map(|t| t.as_secs() / 60)
+    }
+
+    #[test]
+    fn short_push_adds_time_up_to_maximum() {
+        let config = increments_config();
+        let mut trigger = started(&config);
+        assert_eq!(time_remaining(&trigger), Some(20));
+        assert_eq!(trigger.run_time_change(), None);
+
+        assert!(trigger.handle_button(demand_push(1), &config));
+        assert_eq!(time_remaining(&trigger), Some(30));
+        assert_eq!(
+            trigger.run_time_change(),
+            Some(RunTimeChange::Added(Duration::from_secs(60 * 10)))
+        );
+
+        trigger.handle_button(demand_push(1), &config);
+        trigger.handle_button(demand_push(1), &config);
+        assert_eq!(time_remaining(&trigger), Some(45));
+        assert_eq!(
+            trigger.run_time_change(),
+            Some(RunTimeChange::Added(Duration::from_secs(60 * 5)))
+        );
+    }
+
+    #[test]
+    fn double_push_subtracts_time() {
+        let config = increments_config();
+        let mut trigger = started(&config);
+        trigger.handle_button(demand_push(1), &config);
+        assert_eq!(time_remaining(&trigger), Some(30));
+
+        // The first push of the double push added time, which is taken off again
+        trigger.handle_button(demand_push(1), &config);
+        assert!(trigger.handle_button(demand_push(2), &config));
+        assert_eq!(time_remaining(&trigger), Some(20));
+        assert_eq!(
+            trigger.run_time_change(),
+            Some(RunTimeChange::Subtracted(Duration::from_secs(60 * 10)))
+        );
+
+        // Every further push takes off more, but never stops the fan
+        trigger.handle_button(demand_push(3), &config);
+        assert_eq!(time_remaining(&trigger), Some(10));
+        trigger.handle_button(demand_push(4), &config);
+        trigger.handle_button(demand_push(5), &config);
+        assert_eq!(time_remaining(&trigger), Some(1));
+        assert_eq!(
+            trigger.run_time_change(),
+            Some(RunTimeChange::Subtracted(Duration::from_secs(0)))
+        );
+    }
+
+    #[test]
+    fn run_time_change_is_shown_briefly() {
+        let config = increments_config();
+        let mut trigger = started(&config);
+        trigger.handle_button(demand_push(1), &config);
+
+        for _ in 0..CHANGE_SHOWN_FOR_TICKS - 1 {
+            trigger.handle_tick(&config);
+            assert!(trigger.run_time_change().is_some());
+        }
+        trigger.handle_tick(&config);
+        assert_eq!(trigger.run_time_change(), None);
+    }
+
+    #[test]
+    fn long_push_stops() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config));
+        assert_eq!(trigger.demand(), None);
+
+        // Nothing to stop
+        assert!(!trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config));
+    }
+
+    fn purge_config() -> Config {
+        let mut config = Config::DEFAULT;
+        config.run_duration = Duration::from_secs(5);
+        config.purge_duration = Duration::from_secs(3);
+        config
+    }
+
+    fn assert_purging(trigger: &ManualButtonTrigger, remaining: u64) {
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::Low));
+        assert_eq!(demand.priority, Priority::Automatic);
+        assert_eq!(demand.reason, Reason::Purge);
+        assert_eq!(demand.time_remaining, Some(Duration::from_secs(remaining)));
+    }
+
+    #[test]
+    fn purges_after_timer_expires() {
+        let config = purge_config();
+        let mut trigger = started(&config);
+        assert!(trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config));
+
+        for _ in 0..5 {
+            trigger.handle_tick(&config);
+        }
+        assert_purging(&trigger, 3);
+
+        // The speed can't be changed while purging
+        assert!(!trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config));
+
+        for remaining in [2, 1] {
+            assert!(trigger.handle_tick(&config));
+            assert_purging(&trigger, remaining);
+        }
+        assert!(trigger.handle_tick(&config));
+        assert_eq!(trigger.demand(), None);
+        assert!(!trigger.handle_tick(&config));
+    }
+
+    #[test]
+    fn second_long_push_cancels_purge() {
+        let config = purge_config();
+        let mut trigger = started(&config);
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config));
+        assert_purging(&trigger, 3);
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config));
+        assert_eq!(trigger.demand(), None);
+    }
+
+    #[test]
+    fn short_push_while_purging_starts_again() {
+        let config = purge_config();
+        let mut trigger = started(&config);
+        trigger.handle_command(ControlCommand::Stop, &config);
+        assert_purging(&trigger, 3);
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), &config));
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.reason, Reason::Button);
+        assert_eq!(demand.time_remaining, Some(config.run_duration));
+    }
+
+    #[test]
+    fn no_purge_by_default() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+
+        trigger.handle_command(ControlCommand::Stop, &config);
+        assert_eq!(trigger.demand(), None);
+    }
+
+    fn both_push() -> ButtonEvent {
+        push(Button::Both, ButtonPushDuration::Short)
+    }
+
+    #[test]
+    fn both_buttons_run_continuously() {
+        let mut config = Config::DEFAULT;
+        config.start_speed = FanSpeed::Medium;
+        let mut trigger = ManualButtonTrigger::default();
+
+        assert!(trigger.handle_button(both_push(), &config));
+        assert!(trigger.continuous());
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::Medium));
+        assert_eq!(demand.reason, Reason::Button);
+        assert_eq!(demand.time_remaining, None);
+
+        for _ in 0..60 * 60 {
+            assert!(trigger.handle_tick(&config));
+        }
+        assert_eq!(demand, trigger.demand().unwrap());
+
+        // Already running continuously
+        assert!(!trigger.handle_button(both_push(), &config));
+
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config));
+        assert_eq!(trigger.demand(), None);
+        assert!(!trigger.continuous());
+    }
+
+    #[test]
+    fn continuous_from_timed_run_keeps_speed() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+        trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config);
+
+        assert!(trigger.handle_button(both_push(), &config));
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::Medium));
+        assert_eq!(demand.time_remaining, None);
+
+        // A short push goes back to a timed run
+        assert!(trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), &config));
+        assert!(!trigger.continuous());
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::Medium));
+        assert_eq!(demand.time_remaining, Some(config.run_duration));
+    }
+
+    #[test]
+    fn continuous_stops_at_limit() {
+        let mut config = purge_config();
+        config.continuous_max_duration = Duration::from_secs(10);
+        let mut trigger = ManualButtonTrigger::default();
+        trigger.handle_button(both_push(), &config);
+
+        for _ in 0..9 {
+            trigger.handle_tick(&config);
+        }
+        assert!(trigger.continuous());
+
+        // Stopping this way purges as usual
+        trigger.handle_tick(&config);
+        assert_purging(&trigger, 3);
+    }
+
+    #[test]
+    fn continuous_without_limit() {
+        let mut config = Config::DEFAULT;
+        config.continuous_max_duration = Duration::from_secs(0);
+        let mut trigger = ManualButtonTrigger::default();
+        trigger.handle_button(both_push(), &config);
+
+        for _ in 0..48 * 60 * 60 {
+            trigger.handle_tick(&config);
+        }
+        assert!(trigger.continuous());
+    }
+
+    #[test]
+    fn speed_cycles_while_running() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+
+        for speed in [FanSpeed::Medium, FanSpeed::High, FanSpeed::Low] {
+            assert!(trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config));
+            assert_eq!(trigger.demand().unwrap().command, FanCommand::Run(speed));
+        }
+    }
+
+    #[test]
+    fn speed_ignored_while_stopped() {
+        let config = Config::DEFAULT;
+        let mut trigger = ManualButtonTrigger::default();
+
+        assert!(!trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config));
+        assert_eq!(trigger.demand(), None);
+    }
+
+    #[test]
+    fn speed_reset_after_stop() {
+        let config = Config::DEFAULT;
+        let mut trigger = started(&config);
+
+        trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config);
+        trigger.handle_button(push(Button::Demand, ButtonPushDuration::Long), &config);
+        trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), &config);
+
+        assert_eq!(
+            trigger.demand().unwrap().command,
+            FanCommand::Run(FanSpeed::Low)
+        );
+    }
+
+    #[test]
+    fn starts_at_configured_speed() {
+        let mut config = Config::DEFAULT;
+        config.start_speed = FanSpeed::High;
+        let mut trigger = started(&config);
+        assert_eq!(
+            trigger.demand().unwrap().command,
+            FanCommand::Run(FanSpeed::High)
+        );
+
+        // Renewing the timer keeps the chosen speed
+        trigger.handle_button(push(Button::Speed, ButtonPushDuration::Short), &config);
+        trigger.handle_button(push(Button::Demand, ButtonPushDuration::Short), &config);
+        assert_eq!(
+            trigger.demand().unwrap().command,
+            FanCommand::Run(FanSpeed::Low)
+        );
+    }
+
+    #[test]
+    fn remote_start() {
+        let config = Config::DEFAULT;
+        let mut trigger = ManualButtonTrigger::default();
+
+        assert!(trigger.handle_command(
+            ControlCommand::Start {
+                duration: Some(Duration::from_secs(60 * 90)),
+                speed: Some(FanSpeed::High),
+            },
+            &config
+        ));
+
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::High));
+        assert_eq!(demand.reason, Reason::Remote);
+        assert_eq!(demand.time_remaining, Some(Duration::from_secs(60 * 90)));
+
+        assert!(trigger.handle_command(ControlCommand::Stop, &config));
+        assert_eq!(trigger.demand(), None);
+    }
+}
@@ -0,0 +1,440 @@
+mod manual_button_trigger;
+mod overtemperature_interlock;
+mod schedule_trigger;
+mod temperature_trigger;
+
+pub use manual_button_trigger::ManualButtonTrigger;
+pub use overtemperature_interlock::{Fault, FaultCause, InterlockAction, OvertemperatureInterlock};
+pub use schedule_trigger::ScheduleTrigger;
+pub use temperature_trigger::TemperatureTrigger;
+
+use crate::{
+    buttons::ButtonEvent,
+    config::Config,
+    fan::{FanCommand, FanSpeed},
+    temperature::TemperatureReadings,
+};
+use embassy_time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControlCommand {
+    /// Start the fan (or reset the timer if already running), optionally overriding the
+    /// configured run duration and/or the current speed
+    Start {
+        duration: Option<Duration>,
+        speed: Option<FanSpeed>,
+    },
+    Stop,
+    /// Change the fan speed if running
+    SetSpeed(FanSpeed),
+}
+
+/// Something that may want the fan to be doing something.
+pub trait Trigger {
+    /// What this trigger wants the fan to do, or `None` if it does not care.
+    fn demand(&self) -> Option<Demand>;
+}
+
+/// How important a demand is.
+/// Only safety demands override the fan speed demanded by others, otherwise the priority just
+/// decides which of several demands for the same speed is acted on.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    /// Triggers acting on their own (e.g. temperature or the schedule)
+    Automatic,
+    /// Triggers acting on behalf of a person (e.g. buttons)
+    Manual,
+    /// Triggers that protect the equipment or people, these override everything else
+    Safety,
+}
+
+/// Why the fan is being asked to do something.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reason {
+    Button,
+    Remote,
+    Temperature,
+    Schedule,
+    /// Clearing the air after being stopped
+    Purge,
+    /// The overtemperature interlock has tripped
+    Overtemperature,
+}
+
+impl Reason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Button => "Button",
+            Self::Remote => "Remote",
+            Self::Temperature => "Temperature",
+            Self::Schedule => "Schedule",
+            Self::Purge => "Purge",
+            Self::Overtemperature => "Overheating",
+        }
+    }
+}
+
+/// A change made to the remaining run time with the buttons.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RunTimeChange {
+    Added(Duration),
+    Subtracted(Duration),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Demand {
+    pub command: FanCommand,
+    pub priority: Priority,
+    pub reason: Reason,
+    pub time_remaining: Option<Duration>,
+}
+
+/// Picks the demand that should be acted on.
+///
+/// A safety demand always wins. Otherwise the demand for the highest fan speed wins (running at
+/// that speed satisfies every other demand too), the highest priority one if there are several.
+pub fn arbitrate(demands: impl Iterator<Item = Demand>) -> Option<Demand> {
+    let safety = |demand: &Demand| demand.priority == Priority::Safety;
+
+    demands.max_by(|a, b| {
+        safety(a)
+            .cmp(&safety(b))
+            .then_with(|| a.command.cmp(&b.command))
+            .then_with(|| a.priority.cmp(&b.priority))
+    })
+}
+
+/// Every trigger that may control the fan.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Triggers {
+    pub button: ManualButtonTrigger,
+    pub temperature: TemperatureTrigger,
+    pub schedule: ScheduleTrigger,
+    pub interlock: OvertemperatureInterlock,
+}
+
+impl Triggers {
+    /// Passes a button push on to whichever trigger it is for.
+    ///
+    /// While the interlock has tripped the buttons only acknowledge the fault, so that nothing is
+    /// started or stopped by accident while clearing it.
+    pub fn handle_button(&mut self, event: ButtonEvent, config: &Config) -> bool {
+        if self.interlock.fault().is_some() {
+            self.interlock.handle_button(&event)
+        } else {
+            self.button.handle_button(event, config)
+        }
+    }
+
+    pub fn handle_readings(&mut self, readings: &TemperatureReadings, config: &Config) -> bool {
+        let was_clear = self.interlock.fault().is_none();
+
+        // Not short circuited, both triggers have to see every reading
+        let changed = self.temperature.handle_readings(readings, config)
+            | self.interlock.handle_readings(readings, config);
+
+        // Whatever the buttons were doing is abandoned rather than carrying on once the fault
+        // has been acknowledged
+        if was_clear && self.interlock.fault().is_some() {
+            self.button = ManualButtonTrigger::default();
+        }
+
+        changed
+    }
+
+    /// Cancels whatever the buttons were doing once the fan has been stopped by a contactor fault,
+    /// so that it stays off until it is started again.
+    pub fn handle_contactor_fault(&mut self) -> bool {
+        let changed = self.button.demand().is_some();
+        self.button = ManualButtonTrigger::default();
+        changed
+    }
+
+    pub fn resolve(&self) -> State {
+        let demands = [
+            self.button.demand(),
+            self.temperature.demand(),
+            self.schedule.demand(),
+            self.interlock.demand(),
+        ];
+
+        let demand = arbitrate(demands.into_iter().flatten());
+
+        // Only shown while the buttons are in control
+        let buttons_in_control = demand
+            .as_ref()
+            .is_some_and(|demand| demand.reason == Reason::Button);
+        let run_time_change = self.button.run_time_change().filter(|_| buttons_in_control);
+        let continuous = buttons_in_control && self.button.continuous();
+
+        State {
+            demand,
+            run_time_change,
+            continuous,
+            fault: self.interlock.fault().cloned(),
+            clock_not_set: self.schedule.clock_not_set(),
+        }
+    }
+}
+
+/// The resolved outcome of all triggers.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct State {
+    demand: Option<Demand>,
+    run_time_change: Option<RunTimeChange>,
+    continuous: bool,
+    fault: Option<Fault>,
+    clock_not_set: bool,
+}
+
+impl State {
+    pub fn fan_command(&self) -> FanCommand {
+        self.demand
+            .as_ref()
+            .map(|demand| demand.command.clone())
+            .unwrap_or(FanCommand::Stop)
+    }
+
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.demand
+            .as_ref()
+            .and_then(|demand| demand.time_remaining)
+    }
+
+    /// Why the fan is doing what it is doing, `None` when it is stopped because nothing wants it to run.
+    pub fn reason(&self) -> Option<Reason> {
+        self.demand.as_ref().map(|demand| demand.reason)
+    }
+
+    /// The change just made to the remaining run time, if any.
+    pub fn run_time_change(&self) -> Option<RunTimeChange> {
+        self.run_time_change
+    }
+
+    /// Whether the fan is running until it is stopped, in which case there is no time remaining.
+    pub fn continuous(&self) -> bool {
+        self.continuous
+    }
+
+    /// The latched overtemperature fault, if the interlock has tripped.
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    /// Whether the schedule is not being followed because the clock has not been set (e.g. after
+    /// the power has been off).
+    pub fn clock_not_set(&self) -> bool {
+        self.clock_not_set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn demand(command: FanCommand, priority: Priority, reason: Reason) -> Demand {
+        Demand {
+            command,
+            priority,
+            reason,
+            time_remaining: None,
+        }
+    }
+
+    #[test]
+    fn no_demands_stops() {
+        let state = Triggers::default().resolve();
+        assert_eq!(state.fan_command(), FanCommand::Stop);
+        assert_eq!(state.reason(), None);
+        assert_eq!(state.time_remaining(), None);
+    }
+
+    #[test]
+    fn interlock_takes_the_buttons() {
+        let mut config = Config::DEFAULT;
+        config.interlock_sensor = 1;
+        config.interlock_limit = 60.0;
+
+        let mut readings = TemperatureReadings::default();
+        readings.record(1, 65.0, embassy_time::Instant::from_secs(0));
+
+        let mut triggers = Triggers::default();
+        let start = ButtonEvent {
+            button: crate::buttons::Button::Demand,
+            push_duration: crate::buttons::ButtonPushDuration::Short,
+            clicks: 1,
+        };
+        assert!(triggers.handle_button(start.clone(), &config));
+        assert!(triggers.handle_readings(&readings, &config));
+
+        let state = triggers.resolve();
+        assert_eq!(state.fan_command(), FanCommand::Stop);
+        assert_eq!(state.reason(), Some(Reason::Overtemperature));
+        assert!(state.fault().is_some());
+
+        // The buttons no longer control the fan, and the run they started is cancelled
+        assert!(!triggers.handle_button(start.clone(), &config));
+        assert_eq!(triggers.button.demand(), None);
+
+        readings.record(1, 20.0, embassy_time::Instant::from_secs(10));
+        triggers.handle_readings(&readings, &config);
+        let acknowledge = ButtonEvent {
+            button: crate::buttons::Button::Both,
+            push_duration: crate::buttons::ButtonPushDuration::Long,
+            clicks: 1,
+        };
+        assert!(triggers.handle_button(acknowledge, &config));
+        assert_eq!(triggers.resolve().fan_command(), FanCommand::Stop);
+    }
+
+    #[test]
+    fn contactor_fault_cancels_the_buttons() {
+        let config = Config::DEFAULT;
+        let mut triggers = Triggers::default();
+        assert!(!triggers.handle_contactor_fault());
+
+        let start = ButtonEvent {
+            button: crate::buttons::Button::Demand,
+            push_duration: crate::buttons::ButtonPushDuration::Short,
+            clicks: 1,
+        };
+        assert!(triggers.handle_button(start, &config));
+        assert_ne!(triggers.resolve().fan_command(), FanCommand::Stop);
+
+        assert!(triggers.handle_contactor_fault());
+        assert_eq!(triggers.resolve().fan_command(), FanCommand::Stop);
+    }
+
+    #[test]
+    fn fastest_wins_across_priorities() {
+        let winner = arbitrate(
+            [
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Automatic,
+                    Reason::Temperature,
+                ),
+                demand(
+                    FanCommand::Run(FanSpeed::Low),
+                    Priority::Manual,
+                    Reason::Button,
+                ),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        assert_eq!(winner.reason, Reason::Temperature);
+        assert_eq!(winner.command, FanCommand::Run(FanSpeed::Medium));
+    }
+
+    #[test]
+    fn priority_breaks_ties() {
+        for demands in [
+            [
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Manual,
+                    Reason::Button,
+                ),
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Automatic,
+                    Reason::Temperature,
+                ),
+            ],
+            [
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Automatic,
+                    Reason::Temperature,
+                ),
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Manual,
+                    Reason::Button,
+                ),
+            ],
+        ] {
+            assert_eq!(
+                arbitrate(demands.into_iter()).unwrap().reason,
+                Reason::Button
+            );
+        }
+    }
+
+    #[test]
+    fn buttons_and_temperature_run_at_the_highest_speed() {
+        let config = Config::DEFAULT;
+        let mut readings = TemperatureReadings::default();
+        readings.record(1, 35.0, embassy_time::Instant::from_secs(0));
+
+        let mut triggers = Triggers::default();
+        triggers.button.handle_command(
+            ControlCommand::Start {
+                duration: None,
+                speed: Some(FanSpeed::Low),
+            },
+            &config,
+        );
+        triggers.handle_readings(&readings, &config);
+        assert_eq!(
+            triggers.resolve().fan_command(),
+            FanCommand::Run(FanSpeed::Medium)
+        );
+
+        triggers
+            .button
+            .handle_command(ControlCommand::SetSpeed(FanSpeed::High), &config);
+        let state = triggers.resolve();
+        assert_eq!(state.fan_command(), FanCommand::Run(FanSpeed::High));
+        assert_eq!(state.reason(), Some(Reason::Remote));
+    }
+
+    #[test]
+    fn safety_can_force_stop() {
+        let winner = arbitrate(
+            [
+                demand(
+                    FanCommand::Run(FanSpeed::High),
+                    Priority::Manual,
+                    Reason::Button,
+                ),
+                demand(FanCommand::Stop, Priority::Safety, Reason::Temperature),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        assert_eq!(winner.command, FanCommand::Stop);
+    }
+
+    #[test]
+    fn fastest_wins_within_priority() {
+        let winner = arbitrate(
+            [
+                demand(
+                    FanCommand::Run(FanSpeed::Medium),
+                    Priority::Manual,
+                    Reason::Button,
+                ),
+                demand(
+                    FanCommand::Run(FanSpeed::High),
+                    Priority::Manual,
+                    Reason::Remote,
+                ),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        assert_eq!(winner.reason, Reason::Remote);
+    }
+}
@@ -0,0 +1,399 @@
+use super::{Demand, Priority, Reason, Trigger};
+use crate::{
+    buttons::{Button, ButtonEvent, ButtonPushDuration},
+    config::Config,
+    fan::{FanCommand, FanSpeed},
+    temperature::TemperatureReadings,
+};
+use core::{fmt, str::FromStr};
+
+/// What the overtemperature interlock does to the fan when it trips.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterlockAction {
+    /// Stop the fan, for a sensor on the motor or in the contactor enclosure
+    Stop,
+    /// Run the fan at high speed, for a sensor in the room
+    Boost,
+}
+
+impl InterlockAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stop => "stop",
+            Self::Boost => "boost",
+        }
+    }
+}
+
+impl FromStr for InterlockAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(Self::Stop),
+            "boost" => Ok(Self::Boost),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How many readings in a row the interlock sensor can be missing from before the interlock
+/// trips, so that a single failed read does not stop the fan.
+const LOST_SENSOR_READINGS: u8 = 3;
+
+/// Why the overtemperature interlock tripped.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FaultCause {
+    /// The sensor reached the limit
+    Overtemperature,
+    /// The sensor could not be read, so there is no telling whether it is too hot
+    SensorLost,
+}
+
+/// An overtemperature fault, which stays latched until it is acknowledged.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fault {
+    pub action: InterlockAction,
+    /// The sensor that tripped the interlock
+    pub sensor: u64,
+    pub cause: FaultCause,
+    /// The hottest the sensor has been since the fault latched (°C), `None` if it has not been
+    /// read since
+    pub peak_temperature: Option<f32>,
+    /// Set while the sensor is still over the limit, the fault cannot be acknowledged until it
+    /// has cooled down
+    pub over_limit: bool,
+    /// Set while the sensor cannot be read, the fault cannot be acknowledged until it can be read
+    /// again
+    pub sensor_lost: bool,
+}
+
+impl Fault {
+    /// Whether the fault can be acknowledged yet.
+    pub fn can_be_reset(&self) -> bool {
+        !self.over_limit && !self.sensor_lost
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cause {
+            FaultCause::Overtemperature => write!(f, "overheating, sensor {:016x}", self.sensor),
+            FaultCause::SensorLost => write!(f, "interlock sensor {:016x} lost", self.sensor),
+        }
+    }
+}
+
+/// Stops the fan (or runs it at high speed) when the designated sensor gets too hot, or can no
+/// longer be read, overriding everything else that wants the fan to do something.
+///
+/// The fault stays latched once the temperature has fallen, until someone acknowledges it by
+/// holding both buttons together.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OvertemperatureInterlock {
+    fault: Option<Fault>,
+    /// How many readings in a row the sensor has been missing from
+    missed_readings: u8,
+}
+
+impl Trigger for OvertemperatureInterlock {
+    fn demand(&self) -> Option<Demand> {
+        self.fault.as_ref().map(|fault| Demand {
+            command: match fault.action {
+                InterlockAction::Stop => FanCommand::Stop,
+                InterlockAction::Boost => FanCommand::Run(FanSpeed::High),
+            },
+            priority: Priority::Safety,
+            reason: Reason::Overtemperature,
+            time_remaining: None,
+        })
+    }
+}
+
+impl OvertemperatureInterlock {
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn handle_readings(&mut self, readings: &TemperatureReadings, config: &Config) -> bool {
+        // The interlock is disabled until a sensor is chosen, which also lets a fault from a
+        // sensor that has since been removed be acknowledged
+        let enabled = config.interlock_sensor != 0;
+
+        let temperature = readings
+            .iter()
+            .find(|sensor| enabled && sensor.address == config.interlock_sensor)
+            .and_then(|sensor| sensor.fresh_temperature());
+
+        self.missed_readings = match temperature {
+            None if enabled => self.missed_readings.saturating_add(1),
+            _ => 0,
+        };
+        let sensor_lost = self.missed_readings >= LOST_SENSOR_READINGS;
+
+        match (&mut self.fault, temperature) {
+            (None, Some(t)) if t >= config.interlock_limit => {
+                self.fault = Some(Fault {
+                    action: config.interlock_action,
+                    sensor: config.interlock_sensor,
+                    cause: FaultCause::Overtemperature,
+                    peak_temperature: Some(t),
+                    over_limit: true,
+                    sensor_lost: false,
+                });
+                true
+            }
+            (None, None) if sensor_lost => {
+                self.fault = Some(Fault {
+                    action: config.interlock_action,
+                    sensor: config.interlock_sensor,
+                    cause: FaultCause::SensorLost,
+                    peak_temperature: None,
+                    over_limit: false,
+                    sensor_lost: true,
+                });
+                true
+            }
+            (None, _) => false,
+            (Some(fault), t) => {
+                let before = fault.clone();
+
+                fault.over_limit = t.is_some_and(|t| t >= config.interlock_limit);
+                fault.sensor_lost = sensor_lost;
+                if let Some(t) = t {
+                    fault.peak_temperature = Some(fault.peak_temperature.map_or(t, |p| p.max(t)));
+                }
+
+                *fault != before
+            }
+        }
+    }
+
+    /// Clears the fault when both buttons are held together, once the sensor has cooled down.
+    pub fn handle_button(&mut self, event: &ButtonEvent) -> bool {
+        match (&self.fault, event) {
+            (
+                Some(fault),
+                ButtonEvent {
+                    button: Button::Both,
+                    push_duration: ButtonPushDuration::Long,
+                    ..
+                },
+            ) if fault.can_be_reset() => {
+                self.fault = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_time::Instant;
+
+    const SENSOR: u64 = 0x28ff_0000_0000_0001;
+
+    fn config(action: InterlockAction) -> Config {
+        let mut config = Config::DEFAULT;
+        config.interlock_sensor = SENSOR;
+        config.interlock_limit = 60.0;
+        config.interlock_action = action;
+        config
+    }
+
+    fn readings(interlock_sensor: f32, other_sensor: f32) -> TemperatureReadings {
+        let mut readings = TemperatureReadings::default();
+        readings.record(SENSOR, interlock_sensor, Instant::from_secs(0));
+        readings.record(0x28ff_0000_0000_0002, other_sensor, Instant::from_secs(0));
+        readings
+    }
+
+    fn acknowledge() -> ButtonEvent {
+        ButtonEvent {
+            button: Button::Both,
+            push_duration: ButtonPushDuration::Long,
+            clicks: 1,
+        }
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let mut interlock = OvertemperatureInterlock::default();
+        assert!(!interlock.handle_readings(&readings(100.0, 100.0), &Config::DEFAULT));
+        assert_eq!(interlock.demand(), None);
+    }
+
+    #[test]
+    fn only_the_chosen_sensor_trips() {
+        let config = config(InterlockAction::Stop);
+        let mut interlock = OvertemperatureInterlock::default();
+
+        assert!(!interlock.handle_readings(&readings(59.5, 100.0), &config));
+        assert_eq!(interlock.demand(), None);
+
+        assert!(interlock.handle_readings(&readings(60.0, 20.0), &config));
+        let demand = interlock.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Stop);
+        assert_eq!(demand.priority, Priority::Safety);
+        assert_eq!(demand.reason, Reason::Overtemperature);
+    }
+
+    #[test]
+    fn boost() {
+        let config = config(InterlockAction::Boost);
+        let mut interlock = OvertemperatureInterlock::default();
+
+        interlock.handle_readings(&readings(65.0, 20.0), &config);
+        assert_eq!(
+            interlock.demand().unwrap().command,
+            FanCommand::Run(FanSpeed::High)
+        );
+    }
+
+    #[test]
+    fn latches_until_acknowledged() {
+        let config = config(InterlockAction::Stop);
+        let mut interlock = OvertemperatureInterlock::default();
+        interlock.handle_readings(&readings(65.0, 20.0), &config);
+
+        // Can't be acknowledged while still too hot
+        assert!(!interlock.handle_button(&acknowledge()));
+
+        assert!(interlock.handle_readings(&readings(70.0, 20.0), &config));
+        assert!(interlock.handle_readings(&readings(30.0, 20.0), &config));
+        assert_eq!(
+            interlock.fault(),
+            Some(&Fault {
+                action: InterlockAction::Stop,
+                sensor: SENSOR,
+                cause: FaultCause::Overtemperature,
+                peak_temperature: Some(70.0),
+                over_limit: false,
+                sensor_lost: false,
+            })
+        );
+        assert!(interlock.demand().is_some());
+
+        // Only holding both buttons acknowledges it
+        for (button, push_duration) in [
+            (Button::Both, ButtonPushDuration::Short),
+            (Button::Both, ButtonPushDuration::Held),
+            (Button::Demand, ButtonPushDuration::Long),
+        ] {
+            let event = ButtonEvent {
+                button,
+                push_duration,
+                clicks: 1,
+            };
+            assert!(!interlock.handle_button(&event));
+        }
+
+        assert!(interlock.handle_button(&acknowledge()));
+        assert_eq!(interlock.demand(), None);
+        assert!(!interlock.handle_button(&acknowledge()));
+    }
+
+    #[test]
+    fn action_is_kept_when_config_changes() {
+        let mut config = config(InterlockAction::Stop);
+        let mut interlock = OvertemperatureInterlock::default();
+        interlock.handle_readings(&readings(65.0, 20.0), &config);
+
+        config.interlock_action = InterlockAction::Boost;
+        interlock.handle_readings(&readings(65.0, 20.0), &config);
+        assert_eq!(interlock.demand().unwrap().command, FanCommand::Stop);
+    }
+
+    fn only_other_sensor(other_sensor: f32) -> TemperatureReadings {
+        let mut readings = TemperatureReadings::default();
+        readings.record(0x28ff_0000_0000_0002, other_sensor, Instant::from_secs(0));
+        readings
+    }
+
+    #[test]
+    fn missing_sensor_trips() {
+        let config = config(InterlockAction::Stop);
+        let mut interlock = OvertemperatureInterlock::default();
+
+        // A single missed reading is not enough
+        for _ in 1..LOST_SENSOR_READINGS {
+            assert!(!interlock.handle_readings(&only_other_sensor(20.0), &config));
+        }
+        assert!(!interlock.handle_readings(&readings(20.0, 20.0), &config));
+        assert_eq!(interlock.demand(), None);
+
+        for _ in 1..LOST_SENSOR_READINGS {
+            assert!(!interlock.handle_readings(&only_other_sensor(20.0), &config));
+        }
+        assert!(interlock.handle_readings(&only_other_sensor(20.0), &config));
+        assert_eq!(
+            interlock.fault(),
+            Some(&Fault {
+                action: InterlockAction::Stop,
+                sensor: SENSOR,
+                cause: FaultCause::SensorLost,
+                peak_temperature: None,
+                over_limit: false,
+                sensor_lost: true,
+            })
+        );
+        assert_eq!(interlock.demand().unwrap().command, FanCommand::Stop);
+        assert_eq!(
+            std::format!("{}", interlock.fault().unwrap()),
+            "interlock sensor 28ff000000000001 lost"
+        );
+
+        // Can't be acknowledged until the sensor is back
+        assert!(!interlock.handle_button(&acknowledge()));
+        assert!(interlock.handle_readings(&readings(25.0, 20.0), &config));
+        assert_eq!(interlock.fault().unwrap().peak_temperature, Some(25.0));
+        assert!(interlock.handle_button(&acknowledge()));
+        assert_eq!(interlock.demand(), None);
+    }
+
+    #[test]
+    fn stale_sensor_trips() {
+        let config = config(InterlockAction::Boost);
+        let mut interlock = OvertemperatureInterlock::default();
+
+        let mut readings = readings(20.0, 20.0);
+        assert!(!interlock.handle_readings(&readings, &config));
+
+        readings.mark_all_stale();
+        readings.record(0x28ff_0000_0000_0002, 20.0, Instant::from_secs(1));
+        for _ in 1..LOST_SENSOR_READINGS {
+            assert!(!interlock.handle_readings(&readings, &config));
+        }
+        assert!(interlock.handle_readings(&readings, &config));
+        assert_eq!(interlock.fault().unwrap().cause, FaultCause::SensorLost);
+        assert_eq!(
+            interlock.demand().unwrap().command,
+            FanCommand::Run(FanSpeed::High)
+        );
+    }
+
+    #[test]
+    fn disabling_lets_a_lost_sensor_fault_be_acknowledged() {
+        let mut config = config(InterlockAction::Stop);
+        let mut interlock = OvertemperatureInterlock::default();
+        for _ in 0..LOST_SENSOR_READINGS {
+            interlock.handle_readings(&only_other_sensor(20.0), &config);
+        }
+        assert!(!interlock.handle_button(&acknowledge()));
+
+        config.interlock_sensor = 0;
+        assert!(interlock.handle_readings(&only_other_sensor(20.0), &config));
+        assert!(interlock.handle_button(&acknowledge()));
+
+        for _ in 0..LOST_SENSOR_READINGS {
+            assert!(!interlock.handle_readings(&only_other_sensor(20.0), &config));
+        }
+        assert_eq!(interlock.demand(), None);
+    }
+}
@@ -0,0 +1,119 @@
+use super::{Demand, Priority, Reason, Trigger};
+use crate::{
+    fan::{FanCommand, FanSpeed},
+    schedule::Schedule,
+    time::WeekTime,
+};
+use embassy_time::Duration;
+
+/// Runs the fan during the windows of time in the weekly schedule.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScheduleTrigger {
+    /// The speed the fan should be run at and for how much longer, `None` outside of the schedule
+    running: Option<(FanSpeed, Duration)>,
+    /// Set while there is a schedule but it cannot be followed, as the time is not known
+    clock_not_set: bool,
+}
+
+impl Trigger for ScheduleTrigger {
+    fn demand(&self) -> Option<Demand> {
+        self.running.clone().map(|(speed, time_remaining)| Demand {
+            command: FanCommand::Run(speed),
+            priority: Priority::Automatic,
+            reason: Reason::Schedule,
+            time_remaining: Some(time_remaining),
+        })
+    }
+}
+
+impl ScheduleTrigger {
+    /// Checks the schedule against the time of the week, this should be called once per second.
+    ///
+    /// `now` is `None` when the time is not known (e.g. the clock has not been set since power
+    /// was lost), in which case the schedule is not followed.
+    pub fn handle_time(&mut self, now: Option<WeekTime>, schedule: &Schedule) -> bool {
+        let running = now.and_then(|now| schedule.active(now));
+        let clock_not_set = now.is_none() && !schedule.entries().is_empty();
+
+        if running != self.running || clock_not_set != self.clock_not_set {
+            self.running = running;
+            self.clock_not_set = clock_not_set;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the schedule is not being followed because the clock has not been set.
+    pub fn clock_not_set(&self) -> bool {
+        self.clock_not_set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::Weekday;
+
+    fn at(hour: u8, minute: u8, second: u8) -> Option<WeekTime> {
+        WeekTime::new(Weekday::Wednesday, hour, minute, second)
+    }
+
+    #[test]
+    fn runs_during_schedule() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add("wed 18:00-22:00 high".parse().unwrap())
+            .unwrap();
+        let mut trigger = ScheduleTrigger::default();
+
+        assert!(!trigger.handle_time(at(17, 59, 59), &schedule));
+        assert_eq!(trigger.demand(), None);
+
+        assert!(trigger.handle_time(at(18, 0, 0), &schedule));
+        let demand = trigger.demand().unwrap();
+        assert_eq!(demand.command, FanCommand::Run(FanSpeed::High));
+        assert_eq!(demand.priority, Priority::Automatic);
+        assert_eq!(demand.reason, Reason::Schedule);
+        assert_eq!(
+            demand.time_remaining,
+            Some(Duration::from_secs(4 * 60 * 60))
+        );
+
+        // The time remaining counts down
+        assert!(trigger.handle_time(at(21, 59, 59), &schedule));
+        assert_eq!(
+            trigger.demand().unwrap().time_remaining,
+            Some(Duration::from_secs(1))
+        );
+
+        assert!(trigger.handle_time(at(22, 0, 0), &schedule));
+        assert_eq!(trigger.demand(), None);
+    }
+
+    #[test]
+    fn not_followed_without_the_time() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add("daily 00:00-23:59 low".parse().unwrap())
+            .unwrap();
+        let mut trigger = ScheduleTrigger::default();
+
+        assert!(trigger.handle_time(at(12, 0, 0), &schedule));
+        assert!(!trigger.clock_not_set());
+        assert!(trigger.handle_time(None, &schedule));
+        assert_eq!(trigger.demand(), None);
+        assert!(trigger.clock_not_set());
+
+        assert!(trigger.handle_time(at(12, 0, 1), &schedule));
+        assert!(!trigger.clock_not_set());
+    }
+
+    #[test]
+    fn clock_only_matters_with_a_schedule() {
+        let mut trigger = ScheduleTrigger::default();
+        assert!(!trigger.handle_time(None, &Schedule::default()));
+        assert!(!trigger.clock_not_set());
+    }
+}
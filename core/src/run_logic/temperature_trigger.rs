@@ -0,0 +1,128 @@
+use super::{Demand, Priority, Reason, Trigger};
+use crate::{
+    config::Config,
+    fan::{FanCommand, FanSpeed},
+    temperature::TemperatureReadings,
+};
+
+/// Runs the fan while it is too hot.
+///
+/// The sensor chosen for the overtemperature interlock is left out, it is somewhere that does not
+/// say how warm the room is (e.g. on the motor) and running the fan because of it would work
+/// against the interlock stopping the fan.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureTrigger {
+    /// The speed the fan should be run at, `None` when the temperature is fine
+    running: Option<FanSpeed>,
+}
+
+impl Trigger for TemperatureTrigger {
+    fn demand(&self) -> Option<Demand> {
+        self.running.clone().map(|speed| Demand {
+            command: FanCommand::Run(speed),
+            priority: Priority::Automatic,
+            reason: Reason::Temperature,
+            time_remaining: None,
+        })
+    }
+}
+
+impl TemperatureTrigger {
+    pub fn handle_readings(&mut self, readings: &TemperatureReadings, config: &Config) -> bool {
+        // Stale sensors are ignored, if all sensors are stale then the fan is not run
+        let hottest = readings
+            .iter()
+            .filter(|sensor| {
+                config.interlock_sensor == 0 || sensor.address != config.interlock_sensor
+            })
+            .filter_map(|sensor| sensor.fresh_temperature())
+            .reduce(f32::max);
+
+        let running = match hottest {
+            Some(t) if t >= config.temperature_on_threshold => true,
+            Some(t) if t < config.temperature_off_threshold => false,
+            // Between the thresholds, keep doing whatever we were doing
+            Some(_) => self.running.is_some(),
+            // Without any up to date sensors there is nothing to act on
+            None => false,
+        };
+
+        let running = running.then(|| config.temperature_run_speed.clone());
+
+        if running != self.running {
+            self.running = running;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_time::Instant;
+
+    fn readings(temperatures: &[f32]) -> TemperatureReadings {
+        let mut readings = TemperatureReadings::default();
+        for (address, t) in temperatures.iter().enumerate() {
+            readings.record(address as u64, *t, Instant::from_secs(0));
+        }
+        readings
+    }
+
+    #[test]
+    fn hysteresis() {
+        let config = Config::DEFAULT;
+        let mut trigger = TemperatureTrigger::default();
+
+        assert!(!trigger.handle_readings(&readings(&[20.0, 29.0]), &config));
+        assert_eq!(trigger.demand(), None);
+
+        // Any sensor over the on threshold starts the fan
+        assert!(trigger.handle_readings(&readings(&[20.0, 30.5]), &config));
+        assert_eq!(
+            trigger.demand().unwrap().command,
+            FanCommand::Run(config.temperature_run_speed.clone())
+        );
+
+        // Between the thresholds it keeps running
+        assert!(!trigger.handle_readings(&readings(&[20.0, 28.0]), &config));
+        assert!(trigger.demand().is_some());
+
+        // Stops once all sensors are below the off threshold
+        assert!(trigger.handle_readings(&readings(&[20.0, 26.0]), &config));
+        assert_eq!(trigger.demand(), None);
+
+        // Between the thresholds it stays stopped
+        assert!(!trigger.handle_readings(&readings(&[20.0, 28.0]), &config));
+        assert_eq!(trigger.demand(), None);
+    }
+
+    #[test]
+    fn interlock_sensor_is_ignored() {
+        let mut config = Config::DEFAULT;
+        config.interlock_sensor = 1;
+        let mut trigger = TemperatureTrigger::default();
+
+        assert!(!trigger.handle_readings(&readings(&[20.0, 80.0]), &config));
+        assert_eq!(trigger.demand(), None);
+
+        assert!(trigger.handle_readings(&readings(&[35.0, 80.0]), &config));
+        assert!(trigger.demand().is_some());
+    }
+
+    #[test]
+    fn stale_sensors_are_ignored() {
+        let config = Config::DEFAULT;
+        let mut trigger = TemperatureTrigger::default();
+
+        let mut readings = readings(&[35.0]);
+        assert!(trigger.handle_readings(&readings, &config));
+
+        readings.mark_all_stale();
+        assert!(trigger.handle_readings(&readings, &config));
+        assert_eq!(trigger.demand(), None);
+    }
+}
@@ -0,0 +1,339 @@
+//! Tracking of how long the fan has run for, so that it is known when the filters need changing.
+
+use crate::{
+    encoding::{Reader, Writer},
+    fan::FanSpeed,
+};
+use embassy_time::Duration;
+
+/// Incremented whenever the layout produced by [`RuntimeMeter::encode`] changes.
+pub const RUNTIME_VERSION: u16 = 1;
+
+/// The size of buffer needed by [`RuntimeMeter::encode`].
+pub const ENCODED_SIZE: usize = 128;
+
+/// The number of filter changes that are remembered.
+pub const MAX_FILTER_CHANGES: usize = 8;
+
+/// Filter life remaining (as a percentage) at or below which the filters are due for changing.
+pub const FILTER_DUE_PERCENT: u8 = 10;
+
+/// How hard the filters work at each speed, relative to high speed.
+fn speed_weight_percent(speed: &FanSpeed) -> u64 {
+    match speed {
+        FanSpeed::Low => 40,
+        FanSpeed::Medium => 70,
+        FanSpeed::High => 100,
+    }
+}
+
+/// Run time at each fan speed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpeedTotals {
+    pub low: Duration,
+    pub medium: Duration,
+    pub high: Duration,
+}
+
+impl SpeedTotals {
+    pub const ZERO: Self = Self {
+        low: Duration::from_ticks(0),
+        medium: Duration::from_ticks(0),
+        high: Duration::from_ticks(0),
+    };
+
+    fn add(&mut self, speed: &FanSpeed, duration: Duration) {
+        let total = match speed {
+            FanSpeed::Low => &mut self.low,
+            FanSpeed::Medium => &mut self.medium,
+            FanSpeed::High => &mut self.high,
+        };
+        *total += duration;
+    }
+
+    pub fn total(&self) -> Duration {
+        self.low + self.medium + self.high
+    }
+
+    /// The equivalent time at high speed.
+    pub fn weighted(&self) -> Duration {
+        let ticks = [
+            (FanSpeed::Low, self.low),
+            (FanSpeed::Medium, self.medium),
+            (FanSpeed::High, self.high),
+        ]
+        .iter()
+        .map(|(speed, d)| d.as_ticks() * speed_weight_percent(speed) / 100)
+        .sum();
+        Duration::from_ticks(ticks)
+    }
+
+    fn encode(&self, w: &mut Writer<'_>) {
+        for total in [self.low, self.medium, self.high] {
+            w.u32(total.as_secs() as u32);
+        }
+    }
+
+    fn decode(r: &mut Reader<'_>) -> Option<Self> {
+        Some(Self {
+            low: Duration::from_secs(r.u32()? as u64),
+            medium: Duration::from_secs(r.u32()? as u64),
+            high: Duration::from_secs(r.u32()? as u64),
+        })
+    }
+}
+
+/// A record of the filters being changed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterChange {
+    /// The total run time of the fan when the filters were changed
+    pub at: Duration,
+    /// The weighted run time of the filters that were taken out
+    pub filter_runtime: Duration,
+}
+
+/// How urgently the filters need changing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FilterStatus {
+    /// The filters have plenty of life left
+    #[default]
+    Ok,
+    /// The filters are nearly worn out
+    DueSoon,
+    /// The filters have been used for longer than their service interval
+    Overdue,
+}
+
+impl FilterStatus {
+    pub fn from_life_remaining(percent: u8) -> Self {
+        match percent {
+            0 => Self::Overdue,
+            p if p <= FILTER_DUE_PERCENT => Self::DueSoon,
+            _ => Self::Ok,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::DueSoon => "due",
+            Self::Overdue => "overdue",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RuntimeMeter {
+    /// Run time over the life of the controller
+    pub lifetime: SpeedTotals,
+    /// Run time since the filters were last changed
+    pub since_filter_change: SpeedTotals,
+    /// The most recent filter changes, oldest first
+    pub filter_changes: heapless::Vec<FilterChange, MAX_FILTER_CHANGES>,
+}
+
+impl Default for RuntimeMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuntimeMeter {
+    pub const fn new() -> Self {
+        Self {
+            lifetime: SpeedTotals::ZERO,
+            since_filter_change: SpeedTotals::ZERO,
+            filter_changes: heapless::Vec::new(),
+        }
+    }
+
+    /// Records the fan having run at `speed` for `duration`.
+    pub fn add(&mut self, speed: &FanSpeed, duration: Duration) {
+        self.lifetime.add(speed, duration);
+        self.since_filter_change.add(speed, duration);
+    }
+
+    /// How much of the filters' life is left, given how long they last at high speed.
+    pub fn filter_life_remaining_percent(&self, service_interval: Duration) -> u8 {
+        let used = self.since_filter_change.weighted().as_ticks() * 100
+            / service_interval.as_ticks().max(1);
+        100_u64.saturating_sub(used) as u8
+    }
+
+    /// How urgently the filters need changing, given how long they last at high speed.
+    pub fn filter_status(&self, service_interval: Duration) -> FilterStatus {
+        FilterStatus::from_life_remaining(self.filter_life_remaining_percent(service_interval))
+    }
+
+    /// Records the filters being changed, starting their run time again from zero.
+    pub fn filter_changed(&mut self) {
+        if self.filter_changes.is_full() {
+            self.filter_changes.remove(0);
+        }
+        let _ = self.filter_changes.push(FilterChange {
+            at: self.lifetime.total(),
+            filter_runtime: self.since_filter_change.weighted(),
+        });
+
+        self.since_filter_change = SpeedTotals::ZERO;
+    }
+
+    /// Serialises the meter into `buf`, returning the number of bytes used.
+    /// Run times are stored to the second.
+    pub fn encode(&self, buf: &mut [u8; ENCODED_SIZE]) -> usize {
+        let mut w = Writer::new(buf);
+        self.lifetime.encode(&mut w);
+        self.since_filter_change.encode(&mut w);
+        w.u8(self.filter_changes.len() as u8);
+        for change in &self.filter_changes {
+            w.u32(change.at.as_secs() as u32);
+            w.u32(change.filter_runtime.as_secs() as u32);
+        }
+        w.position()
+    }
+
+    /// Deserialises a meter previously produced by [`RuntimeMeter::encode`].
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(buf);
+        let mut meter = Self {
+            lifetime: SpeedTotals::decode(&mut r)?,
+            since_filter_change: SpeedTotals::decode(&mut r)?,
+            filter_changes: heapless::Vec::new(),
+        };
+        for _ in 0..r.u8()? {
+            let change = FilterChange {
+                at: Duration::from_secs(r.u32()? as u64),
+                filter_runtime: Duration::from_secs(r.u32()? as u64),
+            };
+            meter.filter_changes.push(change).ok()?;
+        }
+        r.is_empty().then_some(meter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn accumulates_per_speed() {
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::Low, HOUR);
+        meter.add(&FanSpeed::High, HOUR * 2);
+        meter.add(&FanSpeed::Low, HOUR);
+
+        assert_eq!(meter.lifetime.low, HOUR * 2);
+        assert_eq!(meter.lifetime.medium, Duration::from_ticks(0));
+        assert_eq!(meter.lifetime.high, HOUR * 2);
+        assert_eq!(meter.lifetime.total(), HOUR * 4);
+        assert_eq!(meter.since_filter_change, meter.lifetime);
+    }
+
+    #[test]
+    fn weighted_by_speed() {
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::Low, HOUR * 10);
+        meter.add(&FanSpeed::Medium, HOUR * 10);
+        meter.add(&FanSpeed::High, HOUR * 10);
+
+        assert_eq!(meter.since_filter_change.weighted(), HOUR * (4 + 7 + 10));
+    }
+
+    #[test]
+    fn filter_life() {
+        let service_interval = HOUR * 100;
+        let mut meter = RuntimeMeter::new();
+        assert_eq!(meter.filter_life_remaining_percent(service_interval), 100);
+
+        meter.add(&FanSpeed::High, HOUR * 25);
+        assert_eq!(meter.filter_life_remaining_percent(service_interval), 75);
+
+        // Low speed is easier on the filters
+        meter.add(&FanSpeed::Low, HOUR * 50);
+        assert_eq!(meter.filter_life_remaining_percent(service_interval), 55);
+
+        meter.add(&FanSpeed::High, HOUR * 100);
+        assert_eq!(meter.filter_life_remaining_percent(service_interval), 0);
+    }
+
+    #[test]
+    fn filter_status() {
+        let service_interval = HOUR * 100;
+        let mut meter = RuntimeMeter::new();
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::Ok);
+
+        meter.add(&FanSpeed::High, HOUR * 89);
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::Ok);
+
+        meter.add(&FanSpeed::High, HOUR);
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::DueSoon);
+
+        meter.add(&FanSpeed::High, HOUR * 9);
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::DueSoon);
+
+        meter.add(&FanSpeed::High, HOUR);
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::Overdue);
+
+        meter.filter_changed();
+        assert_eq!(meter.filter_status(service_interval), FilterStatus::Ok);
+    }
+
+    #[test]
+    fn filter_change() {
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::High, HOUR * 10);
+        meter.add(&FanSpeed::Low, HOUR * 10);
+        meter.filter_changed();
+
+        assert_eq!(meter.since_filter_change, SpeedTotals::ZERO);
+        assert_eq!(meter.lifetime.total(), HOUR * 20);
+        assert_eq!(
+            meter.filter_changes,
+            [FilterChange {
+                at: HOUR * 20,
+                filter_runtime: HOUR * 14,
+            }]
+        );
+        assert_eq!(meter.filter_life_remaining_percent(HOUR), 100);
+    }
+
+    #[test]
+    fn filter_change_history_is_limited() {
+        let mut meter = RuntimeMeter::new();
+        for _ in 0..MAX_FILTER_CHANGES + 2 {
+            meter.add(&FanSpeed::High, HOUR);
+            meter.filter_changed();
+        }
+
+        assert_eq!(meter.filter_changes.len(), MAX_FILTER_CHANGES);
+        assert_eq!(meter.filter_changes[0].at, HOUR * 3);
+        assert_eq!(
+            meter.filter_changes.last().unwrap().at,
+            HOUR * (MAX_FILTER_CHANGES as u32 + 2)
+        );
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut meter = RuntimeMeter::new();
+        for _ in 0..MAX_FILTER_CHANGES {
+            meter.add(&FanSpeed::Medium, HOUR * 3);
+            meter.filter_changed();
+        }
+        meter.add(&FanSpeed::Low, HOUR);
+        meter.add(&FanSpeed::High, Duration::from_secs(42));
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = meter.encode(&mut buf);
+
+        assert_eq!(RuntimeMeter::decode(&buf[..len]), Some(meter));
+        assert_eq!(RuntimeMeter::decode(&buf[..len - 1]), None);
+        assert_eq!(RuntimeMeter::decode(&buf[..len + 1]), None);
+    }
+}
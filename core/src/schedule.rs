@@ -0,0 +1,503 @@
+//! A weekly schedule of times to run the fan (e.g. while the workshop is open).
+//!
+//! Each entry is written as the days it applies to, a window of time and a fan speed, e.g.
+//! `mon-fri 18:00-22:00 high` or `sat,sun 10:00-16:00 low`.
+//! Days may be a single day, a range (`mon-fri`), a comma separated list of either, or `daily`.
+//! A window that ends earlier in the day than it starts carries on past midnight into the next
+//! day.
+
+use crate::{
+    encoding::{decode_speed, encode_speed, Reader, Writer},
+    fan::FanSpeed,
+    time::{parse_time_of_day, WeekTime, Weekday},
+};
+use core::{fmt, str::FromStr};
+use embassy_time::Duration;
+
+/// The most entries a schedule can have.
+pub const MAX_ENTRIES: usize = 8;
+
+/// Incremented whenever the layout produced by [`Schedule::encode`] changes.
+pub const SCHEDULE_VERSION: u16 = 1;
+
+/// The size of buffer needed by [`Schedule::encode`].
+pub const ENCODED_SIZE: usize = 1 + MAX_ENTRIES * 6;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScheduleError {
+    InvalidDays,
+    InvalidTime,
+    /// The window starts and ends at the same time
+    EmptyWindow,
+    InvalidSpeed,
+    MissingField,
+    TooManyFields,
+    Full,
+    NoSuchEntry,
+}
+
+impl ScheduleError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::InvalidDays => "invalid days (e.g. mon-fri, sat,sun or daily)",
+            Self::InvalidTime => "invalid times (e.g. 18:00-22:00)",
+            Self::EmptyWindow => "start and end times are the same",
+            Self::InvalidSpeed => "invalid speed (low, mid or high)",
+            Self::MissingField => "expected days, times and speed",
+            Self::TooManyFields => "too many fields",
+            Self::Full => "schedule is full",
+            Self::NoSuchEntry => "no such entry",
+        }
+    }
+}
+
+/// A set of days of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Days(u8);
+
+impl Days {
+    pub const ALL: Self = Self(0x7F);
+
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.index()) != 0
+    }
+
+    fn insert(&mut self, day: Weekday) {
+        self.0 |= 1 << day.index();
+    }
+
+    fn iter(self) -> impl Iterator<Item = Weekday> {
+        Weekday::ALL
+            .into_iter()
+            .filter(move |day| self.contains(*day))
+    }
+}
+
+impl FromStr for Days {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "daily" {
+            return Ok(Self::ALL);
+        }
+
+        let mut days = Self(0);
+
+        for part in s.split(',') {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let first: Weekday = first.parse().map_err(|_| ScheduleError::InvalidDays)?;
+            let last: Weekday = last.parse().map_err(|_| ScheduleError::InvalidDays)?;
+
+            // Ranges may wrap around the end of the week (e.g. `sat-mon`)
+            let count = (last.index() + 7 - first.index()) % 7 + 1;
+            for i in 0..count {
+                days.insert(Weekday::ALL[((first.index() + i) % 7) as usize]);
+            }
+        }
+
+        Ok(days)
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::ALL {
+            return f.write_str("daily");
+        }
+
+        // Runs of three or more days are written as a range
+        let mut separator = "";
+        let mut days = self.iter().peekable();
+        while let Some(first) = days.next() {
+            let mut last = first;
+            while let Some(next) = days.next_if(|day| day.index() == last.index() + 1) {
+                last = next;
+            }
+
+            match last.index() - first.index() {
+                0 => write!(f, "{separator}{}", first.name())?,
+                1 => write!(f, "{separator}{},{}", first.name(), last.name())?,
+                _ => write!(f, "{separator}{}-{}", first.name(), last.name())?,
+            }
+            separator = ",";
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the fan at a speed during a window of time on some days of the week.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScheduleEntry {
+    days: Days,
+    /// Minutes since midnight
+    start: u16,
+    /// Minutes since midnight, earlier than `start` if the window carries on past midnight
+    end: u16,
+    speed: FanSpeed,
+}
+
+impl ScheduleEntry {
+    /// Parses an entry given as separate fields (e.g. `mon-fri`, `18:00-22:00` and `high`).
+    pub fn from_fields(days: &str, window: &str, speed: &str) -> Result<Self, ScheduleError> {
+        let days = days.parse()?;
+
+        let (start, end) = window.split_once('-').ok_or(ScheduleError::InvalidTime)?;
+        let start = parse_minutes(start)?;
+        let end = parse_minutes(end)?;
+        if start == end {
+            return Err(ScheduleError::EmptyWindow);
+        }
+
+        let speed = speed.parse().map_err(|_| ScheduleError::InvalidSpeed)?;
+
+        Ok(Self {
+            days,
+            start,
+            end,
+            speed,
+        })
+    }
+
+    fn length(&self) -> u32 {
+        ((self.end + MINUTES_PER_DAY - self.start) % MINUTES_PER_DAY) as u32 * 60
+    }
+
+    /// How long is left of the window `now` is in, if it is in one.
+    pub fn remaining(&self, now: WeekTime) -> Option<Duration> {
+        self.days.iter().find_map(|day| {
+            let start = WeekTime::new(day, 0, 0, 0)?.seconds() + self.start as u32 * 60;
+
+            // The window may carry on past the end of the week into Monday morning
+            let elapsed =
+                (now.seconds() + WeekTime::SECS_PER_WEEK - start) % WeekTime::SECS_PER_WEEK;
+
+            (elapsed < self.length()).then(|| Duration::from_secs((self.length() - elapsed) as u64))
+        })
+    }
+}
+
+impl FromStr for ScheduleEntry {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let entry = Self::from_fields(
+            fields.next().ok_or(ScheduleError::MissingField)?,
+            fields.next().ok_or(ScheduleError::MissingField)?,
+            fields.next().ok_or(ScheduleError::MissingField)?,
+        )?;
+
+        if fields.next().is_some() {
+            Err(ScheduleError::TooManyFields)
+        } else {
+            Ok(entry)
+        }
+    }
+}
+
+impl fmt::Display for ScheduleEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02}:{:02}-{:02}:{:02} {}",
+            self.days,
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60,
+            self.speed.name()
+        )
+    }
+}
+
+fn parse_minutes(s: &str) -> Result<u16, ScheduleError> {
+    match parse_time_of_day(s) {
+        Some((hour, minute, 0)) if s.len() == 5 => Ok(hour as u16 * 60 + minute as u16),
+        _ => Err(ScheduleError::InvalidTime),
+    }
+}
+
+/// The times each week that the fan should run.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Schedule {
+    entries: heapless::Vec<ScheduleEntry, MAX_ENTRIES>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Schedule {
+    pub const fn new() -> Self {
+        Self {
+            entries: heapless::Vec::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[ScheduleEntry] {
+        &self.entries
+    }
+
+    pub fn add(&mut self, entry: ScheduleEntry) -> Result<(), ScheduleError> {
+        self.entries.push(entry).map_err(|_| ScheduleError::Full)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<ScheduleEntry, ScheduleError> {
+        if index < self.entries.len() {
+            Ok(self.entries.remove(index))
+        } else {
+            Err(ScheduleError::NoSuchEntry)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The speed the fan should be running at `now`, and for how long.
+    ///
+    /// Where entries overlap the highest speed wins.
+    pub fn active(&self, now: WeekTime) -> Option<(FanSpeed, Duration)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .remaining(now)
+                    .map(|remaining| (entry.speed.clone(), remaining))
+            })
+            .max()
+    }
+
+    /// Serialises the schedule into `buf`, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8; ENCODED_SIZE]) -> usize {
+        let mut w = Writer::new(buf);
+        w.u8(self.entries.len() as u8);
+        for entry in &self.entries {
+            w.u8(entry.days.0);
+            w.u16(entry.start);
+            w.u16(entry.end);
+            w.u8(encode_speed(&entry.speed));
+        }
+        w.position()
+    }
+
+    /// Deserialises a schedule previously produced by [`Schedule::encode`].
+    pub fn decode(version: u16, buf: &[u8]) -> Option<Self> {
+        if version != SCHEDULE_VERSION {
+            return None;
+        }
+
+        let mut r = Reader::new(buf);
+        let mut schedule = Self::default();
+
+        for _ in 0..r.u8()? {
+            let entry = ScheduleEntry {
+                days: Days(r.u8()?),
+                start: r.u16()?,
+                end: r.u16()?,
+                speed: decode_speed(r.u8()?)?,
+            };
+
+            let valid = entry.days.0 & !Days::ALL.0 == 0
+                && entry.start < MINUTES_PER_DAY
+                && entry.end < MINUTES_PER_DAY
+                && entry.start != entry.end;
+            if !valid {
+                return None;
+            }
+
+            schedule.add(entry).ok()?;
+        }
+
+        r.is_empty().then_some(schedule)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(s: &str) -> ScheduleEntry {
+        s.parse().unwrap()
+    }
+
+    fn at(day: Weekday, hour: u8, minute: u8) -> WeekTime {
+        WeekTime::new(day, hour, minute, 0).unwrap()
+    }
+
+    fn minutes(m: u64) -> Duration {
+        Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn parse_days() {
+        for (s, expected) in [
+            ("mon", "mon"),
+            ("mon-fri", "mon-fri"),
+            ("sat,sun", "sat,sun"),
+            ("sun,sat", "sat,sun"),
+            ("mon,tue,wed", "mon-wed"),
+            ("mon-wed,fri", "mon-wed,fri"),
+            ("fri-mon", "mon,fri-sun"),
+            ("daily", "daily"),
+            ("mon-sun", "daily"),
+        ] {
+            assert_eq!(format!("{}", s.parse::<Days>().unwrap()), expected, "{s}");
+        }
+
+        for s in ["", "monday", "mon-", "mon,,tue", "Mon", "weekdays"] {
+            assert_eq!(s.parse::<Days>(), Err(ScheduleError::InvalidDays), "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_entry() {
+        let e = entry("mon-fri 18:00-22:30 high");
+        assert_eq!(e.days.iter().count(), 5);
+        assert_eq!(e.start, 18 * 60);
+        assert_eq!(e.end, 22 * 60 + 30);
+        assert_eq!(e.speed, FanSpeed::High);
+        assert_eq!(format!("{e}"), "mon-fri 18:00-22:30 high");
+
+        assert_eq!(
+            format!("{}", entry("  daily   22:00-02:00 medium ")),
+            "daily 22:00-02:00 mid"
+        );
+    }
+
+    #[test]
+    fn parse_entry_errors() {
+        for (s, error) in [
+            ("", ScheduleError::MissingField),
+            ("mon-fri 18:00-22:00", ScheduleError::MissingField),
+            ("mon-fri 18:00-22:00 high now", ScheduleError::TooManyFields),
+            ("someday 18:00-22:00 high", ScheduleError::InvalidDays),
+            ("mon 18:00 high", ScheduleError::InvalidTime),
+            ("mon 18:00-24:00 high", ScheduleError::InvalidTime),
+            ("mon 8:00-12:00 high", ScheduleError::InvalidTime),
+            ("mon 18:00:30-22:00 high", ScheduleError::InvalidTime),
+            ("mon 18:00-18:00 high", ScheduleError::EmptyWindow),
+            ("mon 18:00-22:00 max", ScheduleError::InvalidSpeed),
+        ] {
+            assert_eq!(s.parse::<ScheduleEntry>(), Err(error), "{s}");
+        }
+    }
+
+    #[test]
+    fn entry_remaining() {
+        let e = entry("tue,thu 18:00-22:00 high");
+
+        assert_eq!(e.remaining(at(Weekday::Tuesday, 17, 59)), None);
+        assert_eq!(
+            e.remaining(at(Weekday::Tuesday, 18, 0)),
+            Some(minutes(4 * 60))
+        );
+        assert_eq!(
+            e.remaining(at(Weekday::Thursday, 21, 45)),
+            Some(minutes(15))
+        );
+        assert_eq!(e.remaining(at(Weekday::Thursday, 22, 0)), None);
+        assert_eq!(e.remaining(at(Weekday::Wednesday, 19, 0)), None);
+    }
+
+    #[test]
+    fn window_past_midnight() {
+        let e = entry("sun 23:00-01:00 low");
+
+        assert_eq!(e.remaining(at(Weekday::Sunday, 23, 30)), Some(minutes(90)));
+        // Into Monday, at the start of the next week
+        assert_eq!(e.remaining(at(Weekday::Monday, 0, 30)), Some(minutes(30)));
+        assert_eq!(e.remaining(at(Weekday::Monday, 1, 0)), None);
+        assert_eq!(e.remaining(at(Weekday::Saturday, 23, 30)), None);
+    }
+
+    #[test]
+    fn highest_speed_wins() {
+        let mut schedule = Schedule::default();
+        assert_eq!(schedule.active(at(Weekday::Monday, 19, 0)), None);
+
+        schedule.add(entry("daily 09:00-21:00 low")).unwrap();
+        schedule.add(entry("mon 18:00-20:00 high")).unwrap();
+
+        assert_eq!(
+            schedule.active(at(Weekday::Monday, 17, 0)),
+            Some((FanSpeed::Low, minutes(4 * 60)))
+        );
+        assert_eq!(
+            schedule.active(at(Weekday::Monday, 19, 0)),
+            Some((FanSpeed::High, minutes(60)))
+        );
+        assert_eq!(
+            schedule.active(at(Weekday::Tuesday, 19, 0)),
+            Some((FanSpeed::Low, minutes(2 * 60)))
+        );
+        assert_eq!(schedule.active(at(Weekday::Tuesday, 21, 0)), None);
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut schedule = Schedule::default();
+        for _ in 0..MAX_ENTRIES {
+            schedule.add(entry("mon 18:00-22:00 high")).unwrap();
+        }
+        assert_eq!(
+            schedule.add(entry("tue 18:00-22:00 high")),
+            Err(ScheduleError::Full)
+        );
+
+        assert!(schedule.remove(MAX_ENTRIES - 1).is_ok());
+        assert_eq!(
+            schedule.remove(MAX_ENTRIES - 1),
+            Err(ScheduleError::NoSuchEntry)
+        );
+
+        schedule.clear();
+        assert!(schedule.entries().is_empty());
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut schedule = Schedule::default();
+        schedule.add(entry("mon-fri 18:00-22:00 high")).unwrap();
+        schedule.add(entry("sun 23:00-01:00 low")).unwrap();
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = schedule.encode(&mut buf);
+        assert_eq!(
+            Schedule::decode(SCHEDULE_VERSION, &buf[..len]),
+            Some(schedule)
+        );
+
+        // Full schedules fit
+        let mut schedule = Schedule::default();
+        for _ in 0..MAX_ENTRIES {
+            schedule.add(entry("daily 00:00-23:59 mid")).unwrap();
+        }
+        assert_eq!(schedule.encode(&mut buf), ENCODED_SIZE);
+    }
+
+    #[test]
+    fn decode_rejects_invalid() {
+        let mut schedule = Schedule::default();
+        schedule.add(entry("mon 18:00-22:00 high")).unwrap();
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = schedule.encode(&mut buf);
+
+        assert_eq!(Schedule::decode(SCHEDULE_VERSION + 1, &buf[..len]), None);
+        assert_eq!(Schedule::decode(SCHEDULE_VERSION, &buf[..len - 1]), None);
+
+        // End time after midnight
+        buf[4] = 0xFF;
+        assert_eq!(Schedule::decode(SCHEDULE_VERSION, &buf[..len]), None);
+    }
+}
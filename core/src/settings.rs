@@ -0,0 +1,461 @@
+//! Editing the configuration with the buttons, as done on the settings screen.
+
+use crate::{
+    buttons::{Button, ButtonEvent, ButtonPushDuration},
+    config::{Config, ConfigValue},
+    fan::FanSpeed,
+};
+
+/// How the value of a setting changes with each push.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    Number { step: u64, min: u64, max: u64 },
+    Temperature { step: f32, min: f32, max: f32 },
+    Speed,
+}
+
+/// A configuration value that can be changed with the buttons.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Setting {
+    pub label: &'static str,
+    pub key: &'static str,
+    pub unit: &'static str,
+    pub adjustment: Adjustment,
+}
+
+/// The settings that can be changed with the buttons, in the order they are shown.
+///
+/// Anything that could leave the controller hard to use (e.g. contactor timings) is left to the
+/// serial console.
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        label: "Run time",
+        key: "run_minutes",
+        unit: " min",
+        adjustment: Adjustment::Number {
+            step: 5,
+            min: 5,
+            max: 240,
+        },
+    },
+    Setting {
+        label: "Add per push",
+        key: "run_increment_minutes",
+        unit: " min",
+        adjustment: Adjustment::Number {
+            step: 5,
+            min: 0,
+            max: 60,
+        },
+    },
+    Setting {
+        label: "Max run time",
+        key: "max_run_minutes",
+        unit: " min",
+        adjustment: Adjustment::Number {
+            step: 30,
+            min: 30,
+            max: 480,
+        },
+    },
+    Setting {
+        label: "Purge time",
+        key: "purge_minutes",
+        unit: " min",
+        adjustment: Adjustment::Number {
+            step: 1,
+            min: 0,
+            max: 30,
+        },
+    },
+    Setting {
+        label: "Cont. limit",
+        key: "continuous_max_hours",
+        unit: " h",
+        adjustment: Adjustment::Number {
+            step: 1,
+            min: 0,
+            max: 24,
+        },
+    },
+    Setting {
+        label: "Start speed",
+        key: "start_speed",
+        unit: "",
+        adjustment: Adjustment::Speed,
+    },
+    Setting {
+        label: "Fan on at",
+        key: "temperature_on_c",
+        unit: "C",
+        adjustment: Adjustment::Temperature {
+            step: 0.5,
+            min: 10.0,
+            max: 60.0,
+        },
+    },
+    Setting {
+        label: "Fan off at",
+        key: "temperature_off_c",
+        unit: "C",
+        adjustment: Adjustment::Temperature {
+            step: 0.5,
+            min: 10.0,
+            max: 60.0,
+        },
+    },
+    Setting {
+        label: "Temp. speed",
+        key: "temperature_speed",
+        unit: "",
+        adjustment: Adjustment::Speed,
+    },
+    Setting {
+        label: "Backlight idle",
+        key: "backlight_idle_percent",
+        unit: "%",
+        adjustment: Adjustment::Number {
+            step: 10,
+            min: 10,
+            max: 100,
+        },
+    },
+    Setting {
+        label: "Backlight run",
+        key: "backlight_running_percent",
+        unit: "%",
+        adjustment: Adjustment::Number {
+            step: 10,
+            min: 10,
+            max: 100,
+        },
+    },
+    Setting {
+        label: "Push",
+        key: "push_threshold_ms",
+        unit: " ms",
+        adjustment: Adjustment::Number {
+            step: 25,
+            min: 25,
+            max: 500,
+        },
+    },
+    Setting {
+        label: "Long push",
+        key: "long_push_threshold_ms",
+        unit: " ms",
+        adjustment: Adjustment::Number {
+            step: 250,
+            min: 500,
+            max: 10_000,
+        },
+    },
+];
+
+impl Setting {
+    pub fn value(&self, config: &Config) -> Option<ConfigValue> {
+        config.get_value(self.key).ok()
+    }
+
+    /// Changes the value by one step, without going outside of the limits of the setting.
+    ///
+    /// A value that is already outside of the limits (e.g. set with the serial console) is only
+    /// moved towards them.
+    pub fn adjust(&self, config: &mut Config, up: bool) {
+        let value = match (self.adjustment, self.value(config)) {
+            (Adjustment::Number { step, min, max }, Some(ConfigValue::Number(v))) => {
+                ConfigValue::Number(match up {
+                    true if v < max => v.saturating_add(step).min(max),
+                    false if v > min => v.saturating_sub(step).max(min),
+                    _ => v,
+                })
+            }
+            (Adjustment::Temperature { step, min, max }, Some(ConfigValue::Temperature(v))) => {
+                ConfigValue::Temperature(match up {
+                    true if v < max => (v + step).min(max),
+                    false if v > min => (v - step).max(min),
+                    _ => v,
+                })
+            }
+            (Adjustment::Speed, Some(ConfigValue::Speed(v))) => ConfigValue::Speed(match up {
+                true => match v {
+                    FanSpeed::Low => FanSpeed::Medium,
+                    FanSpeed::Medium => FanSpeed::High,
+                    FanSpeed::High => FanSpeed::Low,
+                },
+                false => match v {
+                    FanSpeed::Low => FanSpeed::High,
+                    FanSpeed::Medium => FanSpeed::Low,
+                    FanSpeed::High => FanSpeed::Medium,
+                },
+            }),
+            _ => return,
+        };
+
+        let _ = config.set(self.key, value);
+    }
+}
+
+/// What has happened to the edits most recently.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EditStatus {
+    #[default]
+    Unchanged,
+    Modified,
+    Saved,
+    /// Saving was refused as the edited configuration does not make sense
+    Invalid,
+}
+
+/// A copy of the configuration that is being edited with the buttons.
+///
+/// Edits are made to the copy, so nothing changes until they are saved and the fan carries on
+/// using the configuration it already had.
+///
+/// A short push of the speed button selects the next setting, after the last setting comes the
+/// option to save. A short push of the demand button increases the selected setting (or saves),
+/// a long push decreases it (or discards all edits).
+#[derive(Debug, Default, Clone)]
+pub struct SettingsEditor {
+    /// Index into [`SETTINGS`], one past the end is the option to save
+    selected: usize,
+    draft: Option<Config>,
+    status: EditStatus,
+}
+
+impl SettingsEditor {
+    /// The selected setting, or `None` when the option to save is selected.
+    pub fn selected(&self) -> Option<&'static Setting> {
+        SETTINGS.get(self.selected)
+    }
+
+    /// The position of the selection, counting the option to save as the last item.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn status(&self) -> EditStatus {
+        self.status
+    }
+
+    /// The configuration as it would be if the edits were saved.
+    pub fn draft<'a>(&'a self, current: &'a Config) -> &'a Config {
+        self.draft.as_ref().unwrap_or(current)
+    }
+
+    /// Handles a button push, returning the configuration to save (if it is time to save it).
+    pub fn handle_button(&mut self, event: &ButtonEvent, current: &Config) -> Option<Config> {
+        match (self.selected(), &event.button, &event.push_duration) {
+            (_, Button::Speed, ButtonPushDuration::Short) => {
+                self.selected = (self.selected + 1) % (SETTINGS.len() + 1);
+            }
+            (
+                Some(setting),
+                Button::Demand,
+                duration @ (ButtonPushDuration::Short | ButtonPushDuration::Long),
+            ) => {
+                let draft = self.draft.get_or_insert_with(|| current.clone());
+                setting.adjust(draft, *duration == ButtonPushDuration::Short);
+                self.status = EditStatus::Modified;
+            }
+            (None, Button::Demand, ButtonPushDuration::Short) => match self.draft.take() {
+                Some(draft) if draft.is_valid() => {
+                    self.status = EditStatus::Saved;
+                    return Some(draft);
+                }
+                Some(draft) => {
+                    self.draft = Some(draft);
+                    self.status = EditStatus::Invalid;
+                }
+                None => {}
+            },
+            (None, Button::Demand, ButtonPushDuration::Long) => {
+                self.draft = None;
+                self.status = EditStatus::Unchanged;
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Throws away any edits that have not been saved, and goes back to the first setting.
+    pub fn discard(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn push(button: Button, push_duration: ButtonPushDuration) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            push_duration,
+            clicks: 1,
+        }
+    }
+
+    fn setting(key: &str) -> &'static Setting {
+        SETTINGS.iter().find(|s| s.key == key).unwrap()
+    }
+
+    /// Selects a setting (or the option to save with `None`) from wherever the selection is.
+    fn select(editor: &mut SettingsEditor, key: Option<&str>, config: &Config) {
+        while editor.selected().map(|s| s.key) != key {
+            editor.handle_button(&push(Button::Speed, ButtonPushDuration::Short), config);
+        }
+    }
+
+    #[test]
+    fn every_setting_exists_and_is_within_its_limits() {
+        for setting in SETTINGS {
+            match (setting.adjustment, setting.value(&Config::DEFAULT)) {
+                (Adjustment::Number { min, max, .. }, Some(ConfigValue::Number(v))) => {
+                    assert!((min..=max).contains(&v), "{}", setting.key)
+                }
+                (Adjustment::Temperature { min, max, .. }, Some(ConfigValue::Temperature(v))) => {
+                    assert!((min..=max).contains(&v), "{}", setting.key)
+                }
+                (Adjustment::Speed, Some(ConfigValue::Speed(_))) => {}
+                _ => panic!("{} does not match its adjustment", setting.key),
+            }
+        }
+    }
+
+    #[test]
+    fn adjust_stays_within_limits() {
+        let mut config = Config::DEFAULT;
+        let run_time = setting("run_minutes");
+
+        run_time.adjust(&mut config, true);
+        assert_eq!(config.run_duration.as_secs(), 25 * 60);
+
+        for _ in 0..100 {
+            run_time.adjust(&mut config, true);
+        }
+        assert_eq!(config.run_duration.as_secs(), 240 * 60);
+
+        for _ in 0..100 {
+            run_time.adjust(&mut config, false);
+        }
+        assert_eq!(config.run_duration.as_secs(), 5 * 60);
+
+        let off = setting("temperature_off_c");
+        off.adjust(&mut config, false);
+        assert_eq!(config.temperature_off_threshold, 26.5);
+
+        // Already outside of the limits, so only moves back towards them
+        config.backlight_idle_percent = 0;
+        setting("backlight_idle_percent").adjust(&mut config, false);
+        assert_eq!(config.backlight_idle_percent, 0);
+        setting("backlight_idle_percent").adjust(&mut config, true);
+        assert_eq!(config.backlight_idle_percent, 10);
+    }
+
+    #[test]
+    fn adjust_speed_wraps_around() {
+        let mut config = Config::DEFAULT;
+        let start_speed = setting("start_speed");
+
+        start_speed.adjust(&mut config, false);
+        assert_eq!(config.start_speed, FanSpeed::High);
+        start_speed.adjust(&mut config, true);
+        assert_eq!(config.start_speed, FanSpeed::Low);
+        start_speed.adjust(&mut config, true);
+        assert_eq!(config.start_speed, FanSpeed::Medium);
+    }
+
+    #[test]
+    fn speed_button_selects_settings_then_save() {
+        let config = Config::DEFAULT;
+        let mut editor = SettingsEditor::default();
+        let next = push(Button::Speed, ButtonPushDuration::Short);
+
+        for setting in &SETTINGS[1..] {
+            editor.handle_button(&next, &config);
+            assert_eq!(editor.selected(), Some(setting));
+        }
+
+        editor.handle_button(&next, &config);
+        assert_eq!(editor.selected(), None);
+        assert_eq!(editor.selected_index(), SETTINGS.len());
+
+        editor.handle_button(&next, &config);
+        assert_eq!(editor.selected(), Some(&SETTINGS[0]));
+    }
+
+    #[test]
+    fn edits_are_only_returned_once_saved() {
+        let config = Config::DEFAULT;
+        let mut editor = SettingsEditor::default();
+        let up = push(Button::Demand, ButtonPushDuration::Short);
+        let down = push(Button::Demand, ButtonPushDuration::Long);
+
+        // Nothing to save
+        select(&mut editor, None, &config);
+        assert_eq!(editor.handle_button(&up, &config), None);
+        assert_eq!(editor.status(), EditStatus::Unchanged);
+
+        select(&mut editor, Some("backlight_idle_percent"), &config);
+        assert_eq!(editor.handle_button(&up, &config), None);
+        assert_eq!(editor.handle_button(&up, &config), None);
+        assert_eq!(editor.handle_button(&down, &config), None);
+        assert_eq!(editor.status(), EditStatus::Modified);
+        assert_eq!(editor.draft(&config).backlight_idle_percent, 30);
+
+        select(&mut editor, None, &config);
+        let saved = editor.handle_button(&up, &config).unwrap();
+        assert_eq!(saved.backlight_idle_percent, 30);
+        assert_eq!(editor.status(), EditStatus::Saved);
+
+        // Further edits start from the saved configuration
+        assert_eq!(editor.draft(&saved), &saved);
+    }
+
+    #[test]
+    fn invalid_edits_are_not_saved() {
+        let config = Config::DEFAULT;
+        let mut editor = SettingsEditor::default();
+        let up = push(Button::Demand, ButtonPushDuration::Short);
+
+        select(&mut editor, Some("temperature_off_c"), &config);
+        for _ in 0..6 {
+            editor.handle_button(&up, &config);
+        }
+        assert_eq!(editor.draft(&config).temperature_off_threshold, 30.0);
+
+        select(&mut editor, None, &config);
+        assert_eq!(editor.handle_button(&up, &config), None);
+        assert_eq!(editor.status(), EditStatus::Invalid);
+
+        // The edits are kept so they can be corrected
+        select(&mut editor, Some("temperature_on_c"), &config);
+        editor.handle_button(&up, &config);
+        select(&mut editor, None, &config);
+        let saved = editor.handle_button(&up, &config).unwrap();
+        assert_eq!(saved.temperature_on_threshold, 30.5);
+        assert_eq!(saved.temperature_off_threshold, 30.0);
+    }
+
+    #[test]
+    fn edits_can_be_discarded() {
+        let config = Config::DEFAULT;
+        let mut editor = SettingsEditor::default();
+
+        editor.handle_button(&push(Button::Demand, ButtonPushDuration::Short), &config);
+        select(&mut editor, None, &config);
+        editor.handle_button(&push(Button::Demand, ButtonPushDuration::Long), &config);
+        assert_eq!(editor.status(), EditStatus::Unchanged);
+        assert_eq!(editor.draft(&config), &config);
+
+        editor.handle_button(&push(Button::Speed, ButtonPushDuration::Short), &config);
+        editor.handle_button(&push(Button::Demand, ButtonPushDuration::Short), &config);
+        editor.discard();
+        assert_eq!(editor.status(), EditStatus::Unchanged);
+        assert_eq!(editor.draft(&config), &config);
+        assert_eq!(editor.selected(), Some(&SETTINGS[0]));
+    }
+}
@@ -0,0 +1,164 @@
+use embassy_time::Instant;
+
+/// The maximum number of temperature sensors that will be reported on.
+pub const MAX_SENSORS: usize = 8;
+
+/// The latest known state of every temperature sensor that has been seen on the bus.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureReadings {
+    sensors: heapless::Vec<SensorReading, MAX_SENSORS>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorReading {
+    pub address: u64,
+    /// The most recent successfully read temperature in °C.
+    pub temperature: Option<f32>,
+    /// When `temperature` was read.
+    pub timestamp: Option<Instant>,
+    /// The total number of failed reads from this sensor.
+    pub read_errors: u32,
+    /// Set when the sensor was either not found in the last search or could not be read,
+    /// in which case `temperature` is the last known value (if any).
+    pub stale: bool,
+}
+
+impl SensorReading {
+    fn new(address: u64) -> Self {
+        Self {
+            address,
+            temperature: None,
+            timestamp: None,
+            read_errors: 0,
+            stale: true,
+        }
+    }
+
+    /// The temperature of this sensor, only if it is up to date.
+    pub fn fresh_temperature(&self) -> Option<f32> {
+        if self.stale {
+            None
+        } else {
+            self.temperature
+        }
+    }
+}
+
+impl TemperatureReadings {
+    pub fn iter(&self) -> impl Iterator<Item = &SensorReading> {
+        self.sensors.iter()
+    }
+
+    /// The highest temperature reported by any sensor that is not stale.
+    pub fn hottest(&self) -> Option<f32> {
+        self.sensors
+            .iter()
+            .filter_map(SensorReading::fresh_temperature)
+            .reduce(f32::max)
+    }
+
+    /// Marks every sensor as stale, this should be done before each search of the bus.
+    pub fn mark_all_stale(&mut self) {
+        for sensor in self.sensors.iter_mut() {
+            sensor.stale = true;
+        }
+    }
+
+    /// Gets the entry for a sensor, adding it if it has not been seen before.
+    /// When the table is full the entry for a stale sensor is given up to make space.
+    fn entry(&mut self, address: u64) -> Option<&mut SensorReading> {
+        let idx = match self.sensors.iter().position(|s| s.address == address) {
+            Some(idx) => idx,
+            None => {
+                if self.sensors.is_full() {
+                    let stale = self.sensors.iter().position(|s| s.stale)?;
+                    self.sensors.swap_remove(stale);
+                }
+                self.sensors
+                    .push(SensorReading::new(address))
+                    .expect("there should be space in the table");
+                self.sensors.len() - 1
+            }
+        };
+
+        Some(&mut self.sensors[idx])
+    }
+
+    /// Records a successful read of a sensor.
+    pub fn record(&mut self, address: u64, temperature: f32, timestamp: Instant) -> bool {
+        match self.entry(address) {
+            Some(entry) => {
+                entry.temperature = Some(temperature);
+                entry.timestamp = Some(timestamp);
+                entry.stale = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records a failed read of a sensor.
+    pub fn record_error(&mut self, address: u64) -> bool {
+        match self.entry(address) {
+            Some(entry) => {
+                entry.read_errors = entry.read_errors.saturating_add(1);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_sensors_become_stale() {
+        let mut readings = TemperatureReadings::default();
+        readings.record(1, 20.0, Instant::from_secs(0));
+        readings.record(2, 25.0, Instant::from_secs(0));
+        assert_eq!(readings.hottest(), Some(25.0));
+
+        // Sensor 2 is not found in the next search
+        readings.mark_all_stale();
+        readings.record(1, 21.0, Instant::from_secs(10));
+
+        assert_eq!(readings.iter().count(), 2);
+        assert_eq!(readings.hottest(), Some(21.0));
+
+        let sensor_2 = readings.iter().find(|s| s.address == 2).unwrap();
+        assert!(sensor_2.stale);
+        assert_eq!(sensor_2.temperature, Some(25.0));
+    }
+
+    #[test]
+    fn read_errors_are_counted() {
+        let mut readings = TemperatureReadings::default();
+        readings.mark_all_stale();
+        readings.record_error(1);
+        readings.record_error(1);
+
+        let sensor = readings.iter().next().unwrap();
+        assert_eq!(sensor.read_errors, 2);
+        assert!(sensor.stale);
+        assert_eq!(readings.hottest(), None);
+    }
+
+    #[test]
+    fn full_table_replaces_stale_sensors() {
+        let mut readings = TemperatureReadings::default();
+        for address in 0..MAX_SENSORS as u64 {
+            assert!(readings.record(address, 20.0, Instant::from_secs(0)));
+        }
+
+        // No space and nothing is stale
+        assert!(!readings.record(100, 20.0, Instant::from_secs(0)));
+
+        readings.mark_all_stale();
+        assert!(readings.record(100, 20.0, Instant::from_secs(10)));
+        assert_eq!(readings.iter().count(), MAX_SENSORS);
+    }
+}
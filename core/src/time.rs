@@ -0,0 +1,314 @@
+use core::{fmt, fmt::Write, str::FromStr};
+use embassy_time::Duration;
+
+const SECS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Formats a duration as minutes and seconds (e.g. `05:30`).
+pub fn format_minutes_seconds(duration: Duration) -> heapless::String<16> {
+    let seconds = duration.as_secs();
+    let minutes = seconds / 60;
+    let seconds = seconds % 60;
+
+    let mut s = heapless::String::new();
+    s.write_fmt(format_args!("{:02}:{:02}", minutes, seconds))
+        .unwrap();
+    s
+}
+
+/// Formats a duration as a whole number of hours (e.g. `123h`).
+pub fn format_hours(duration: Duration) -> heapless::String<16> {
+    let mut s = heapless::String::new();
+    s.write_fmt(format_args!("{}h", duration.as_secs() / (60 * 60)))
+        .unwrap();
+    s
+}
+
+/// A day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    pub const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+
+    /// The number of days since Monday.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Monday => "mon",
+            Self::Tuesday => "tue",
+            Self::Wednesday => "wed",
+            Self::Thursday => "thu",
+            Self::Friday => "fri",
+            Self::Saturday => "sat",
+            Self::Sunday => "sun",
+        }
+    }
+}
+
+impl FromStr for Weekday {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|day| s == day.name()).ok_or(())
+    }
+}
+
+/// A time within the week, to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WeekTime {
+    /// Seconds since midnight at the start of Monday
+    seconds: u32,
+}
+
+impl WeekTime {
+    pub const SECS_PER_WEEK: u32 = 7 * SECS_PER_DAY;
+
+    pub fn new(day: Weekday, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        (hour < 24 && minute < 60 && second < 60).then(|| Self {
+            seconds: day.index() as u32 * SECS_PER_DAY
+                + hour as u32 * 60 * 60
+                + minute as u32 * 60
+                + second as u32,
+        })
+    }
+
+    pub fn weekday(self) -> Weekday {
+        Weekday::ALL[(self.seconds / SECS_PER_DAY) as usize]
+    }
+
+    /// Seconds since midnight at the start of Monday.
+    pub fn seconds(self) -> u32 {
+        self.seconds
+    }
+}
+
+/// A calendar date and time of day, as kept by the real time clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Parses a date (e.g. `2024-10-17`) and a time of day (e.g. `19:30` or `19:30:15`).
+    pub fn parse(date: &str, time: &str) -> Option<Self> {
+        let mut date_parts = date.split('-');
+        let year = date_parts.next()?.parse().ok()?;
+        let month = date_parts.next()?.parse().ok()?;
+        let day = date_parts.next()?.parse().ok()?;
+        if date_parts.next().is_some() {
+            return None;
+        }
+
+        let (hour, minute, second) = parse_time_of_day(time)?;
+
+        let datetime = Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        datetime.is_valid().then_some(datetime)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let days_in_month = match self.month {
+            2 if self.is_leap_year() => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => 0,
+        };
+
+        (2000..=4095).contains(&self.year)
+            && (1..=days_in_month).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    fn is_leap_year(&self) -> bool {
+        (self.year % 4 == 0 && self.year % 100 != 0) || self.year % 400 == 0
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // Sakamoto's method, which counts from Sunday
+        const OFFSETS: [u16; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+
+        let year = if self.month < 3 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let from_sunday = (year + year / 4 - year / 100
+            + year / 400
+            + OFFSETS[self.month as usize - 1]
+            + self.day as u16)
+            % 7;
+
+        Weekday::ALL[((from_sunday + 6) % 7) as usize]
+    }
+
+    pub fn week_time(&self) -> WeekTime {
+        WeekTime::new(self.weekday(), self.hour, self.minute, self.second)
+            .expect("time of day should be valid")
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} ({})",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.weekday().name()
+        )
+    }
+}
+
+/// Parses a time of day given as hours and minutes, with optional seconds (e.g. `19:30` or
+/// `19:30:15`).
+pub(crate) fn parse_time_of_day(s: &str) -> Option<(u8, u8, u8)> {
+    let mut parts = s.split(':');
+    let hour = parse_two_digits(parts.next()?)?;
+    let minute = parse_two_digits(parts.next()?)?;
+    let second = match parts.next() {
+        Some(second) => parse_two_digits(second)?,
+        None => 0,
+    };
+    if parts.next().is_some() || hour >= 24 || minute >= 60 || second >= 60 {
+        return None;
+    }
+
+    Some((hour, minute, second))
+}
+
+fn parse_two_digits(s: &str) -> Option<u8> {
+    (s.len() == 2 && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_minutes_seconds(Duration::from_secs(0)), "00:00");
+        assert_eq!(format_minutes_seconds(Duration::from_secs(59)), "00:59");
+        assert_eq!(
+            format_minutes_seconds(Duration::from_secs(60 * 20)),
+            "20:00"
+        );
+        assert_eq!(
+            format_minutes_seconds(Duration::from_millis(60 * 1000 + 1500)),
+            "01:01"
+        );
+        assert_eq!(
+            format_minutes_seconds(Duration::from_secs(60 * 120)),
+            "120:00"
+        );
+    }
+
+    #[test]
+    fn format_whole_hours() {
+        assert_eq!(format_hours(Duration::from_secs(0)), "0h");
+        assert_eq!(format_hours(Duration::from_secs(60 * 60 - 1)), "0h");
+        assert_eq!(format_hours(Duration::from_secs(60 * 60 * 3 + 1)), "3h");
+        assert_eq!(format_hours(Duration::from_secs(60 * 60 * 12345)), "12345h");
+    }
+
+    fn datetime(date: &str, time: &str) -> DateTime {
+        DateTime::parse(date, time).unwrap()
+    }
+
+    #[test]
+    fn parse_datetime() {
+        assert_eq!(
+            datetime("2024-10-17", "19:30"),
+            DateTime {
+                year: 2024,
+                month: 10,
+                day: 17,
+                hour: 19,
+                minute: 30,
+                second: 0,
+            }
+        );
+        assert_eq!(datetime("2024-02-29", "00:00:59").second, 59);
+
+        for (date, time) in [
+            ("2023-02-29", "12:00"),
+            ("2024-04-31", "12:00"),
+            ("2024-13-01", "12:00"),
+            ("1999-12-31", "12:00"),
+            ("2024-10", "12:00"),
+            ("2024-10-17", "24:00"),
+            ("2024-10-17", "12:60"),
+            ("2024-10-17", "9:30"),
+            ("2024-10-17", "12:00:00:00"),
+        ] {
+            assert_eq!(DateTime::parse(date, time), None, "{date} {time}");
+        }
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(datetime("2024-01-01", "12:00").weekday(), Weekday::Monday);
+        assert_eq!(datetime("2024-02-29", "12:00").weekday(), Weekday::Thursday);
+        assert_eq!(datetime("2024-10-19", "12:00").weekday(), Weekday::Saturday);
+        assert_eq!(datetime("2000-01-01", "12:00").weekday(), Weekday::Saturday);
+        assert_eq!(datetime("2100-03-01", "12:00").weekday(), Weekday::Monday);
+    }
+
+    #[test]
+    fn week_time() {
+        assert_eq!(datetime("2024-01-01", "00:00").week_time().seconds(), 0);
+
+        let sunday_night = datetime("2024-01-07", "23:59:59").week_time();
+        assert_eq!(sunday_night.seconds(), WeekTime::SECS_PER_WEEK - 1);
+        assert_eq!(sunday_night.weekday(), Weekday::Sunday);
+    }
+
+    #[test]
+    fn display_datetime() {
+        assert_eq!(
+            format!("{}", datetime("2024-10-17", "09:05:01")),
+            "2024-10-17 09:05:01 (thu)"
+        );
+    }
+}
@@ -9,5 +9,8 @@
     # Rust toolchain
     rustup
     probe-rs
+
+    # Simulator window
+    SDL2
   ];
 }
@@ -1,70 +1,24 @@
-use defmt::{info, Format};
-use embassy_futures::select::{select, Either};
+use defmt::info;
+use embassy_futures::select::select3;
 use embassy_rp::gpio::{Input, Level, Pull};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, pubsub::PubSubChannel};
-use embassy_time::{Duration, Instant};
+use embassy_time::{Instant, Timer};
+use ms_air_filter_core::{buttons::Buttons, clock::Clock};
 
-pub(crate) static BUTTON_EVENTS: PubSubChannel<CriticalSectionRawMutex, ButtonEvent, 8, 2, 1> =
-    PubSubChannel::new();
-
-#[derive(Clone, PartialEq, Eq, Format)]
-pub(crate) struct ButtonEvent {
-    pub button: Button,
-    pub push_duration: ButtonPushDuration,
-}
-
-#[derive(Clone, PartialEq, Eq, Format)]
-pub(crate) enum Button {
-    Demand,
-    Speed,
-}
-
-#[derive(Clone, PartialEq, Eq, Format)]
-pub(crate) enum ButtonPushDuration {
-    Short,
-    Long,
-}
-
-const PUSH_THRESHOLD: Duration = Duration::from_millis(75);
-const LONG_PUSH_THRESHOLD: Duration = Duration::from_secs(3);
-
-#[derive(Format)]
-enum ButtonState {
-    Pressed { at: Instant },
-    Released { at: Instant },
-}
+pub(crate) use ms_air_filter_core::buttons::ButtonEvent;
 
-impl Default for ButtonState {
-    fn default() -> Self {
-        Self::Released { at: Instant::now() }
-    }
-}
+/// Every press matters, so they are queued rather than only keeping the newest.
+///
+/// The display task is the only subscriber, it passes on the pushes that are for the run logic.
+pub(crate) static BUTTON_EVENTS: PubSubChannel<CriticalSectionRawMutex, ButtonEvent, 8, 1, 1> =
+    PubSubChannel::new();
 
-impl ButtonState {
-    fn update(&mut self, level: Level) -> Option<ButtonPushDuration> {
-        let now = Instant::now();
-        match self {
-            ButtonState::Pressed { at } => {
-                let since = now - *at;
-                if level == Level::High {
-                    if since >= LONG_PUSH_THRESHOLD {
-                        *self = Self::Released { at: now };
-                        return Some(ButtonPushDuration::Long);
-                    } else if since >= PUSH_THRESHOLD {
-                        *self = Self::Released { at: now };
-                        return Some(ButtonPushDuration::Short);
-                    }
-                }
-            }
-            ButtonState::Released { at } => {
-                let since = now - *at;
-                if level == Level::Low && since >= Duration::from_millis(250) {
-                    *self = Self::Pressed { at: now };
-                }
-            }
-        }
+/// The real time, as kept by the embassy time driver.
+struct SystemClock;
 
-        None
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
     }
 }
 
@@ -75,35 +29,33 @@ pub(super) async fn task(r: crate::ButtonResources) {
 
     let tx = BUTTON_EVENTS.publisher().unwrap();
 
-    let mut demand_button_state = ButtonState::default();
-    let mut speed_button_state = ButtonState::default();
+    let mut buttons = Buttons::new(&SystemClock);
 
     loop {
-        let event = match select(
+        // Wait for either button to change, or for a held button to be due to repeat
+        let deadline = buttons.next_deadline(&crate::config::get());
+        let timeout = async {
+            match deadline {
+                Some(deadline) => Timer::at(deadline).await,
+                None => core::future::pending().await,
+            }
+        };
+
+        select3(
             demand_button.wait_for_any_edge(),
             speed_button.wait_for_any_edge(),
+            timeout,
         )
-        .await
-        {
-            Either::First(_) => {
-                demand_button_state
-                    .update(demand_button.get_level())
-                    .map(|push_duration| ButtonEvent {
-                        button: Button::Demand,
-                        push_duration,
-                    })
-            }
-            Either::Second(_) => {
-                speed_button_state
-                    .update(speed_button.get_level())
-                    .map(|push_duration| ButtonEvent {
-                        button: Button::Speed,
-                        push_duration,
-                    })
-            }
-        };
+        .await;
+
+        let events = buttons.update(
+            demand_button.get_level() == Level::Low,
+            speed_button.get_level() == Level::Low,
+            &SystemClock,
+            &crate::config::get(),
+        );
 
-        if let Some(event) = event {
+        for event in events {
             info!("Button event: {:?}", event);
             tx.publish(event).await;
         }
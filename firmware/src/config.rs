@@ -0,0 +1,88 @@
+use crate::storage::{RecordStore, SharedFlash, StorageFlash, CONFIG_STORE, MAX_RECORD_SIZE};
+use core::cell::RefCell;
+use defmt::{info, warn};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use ms_air_filter_core::config::{CONFIG_VERSION, ENCODED_SIZE};
+
+pub(crate) use ms_air_filter_core::config::{Config, ConfigError, KEYS};
+
+const _: () = assert!(ENCODED_SIZE <= MAX_RECORD_SIZE);
+
+static CONFIG: Mutex<CriticalSectionRawMutex, RefCell<Config>> =
+    Mutex::new(RefCell::new(Config::DEFAULT));
+
+static SAVE_REQUESTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Returns a copy of the current configuration.
+pub(crate) fn get() -> Config {
+    CONFIG.lock(|config| config.borrow().clone())
+}
+
+/// Replaces the current configuration, it will be saved to flash shortly after.
+pub(crate) fn set(config: Config) -> Result<(), ConfigError> {
+    if !config.is_valid() {
+        return Err(ConfigError::Invalid);
+    }
+
+    CONFIG.lock(|c| c.replace(config));
+    SAVE_REQUESTED.signal(());
+    Ok(())
+}
+
+/// Loads the configuration from flash, falling back to (and storing) the defaults if there is
+/// no usable configuration stored.
+///
+/// This should be called before any tasks that use the configuration are started.
+/// The returned store should be given to [`task`] to save future changes.
+pub(crate) fn load(flash: &mut StorageFlash) -> RecordStore {
+    let mut store = CONFIG_STORE;
+    let mut buf = [0_u8; MAX_RECORD_SIZE];
+
+    let config = match store.load(flash, &mut buf) {
+        Some((version, len)) => {
+            let config = Config::decode(version, &buf[..len]);
+            if config.is_none() {
+                warn!("Stored config (version {}) could not be read", version);
+            }
+            config.filter(Config::is_valid)
+        }
+        None => None,
+    };
+
+    match config {
+        Some(config) => {
+            info!("Loaded config: {:?}", config);
+            CONFIG.lock(|c| c.replace(config));
+        }
+        None => {
+            warn!("No valid config stored, using defaults");
+
+            let mut buf = [0_u8; ENCODED_SIZE];
+            let len = Config::DEFAULT.encode(&mut buf);
+            if let Err(e) = store.save(flash, CONFIG_VERSION, &buf[..len]) {
+                warn!("Failed to store default config: {:?}", e);
+            }
+        }
+    }
+
+    store
+}
+
+#[embassy_executor::task]
+pub(super) async fn task(flash: &'static SharedFlash, mut store: RecordStore) {
+    loop {
+        SAVE_REQUESTED.wait().await;
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = get().encode(&mut buf);
+
+        let result = store.save(&mut *flash.lock().await, CONFIG_VERSION, &buf[..len]);
+        match result {
+            Ok(()) => info!("Config saved"),
+            Err(e) => warn!("Failed to save config: {:?}", e),
+        }
+    }
+}
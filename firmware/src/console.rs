@@ -0,0 +1,407 @@
+use crate::{
+    config, display,
+    fan::FanCommand,
+    network, rtc,
+    run_logic::{ControlCommand, State, CONTROL_COMMANDS, STATE},
+    runtime::{self, RuntimeMeter},
+    schedule::{self, Schedule},
+    temperature_sensors::{TemperatureReadings, TEMPERATURE_READINGS},
+};
+use core::fmt::Write;
+use defmt::{info, unwrap, warn};
+use embassy_futures::join::join;
+use embassy_rp::{
+    bind_interrupts,
+    peripherals::USB,
+    usb::{Driver, InterruptHandler},
+};
+use embassy_time::{with_timeout, Duration};
+use embassy_usb::{
+    class::cdc_acm::{self, CdcAcmClass},
+    driver::EndpointError,
+    Builder,
+};
+use ms_air_filter_core::{
+    console::{self as command, Command, HELP},
+    time::format_minutes_seconds,
+};
+use portable_atomic::Ordering;
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+const MAX_PACKET_SIZE: usize = 64;
+
+/// Output is dropped if the host does not read it within this time, so that a host that has the
+/// port enumerated but not open cannot stall the console.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+type Class<'d> = CdcAcmClass<'d, Driver<'d, USB>>;
+type Output = heapless::String<1024>;
+
+#[embassy_executor::task]
+pub(super) async fn task(r: crate::UsbResources) {
+    let driver = Driver::new(r.usb, Irqs);
+
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Maker Space Newcastle");
+    config.product = Some("Air Filter Controller");
+    config.max_power = 100;
+    config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = cdc_acm::State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE as u16);
+
+    let mut usb = builder.build();
+
+    join(usb.run(), console(&mut class)).await;
+}
+
+enum UsbEvent {
+    Connected,
+    Disconnected,
+    Data(usize),
+}
+
+async fn console(class: &mut Class<'_>) {
+    let mut connected = false;
+    let mut line = heapless::String::<64>::new();
+    let mut rx_buf = [0_u8; MAX_PACKET_SIZE];
+
+    loop {
+        let usb_event = if connected {
+            match class.read_packet(&mut rx_buf).await {
+                Ok(n) => UsbEvent::Data(n),
+                Err(_) => UsbEvent::Disconnected,
+            }
+        } else {
+            class.wait_connection().await;
+            UsbEvent::Connected
+        };
+
+        match usb_event {
+            UsbEvent::Connected => {
+                info!("Console connected");
+                connected = true;
+                line.clear();
+            }
+            UsbEvent::Disconnected => {
+                info!("Console disconnected");
+                connected = false;
+            }
+            UsbEvent::Data(n) => {
+                let mut out = Output::new();
+
+                for c in rx_buf[..n].iter().map(|b| *b as char) {
+                    match c {
+                        '\r' | '\n' => {
+                            let _ = out.write_str("\n");
+                            if !line.trim().is_empty() {
+                                // The newest state and readings, whenever they were last sent
+                                let state = STATE.try_get().unwrap_or_default();
+                                let temperatures =
+                                    TEMPERATURE_READINGS.try_get().unwrap_or_default();
+                                handle_line(&line, &mut out, &state, &temperatures);
+                            }
+                            line.clear();
+                            let _ = out.write_str("> ");
+                        }
+                        // Backspace/delete
+                        '\x08' | '\x7f' => {
+                            if line.pop().is_some() {
+                                let _ = out.write_str("\x08 \x08");
+                            }
+                        }
+                        c if c.is_ascii_graphic() || c == ' ' => {
+                            if line.push(c).is_ok() {
+                                let _ = out.write_char(c);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                if let Err(e) = write(class, &out).await {
+                    warn!("Console write failed: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Writes to the host, converting line endings for the benefit of terminals.
+async fn write(class: &mut Class<'_>, s: &str) -> Result<(), EndpointError> {
+    let mut packet = heapless::Vec::<u8, MAX_PACKET_SIZE>::new();
+
+    for b in s.bytes() {
+        if b == b'\n' {
+            push_byte(class, &mut packet, b'\r').await?;
+        }
+        push_byte(class, &mut packet, b).await?;
+    }
+
+    if !packet.is_empty() {
+        write_packet(class, &packet).await?;
+    }
+
+    Ok(())
+}
+
+async fn push_byte(
+    class: &mut Class<'_>,
+    packet: &mut heapless::Vec<u8, MAX_PACKET_SIZE>,
+    b: u8,
+) -> Result<(), EndpointError> {
+    if packet.is_full() {
+        write_packet(class, packet).await?;
+        packet.clear();
+    }
+    let _ = packet.push(b);
+    Ok(())
+}
+
+async fn write_packet(class: &mut Class<'_>, packet: &[u8]) -> Result<(), EndpointError> {
+    with_timeout(WRITE_TIMEOUT, class.write_packet(packet))
+        .await
+        .unwrap_or(Err(EndpointError::Disabled))
+}
+
+fn handle_line(line: &str, out: &mut Output, state: &State, temperatures: &TemperatureReadings) {
+    let command = match command::parse(line) {
+        Ok(command) => command,
+        Err(e) => {
+            let _ = writeln!(out, "error: {}", e.description());
+            return;
+        }
+    };
+
+    info!("Console command: {:?}", command);
+
+    // Output that does not fit in the buffer is truncated
+    let _ = match command {
+        Command::Help => writeln!(out, "{HELP}"),
+        Command::Status => write_status(out, state),
+        Command::Start { .. } | Command::Stop | Command::Speed(_) => {
+            control(out, unwrap!(command.control_command()))
+        }
+        Command::Temperatures => write_temperatures(out, temperatures),
+        Command::ConfigGet(Some(key)) => match config::get().get_value(key) {
+            Ok(value) => writeln!(out, "{key} = {value}"),
+            Err(e) => writeln!(out, "error: {}", e.description()),
+        },
+        Command::ConfigGet(None) => {
+            let config = config::get();
+            config::KEYS
+                .iter()
+                .try_for_each(|key| match config.get_value(key) {
+                    Ok(value) => writeln!(out, "{key} = {value}"),
+                    Err(_) => Ok(()),
+                })
+        }
+        Command::ConfigSet { key, value } => {
+            let mut new_config = config::get();
+            match new_config
+                .set_value(key, value)
+                .and_then(|_| config::set(new_config))
+            {
+                Ok(()) => writeln!(out, "ok"),
+                Err(e) => writeln!(out, "error: {}", e.description()),
+            }
+        }
+        Command::Filter => write_filter(out, &runtime::get()),
+        Command::FilterReset => {
+            runtime::filter_changed();
+            writeln!(out, "ok")
+        }
+        Command::Time => match rtc::now() {
+            Some(now) => writeln!(out, "{now}"),
+            None => writeln!(out, "not set"),
+        },
+        Command::TimeSet(datetime) => match rtc::set(&datetime) {
+            Ok(()) => writeln!(out, "ok"),
+            Err(()) => writeln!(out, "error: could not set clock"),
+        },
+        Command::Schedule => write_schedule(out, &schedule::get()),
+        Command::ScheduleAdd(entry) => {
+            let mut new_schedule = schedule::get();
+            match new_schedule.add(entry) {
+                Ok(()) => {
+                    schedule::set(new_schedule);
+                    writeln!(out, "ok")
+                }
+                Err(e) => writeln!(out, "error: {}", e.description()),
+            }
+        }
+        Command::ScheduleRemove(n) => {
+            let mut new_schedule = schedule::get();
+            match new_schedule.remove(n - 1) {
+                Ok(_) => {
+                    schedule::set(new_schedule);
+                    writeln!(out, "ok")
+                }
+                Err(e) => writeln!(out, "error: {}", e.description()),
+            }
+        }
+        Command::ScheduleClear => {
+            schedule::set(Schedule::default());
+            writeln!(out, "ok")
+        }
+        Command::Version => writeln!(out, "{}", env!("VERSION")),
+    };
+}
+
+/// Passes a command on to the run logic.
+/// This does not wait for space in the channel as the console must keep servicing its subscribers
+/// (which the run logic may be waiting on).
+fn control(out: &mut Output, command: ControlCommand) -> core::fmt::Result {
+    match CONTROL_COMMANDS.publisher().unwrap().try_publish(command) {
+        Ok(()) => writeln!(out, "ok"),
+        Err(_) => writeln!(out, "error: busy, try again"),
+    }
+}
+
+fn write_status(out: &mut Output, state: &State) -> core::fmt::Result {
+    match state.fan_command() {
+        FanCommand::Stop => writeln!(out, "fan: off")?,
+        FanCommand::Run(speed) => writeln!(out, "fan: {}", speed.name())?,
+    }
+
+    match state.reason() {
+        Some(reason) => writeln!(out, "reason: {}", reason.description())?,
+        None => writeln!(out, "reason: -")?,
+    }
+
+    match state.time_remaining() {
+        Some(t) => writeln!(out, "remaining: {}", format_minutes_seconds(t))?,
+        None if state.continuous() => writeln!(out, "remaining: continuous")?,
+        None => writeln!(out, "remaining: -")?,
+    }
+
+    if let Some(fault) = state.fault() {
+        write!(out, "fault: {fault}")?;
+        if let Some(peak) = fault.peak_temperature {
+            write!(out, ", peak {peak:.1}C")?;
+        }
+        writeln!(
+            out,
+            ", action {}{}",
+            fault.action.name(),
+            if fault.can_be_reset() {
+                ", hold both buttons to reset"
+            } else {
+                ""
+            }
+        )?;
+    }
+
+    // What the fan is actually doing, which lags behind what it has been told to do
+    writeln!(out, "applied: {}", crate::fan::status())?;
+
+    writeln!(
+        out,
+        "display recoveries: {}",
+        display::RECOVERIES.load(Ordering::Relaxed)
+    )?;
+
+    if config::get().mqtt_broker == [0; 4] {
+        writeln!(out, "mqtt: disabled")
+    } else if network::MQTT_CONNECTED.load(Ordering::Relaxed) {
+        writeln!(out, "mqtt: connected")
+    } else {
+        writeln!(out, "mqtt: not connected")
+    }
+}
+
+fn write_schedule(out: &mut Output, schedule: &Schedule) -> core::fmt::Result {
+    if schedule.entries().is_empty() {
+        writeln!(out, "no entries")?;
+    }
+
+    for (i, entry) in schedule.entries().iter().enumerate() {
+        writeln!(out, "{}: {entry}", i + 1)?;
+    }
+
+    if rtc::now().is_none() {
+        writeln!(out, "clock not set, the schedule is not being followed")?;
+    }
+
+    Ok(())
+}
+
+fn write_temperatures(out: &mut Output, temperatures: &TemperatureReadings) -> core::fmt::Result {
+    let mut any = false;
+
+    for sensor in temperatures.iter() {
+        any = true;
+
+        write!(out, "{:016x}: ", sensor.address)?;
+        match sensor.temperature {
+            Some(t) => write!(out, "{t:.1}C")?,
+            None => write!(out, "-")?,
+        }
+        if sensor.stale {
+            write!(out, " (stale)")?;
+        }
+        writeln!(out, ", {} read errors", sensor.read_errors)?;
+    }
+
+    if !any {
+        writeln!(out, "no sensors found")?;
+    }
+
+    Ok(())
+}
+
+fn write_filter(out: &mut Output, meter: &RuntimeMeter) -> core::fmt::Result {
+    fn hours(d: Duration) -> f32 {
+        d.as_secs() as f32 / 3600.0
+    }
+
+    let service_interval = config::get().filter_service_interval;
+    writeln!(
+        out,
+        "filter life remaining: {}%",
+        meter.filter_life_remaining_percent(service_interval)
+    )?;
+
+    for (name, totals) in [
+        ("since filter change", &meter.since_filter_change),
+        ("total", &meter.lifetime),
+    ] {
+        writeln!(
+            out,
+            "{name}: low {:.1}h, mid {:.1}h, high {:.1}h",
+            hours(totals.low),
+            hours(totals.medium),
+            hours(totals.high)
+        )?;
+    }
+
+    if meter.filter_changes.is_empty() {
+        writeln!(out, "no filter changes recorded")?;
+    }
+    for change in meter.filter_changes.iter().rev() {
+        writeln!(
+            out,
+            "filter changed at {:.1}h, after {:.1}h",
+            hours(change.at),
+            hours(change.filter_runtime)
+        )?;
+    }
+
+    Ok(())
+}
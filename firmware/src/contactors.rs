@@ -0,0 +1,120 @@
+//! Driver for the fan speed selection contactors and the contactor supply voltage.
+
+use crate::{config::Config, fan::FanSpeed};
+use defmt::{debug, unwrap};
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_time::{Duration, Instant, Timer};
+use ms_air_filter_core::contactors::{dead_time_remaining, AuxContacts, SpeedOutputs};
+
+pub(crate) use ms_air_filter_core::contactors::ContactorFault;
+
+/// How often the auxiliary contacts are read while waiting for them to agree.
+const FEEDBACK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The speed selection contactors, at most one of which is ever closed.
+///
+/// The outputs are owned by [`SpeedOutputs`], which can only drive one of them at a time.
+pub(crate) struct SpeedContactors<'d> {
+    speed: SpeedOutputs<Output<'d>>,
+    /// Auxiliary contact inputs in the order low, medium, high
+    aux: [Input<'d>; 3],
+    voltage: Output<'d>,
+
+    /// When the last closed contactor was opened
+    opened_at: Option<Instant>,
+}
+
+impl SpeedContactors<'static> {
+    pub(crate) fn new(r: crate::FanRelayResources) -> Self {
+        Self {
+            speed: unwrap!(SpeedOutputs::new(
+                Output::new(r.low, Level::Low),
+                Output::new(r.medium, Level::Low),
+                Output::new(r.high, Level::Low),
+            )),
+            aux: [
+                Input::new(r.aux_low, Pull::Down),
+                Input::new(r.aux_medium, Pull::Down),
+                Input::new(r.aux_high, Pull::Down),
+            ],
+            voltage: Output::new(r.contactor_voltage, Level::Low),
+            opened_at: None,
+        }
+    }
+}
+
+impl SpeedContactors<'_> {
+    /// Opens every speed selection contactor and drops the contactor voltage, then checks that
+    /// they have all opened.
+    pub(crate) async fn open(&mut self, config: &Config) -> Result<(), ContactorFault> {
+        debug!("Open all speed selection contactors");
+        if self.speed.selected().is_some() {
+            self.opened_at = Some(Instant::now());
+        }
+        unwrap!(self.speed.select(None));
+        self.voltage.set_low();
+
+        Timer::after(config.contactor_switch_delay).await;
+        self.confirm(config).await
+    }
+
+    /// Closes the contactor for `speed` once the motor has had time to coast down, pulling it in
+    /// at 24V before dropping to 5V to hold it, then checks that it has closed.
+    ///
+    /// This should only be called after [`SpeedContactors::open`].
+    pub(crate) async fn close(
+        &mut self,
+        speed: FanSpeed,
+        config: &Config,
+    ) -> Result<(), ContactorFault> {
+        let dead_time = dead_time_remaining(self.opened_at.map(|t| t.elapsed()), config);
+        if dead_time.as_ticks() > 0 {
+            debug!(
+                "Waiting {}ms for the motor to coast down",
+                dead_time.as_millis()
+            );
+            Timer::after(dead_time).await;
+        }
+
+        debug!("Set contactor voltage to 24V");
+        self.voltage.set_high();
+
+        Timer::after(config.contactor_switch_delay).await;
+
+        debug!("Close speed selection contactor for {}", speed);
+        unwrap!(self.speed.select(Some(speed)));
+
+        Timer::after(config.contactor_pull_in_time).await;
+
+        debug!("Set contactor voltage to 5V");
+        self.voltage.set_low();
+
+        self.confirm(config).await
+    }
+
+    /// Waits for the auxiliary contacts to agree with the selected speed, if they are used.
+    async fn confirm(&self, config: &Config) -> Result<(), ContactorFault> {
+        if config.contactor_feedback_timeout.as_ticks() == 0 {
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + config.contactor_feedback_timeout;
+        loop {
+            let result = self.read_aux().check(self.speed.selected());
+            if result.is_ok() || Instant::now() >= deadline {
+                return result;
+            }
+            Timer::after(FEEDBACK_POLL_INTERVAL).await;
+        }
+    }
+
+    fn read_aux(&self) -> AuxContacts {
+        // The inputs are pulled low when the contact is closed
+        let [low, medium, high] = &self.aux;
+        AuxContacts {
+            low: low.is_low(),
+            medium: medium.is_low(),
+            high: high.is_low(),
+        }
+    }
+}
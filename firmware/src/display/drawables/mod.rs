@@ -1,2 +0,0 @@
-pub(super) mod boot_screen;
-pub(super) mod main_screen;
@@ -0,0 +1,62 @@
+//! Detection of a display that has lost its configuration, which happens when it is disturbed by
+//! electrical noise from the contactors.
+//!
+//! The status of the display is read back over MISO, this does not go through the display driver
+//! as it has no support for reading from the display.
+
+use core::cell::RefCell;
+use defmt::warn;
+use embassy_rp::gpio::Output;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+/// Read display power mode
+const RDDPM: u8 = 0x0A;
+/// Read display pixel format
+const RDDCOLMOD: u8 = 0x0C;
+
+const POWER_MODE_SLEEP_OUT: u8 = 1 << 4;
+const POWER_MODE_DISPLAY_ON: u8 = 1 << 2;
+const POWER_MODE_EXPECTED: u8 = POWER_MODE_SLEEP_OUT | POWER_MODE_DISPLAY_ON;
+
+const PIXEL_FORMAT_MASK: u8 = 0x07;
+const PIXEL_FORMAT_16_BIT: u8 = 0x05;
+
+/// Checks that the display is still awake and configured as it was when it was initialised.
+pub(super) fn is_healthy<SPI: SpiDevice>(spi: &mut SPI, dc: &RefCell<Output<'_>>) -> bool {
+    match (
+        read_register(spi, dc, RDDPM),
+        read_register(spi, dc, RDDCOLMOD),
+    ) {
+        (Ok(power_mode), Ok(pixel_format)) => {
+            let healthy = power_mode & POWER_MODE_EXPECTED == POWER_MODE_EXPECTED
+                && pixel_format & PIXEL_FORMAT_MASK == PIXEL_FORMAT_16_BIT;
+
+            if !healthy {
+                warn!(
+                    "Unexpected display status: power mode {:#x}, pixel format {:#x}",
+                    power_mode, pixel_format
+                );
+            }
+
+            healthy
+        }
+        _ => {
+            warn!("Failed to read display status");
+            false
+        }
+    }
+}
+
+fn read_register<SPI: SpiDevice>(
+    spi: &mut SPI,
+    dc: &RefCell<Output<'_>>,
+    command: u8,
+) -> Result<u8, SPI::Error> {
+    // Low selects command mode, the driver sets this as it needs to before every write so it does
+    // not need to be restored
+    dc.borrow_mut().set_low();
+
+    let mut value = [0];
+    spi.transaction(&mut [Operation::Write(&[command]), Operation::Read(&mut value)])?;
+    Ok(value[0])
+}
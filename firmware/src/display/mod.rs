@@ -1,28 +1,94 @@
-mod drawables;
+mod health;
 mod no_cs;
+mod shared_output;
 
 use crate::{
-    fan::FanCommand,
-    run_logic::{Trigger, STATE_CHANGED},
+    buttons::{ButtonEvent, BUTTON_EVENTS},
+    fan::{FanCommand, FanStatus, FAN_STATUS},
+    run_logic::STATE,
+    temperature_sensors::TEMPERATURE_READINGS,
 };
 use core::cell::RefCell;
-use defmt::{debug, warn};
-use drawables::{boot_screen::BootScreen, main_screen::MainScreen};
+use defmt::{debug, info, warn};
 use embassy_embedded_hal::shared_bus::blocking::spi::SpiDeviceWithConfig;
+use embassy_futures::select::{select, select4, Either, Either4};
 use embassy_rp::{
     gpio::{Level, Output},
     pwm::{Pwm, SetDutyCycle},
 };
 use embassy_sync::{
-    blocking_mutex::{raw::NoopRawMutex, Mutex},
-    pubsub::WaitResult,
+    blocking_mutex::{
+        raw::{CriticalSectionRawMutex, NoopRawMutex},
+        Mutex,
+    },
+    pubsub::{PubSubChannel, Publisher, Subscriber},
 };
-use embassy_time::{Delay, Timer};
-use embedded_graphics::{pixelcolor::Rgb565, Drawable};
+use embassy_time::{Delay, Duration, Instant, Ticker, Timer};
+use embedded_graphics::Drawable;
 use mipidsi::{interface::SpiInterface, models::ST7789, options::ColorInversion};
+use ms_air_filter_core::{display::backlight_pulse, runtime::FilterStatus};
+use ms_air_filter_ui::{Action, BootScreen, Diagnostics, Router, DISPLAY_SIZE};
 use no_cs::NoCs;
+use portable_atomic::{AtomicU32, Ordering};
+use shared_output::SharedOutput;
 
-type Color = Rgb565;
+/// How often the display status is read back to check that it is still working.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the information shown on the screens and the backlight are updated.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time between attempts to initialise the display if it fails.
+const INIT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The number of times the display has been reinitialised after it stopped working.
+pub(crate) static RECOVERIES: AtomicU32 = AtomicU32::new(0);
+
+/// Button pushes for the run logic, in the order they were pushed.
+///
+/// Every push goes to the router first, which only passes it on if the main screen was open (see
+/// [`route_button`]).
+pub(crate) static FAN_BUTTON_EVENTS: PubSubChannel<CriticalSectionRawMutex, ButtonEvent, 8, 1, 1> =
+    PubSubChannel::new();
+
+type ButtonSubscriber = Subscriber<'static, CriticalSectionRawMutex, ButtonEvent, 8, 1, 1>;
+type FanButtonPublisher = Publisher<'static, CriticalSectionRawMutex, ButtonEvent, 8, 1, 1>;
+
+/// Hands a button push to the router, then passes it on to the run logic unless another screen was
+/// open and so the buttons were operating that instead.
+async fn route_button(router: &mut Router, event: ButtonEvent, fan_buttons: &FanButtonPublisher) {
+    let captured = router.buttons_captured();
+
+    match router.handle_button(&event, Instant::now()) {
+        Some(Action::FilterChanged) => crate::runtime::filter_changed(),
+        Some(Action::SaveConfig(config)) => {
+            if let Err(e) = crate::config::set(config) {
+                warn!("Settings from the display not saved: {}", e);
+            }
+        }
+        None => {}
+    }
+
+    if !captured {
+        fan_buttons.publish(event).await;
+    }
+}
+
+/// Waits for `duration` while nothing can be drawn, still routing button pushes so that the fan
+/// can be controlled without the display.
+async fn route_buttons_for(
+    duration: Duration,
+    router: &mut Router,
+    button_sub: &mut ButtonSubscriber,
+    fan_buttons: &FanButtonPublisher,
+) {
+    let deadline = Instant::now() + duration;
+    while let Either::Second(event) =
+        select(Timer::at(deadline), button_sub.next_message_pure()).await
+    {
+        route_button(router, event, fan_buttons).await;
+    }
+}
 
 #[embassy_executor::task]
 pub(super) async fn task(r: crate::DisplayResources) {
@@ -36,8 +102,6 @@ pub(super) async fn task(r: crate::DisplayResources) {
     let spi = embassy_rp::spi::Spi::new_blocking(r.spi, r.clk, r.mosi, r.miso, config.clone());
     let spi_bus: Mutex<NoopRawMutex, _> = Mutex::new(RefCell::new(spi));
 
-    let display_spi = SpiDeviceWithConfig::new(&spi_bus, NoCs, config);
-
     let mut backlight = Pwm::new_output_b(
         r.backlight_pwm,
         r.backlight,
@@ -45,49 +109,173 @@ pub(super) async fn task(r: crate::DisplayResources) {
     );
     let _ = backlight.set_duty_cycle_fully_on();
 
-    let dc = Output::new(r.dc, Level::Low);
-    let rst = Output::new(r.rst, Level::Low);
+    let dc = RefCell::new(Output::new(r.dc, Level::Low));
+    let mut rst = Output::new(r.rst, Level::Low);
 
     let mut buffer = [0_u8; 512];
-    let interface = SpiInterface::new(display_spi, dc, &mut buffer);
 
-    let mut display = mipidsi::Builder::new(ST7789, interface)
-        .display_size(240, 240)
-        .invert_colors(ColorInversion::Inverted)
-        .reset_pin(rst)
-        .init(&mut Delay)
-        .unwrap();
+    let mut state_rx = STATE.receiver().unwrap();
+    let mut button_sub = BUTTON_EVENTS.subscriber().unwrap();
+    let fan_buttons = FAN_BUTTON_EVENTS.publisher().unwrap();
+    let mut temperature_rx = TEMPERATURE_READINGS.receiver().unwrap();
+    let mut fan_status_rx = FAN_STATUS.receiver().unwrap();
+    let mut ticker = Ticker::every(TICK_INTERVAL);
 
-    let mut state_sub = STATE_CHANGED.subscriber().unwrap();
+    let mut router = Router::default();
+    let mut filter_status = FilterStatus::Ok;
+    let mut sensor_read_errors = 0;
+    let mut backlight_percent = crate::config::get().backlight_idle_percent;
+    let mut booting = true;
 
-    // Show the boot splash screen
-    BootScreen {}.draw(&mut display).unwrap();
-    Timer::after_secs(3).await;
+    loop {
+        let display_spi = SpiDeviceWithConfig::new(&spi_bus, NoCs, config.clone());
+        let interface = SpiInterface::new(display_spi, SharedOutput(&dc), &mut buffer);
 
-    let mut main_screen = MainScreen::default();
+        // This also resets the display with the reset pin
+        let mut display = match mipidsi::Builder::new(ST7789, interface)
+            .display_size(DISPLAY_SIZE.width as u16, DISPLAY_SIZE.height as u16)
+            .invert_colors(ColorInversion::Inverted)
+            .reset_pin(&mut rst)
+            .init(&mut Delay)
+        {
+            Ok(display) => display,
+            Err(_) => {
+                warn!("Failed to initialise display");
+                route_buttons_for(INIT_RETRY_DELAY, &mut router, &mut button_sub, &fan_buttons)
+                    .await;
+                continue;
+            }
+        };
 
-    loop {
-        match state_sub.next_message().await {
-            WaitResult::Lagged(count) => {
-                warn!("Subscriber lagged, lost {} messages", count);
+        // Not every display can be read from, in which case only write errors can be detected
+        let mut status_spi = SpiDeviceWithConfig::new(&spi_bus, NoCs, config.clone());
+        let status_readable = health::is_healthy(&mut status_spi, &dc);
+        if !status_readable {
+            info!("Display status cannot be read, only write errors will be detected");
+        }
+
+        if booting {
+            // Show the boot splash screen
+            let _ = BootScreen {
+                version: env!("VERSION"),
             }
-            WaitResult::Message(state) => {
-                debug!("Got new state to draw");
+            .draw(&mut display);
+            route_buttons_for(
+                Duration::from_secs(3),
+                &mut router,
+                &mut button_sub,
+                &fan_buttons,
+            )
+            .await;
+            booting = false;
+        }
+
+        // Whatever was on the display before it was reset has gone
+        router.invalidate();
+        let mut redraw_ok = router.draw(&mut display).is_ok();
+        let mut last_health_check = Instant::now();
+
+        while redraw_ok {
+            redraw_ok = match select4(
+                state_rx.changed(),
+                button_sub.next_message_pure(),
+                ticker.next(),
+                select(temperature_rx.changed(), fan_status_rx.changed()),
+            )
+            .await
+            {
+                Either4::First(state) => {
+                    debug!("Got new state to draw");
+
+                    // Set backlight intensity
+                    let config = crate::config::get();
+                    backlight_percent = match state.fan_command() {
+                        FanCommand::Stop => config.backlight_idle_percent,
+                        FanCommand::Run(_) => config.backlight_running_percent,
+                    };
+                    let _ = backlight.set_duty_cycle_percent(backlight_percent);
+
+                    // Update display contents, this may show or clear the overtemperature alarm
+                    router.update_state(state);
+                    router.draw(&mut display).is_ok()
+                }
+                Either4::Second(event) => {
+                    route_button(&mut router, event, &fan_buttons).await;
+                    router.draw(&mut display).is_ok()
+                }
+                Either4::Third(_) => {
+                    let config = crate::config::get();
+                    let meter = crate::runtime::get();
+
+                    let new_filter_status = meter.filter_status(config.filter_service_interval);
+                    if new_filter_status != filter_status {
+                        info!("Filter status: {:?}", new_filter_status);
+                        filter_status = new_filter_status;
+                        router.main.update_filter_status(filter_status);
 
-                // Set backlight intensity
-                match state.fan_command() {
-                    FanCommand::Stop => {
-                        let _ = backlight.set_duty_cycle_percent(20);
+                        if filter_status != FilterStatus::Overdue {
+                            let _ = backlight.set_duty_cycle_percent(backlight_percent);
+                        }
                     }
-                    FanCommand::Run(_) => {
-                        let _ = backlight.set_duty_cycle_fully_on();
+
+                    // Pulse the backlight to draw attention to overdue filters
+                    if filter_status == FilterStatus::Overdue {
+                        let elapsed = Duration::from_ticks(Instant::now().as_ticks());
+                        let _ = backlight
+                            .set_duty_cycle_percent(backlight_pulse(backlight_percent, elapsed));
                     }
+
+                    router
+                        .statistics
+                        .update(meter, config.filter_service_interval);
+                    router.settings.update_config(config);
+                    router.diagnostics.update_diagnostics(Diagnostics {
+                        version: env!("VERSION"),
+                        uptime: Duration::from_ticks(Instant::now().as_ticks()),
+                        display_recoveries: RECOVERIES.load(Ordering::Relaxed),
+                        sensor_read_errors,
+                    });
+
+                    router.handle_tick(Instant::now());
+
+                    let healthy = if last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+                        last_health_check = Instant::now();
+                        !status_readable || health::is_healthy(&mut status_spi, &dc)
+                    } else {
+                        true
+                    };
+
+                    // Only what has changed is redrawn
+                    healthy && router.draw(&mut display).is_ok()
                 }
+                Either4::Fourth(Either::First(readings)) => {
+                    sensor_read_errors = readings
+                        .iter()
+                        .fold(0_u32, |total, s| total.saturating_add(s.read_errors));
+                    router.temperatures.update_readings(readings);
+                    router.draw(&mut display).is_ok()
+                }
+                Either4::Fourth(Either::Second(FanStatus::Switching(command))) => {
+                    router.main.update_fan_status(FanStatus::Switching(command));
+                    router.draw(&mut display).is_ok()
+                }
+                Either4::Fourth(Either::Second(status)) => {
+                    router.main.update_fan_status(status);
 
-                // Update display contents
-                main_screen.update_state(state);
-                main_screen.draw(&mut display).unwrap();
-            }
+                    // Switching the contactors is when the display is most likely to be
+                    // disturbed, redraw everything in case the contents were corrupted
+                    debug!("Redrawing after contactor switching");
+                    router.invalidate();
+                    (!status_readable || health::is_healthy(&mut status_spi, &dc))
+                        && router.draw(&mut display).is_ok()
+                }
+            };
         }
+
+        let recoveries = RECOVERIES.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "Display is not responding, reinitialising (recovery {})",
+            recoveries
+        );
     }
 }
@@ -0,0 +1,22 @@
+use core::{cell::RefCell, convert::Infallible};
+use embassy_rp::gpio::Output;
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+/// An output that is given to the display driver but is also used outside of it.
+pub(super) struct SharedOutput<'a, 'd>(pub(super) &'a RefCell<Output<'d>>);
+
+impl OutputPin for SharedOutput<'_, '_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_low();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_high();
+        Ok(())
+    }
+}
+
+impl ErrorType for SharedOutput<'_, '_> {
+    type Error = Infallible;
+}
@@ -1,89 +1,63 @@
-use defmt::{debug, info, warn, Format};
-use embassy_rp::gpio::{Level, Output};
-use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    pubsub::{PubSubChannel, WaitResult},
-};
+use crate::contactors::SpeedContactors;
+use defmt::{error, info};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
 use embassy_time::Timer;
 
-pub(crate) static FAN_COMMAND: PubSubChannel<CriticalSectionRawMutex, FanCommand, 1, 2, 1> =
-    PubSubChannel::new();
+pub(crate) use ms_air_filter_core::fan::{FanCommand, FanController, FanSpeed, FanStatus};
 
-#[derive(Clone, Format, Eq, PartialEq)]
-pub(crate) enum FanCommand {
-    Stop,
-    Run(FanSpeed),
-}
+/// What the fan should be doing.
+///
+/// Only the newest command matters, the fan task skips straight to it after any it missed while
+/// switching the contactors.
+pub(crate) static FAN_COMMAND: Watch<CriticalSectionRawMutex, FanCommand, 1> = Watch::new();
+
+/// What the fan is actually doing, sent before and after the contactors are switched.
+///
+/// Switching the contactors is also when the display is most likely to be disturbed, and a fault
+/// stops whatever the run logic had started.
+pub(crate) static FAN_STATUS: Watch<CriticalSectionRawMutex, FanStatus, 4> =
+    Watch::new_with(FanStatus::Idle);
 
-#[derive(Clone, Format, Eq, PartialEq)]
-pub(crate) enum FanSpeed {
-    Low,
-    Medium,
-    High,
+/// What the fan is actually doing right now.
+pub(crate) fn status() -> FanStatus {
+    FAN_STATUS.try_get().unwrap_or_default()
 }
 
-impl FanSpeed {
-    pub(crate) fn cycle(&mut self) {
-        *self = match self {
-            Self::Low => Self::Medium,
-            Self::Medium => Self::High,
-            Self::High => Self::Low,
-        };
-    }
+fn set_status(status: FanStatus) {
+    info!("Fan status: {:?}", status);
+    FAN_STATUS.sender().send(status);
 }
 
 #[embassy_executor::task]
 pub(super) async fn task(r: crate::FanRelayResources) {
-    let mut fan_high = Output::new(r.high, Level::Low);
-    let mut fan_medium = Output::new(r.medium, Level::Low);
-    let mut fan_low = Output::new(r.low, Level::Low);
-    let mut contactor_voltage = Output::new(r.contactor_voltage, Level::Low);
+    let mut contactors = SpeedContactors::new(r);
+    let mut fan = FanController::default();
 
-    let mut last = FanCommand::Stop;
-
-    let mut rx = FAN_COMMAND.subscriber().unwrap();
+    let mut rx = FAN_COMMAND.receiver().unwrap();
 
     loop {
-        match rx.next_message().await {
-            WaitResult::Lagged(count) => {
-                warn!("Subscriber lagged, lost {} messages", count);
-            }
-            WaitResult::Message(cmd) => {
-                if cmd != last {
-                    info!("Set fan to {:?}", cmd);
-                    debug!("Open all speed selection contactors");
-                    fan_low.set_low();
-                    fan_medium.set_low();
-                    fan_high.set_low();
-
-                    Timer::after_millis(10).await;
+        // Anything sent while the last command was being carried out is waiting here already
+        let latest = rx.changed().await;
 
-                    if let FanCommand::Run(speed) = cmd.clone() {
-                        debug!("Set contactor voltage to 24V");
-                        contactor_voltage.set_high();
+        if let Some(cmd) = fan.pending(&latest) {
+            let config = crate::config::get();
 
-                        Timer::after_millis(10).await;
+            info!("Set fan to {:?}", cmd);
+            set_status(FanStatus::Switching(cmd.clone()));
+            let mut result = contactors.open(&config).await;
 
-                        debug!("Close speed selection contactor for {}", speed);
-                        match speed {
-                            FanSpeed::Low => &mut fan_low,
-                            FanSpeed::Medium => &mut fan_medium,
-                            FanSpeed::High => &mut fan_high,
-                        }
-                        .set_high();
-
-                        Timer::after_millis(500).await;
-
-                        debug!("Set contactor voltage to 5V");
-                        contactor_voltage.set_low();
-                    }
-
-                    // Enforce the new speed for a very minimal sensible amount of time
-                    Timer::after_secs(1).await;
+            if let (Ok(()), FanCommand::Run(speed)) = (&result, cmd.clone()) {
+                result = contactors.close(speed, &config).await;
+            }
 
-                    last = cmd;
-                }
+            if let Err(fault) = &result {
+                error!("Contactor fault: {}", fault);
+                let _ = contactors.open(&config).await;
             }
+            set_status(fan.switched(cmd, result));
+
+            // Enforce the new speed for a very minimal sensible amount of time
+            Timer::after(config.minimum_speed_hold_time).await;
         }
     }
 }
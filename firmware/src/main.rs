@@ -2,25 +2,37 @@
 #![no_main]
 
 mod buttons;
+mod config;
+mod console;
+mod contactors;
 mod display;
 mod fan;
+mod modbus;
+mod network;
+mod rtc;
 mod run_logic;
+mod runtime;
+mod schedule;
+mod storage;
 mod temperature_sensors;
 
 use defmt::{info, unwrap};
 use defmt_rtt as _;
 use embassy_executor::{Executor, Spawner};
 use embassy_rp::{
+    flash::Flash,
     gpio::{Level, Output},
     multicore::{spawn_core1, Stack},
     watchdog::Watchdog,
 };
+use embassy_sync::mutex::Mutex;
 use embassy_time::{Duration, Ticker};
 #[cfg(feature = "panic-probe")]
 use panic_probe as _;
 use pico_plc_bsp::peripherals::{self, PicoPlc};
 use portable_atomic as _;
 use static_cell::StaticCell;
+use storage::SharedFlash;
 
 assign_resources::assign_resources! {
     fan_relays: FanRelayResources {
@@ -28,6 +40,9 @@ assign_resources::assign_resources! {
         medium: RELAY_1,
         high: RELAY_0,
         contactor_voltage: RELAY_3,
+        aux_low: IN_2,
+        aux_medium: IN_1,
+        aux_high: IN_0,
     },
     buttons: ButtonResources {
         demand: IN_7,
@@ -46,10 +61,36 @@ assign_resources::assign_resources! {
     onewire: OnewireResources {
         data: ONEWIRE,
     },
+    rtc: RtcResources {
+        rtc: RTC,
+    },
     status: StatusResources {
         watchdog: WATCHDOG,
         led: PIN_25,
     },
+    storage: StorageResources {
+        flash: FLASH,
+    },
+    usb: UsbResources {
+        usb: USB,
+    },
+    ethernet: EthernetResources {
+        spi: SPI1,
+        clk: PIN_10,
+        mosi: PIN_11,
+        miso: PIN_12,
+        cs: PIN_13,
+        int: IO_6,
+        reset: IO_7,
+        tx_dma: DMA_CH0,
+        rx_dma: DMA_CH1,
+    },
+    rs485: Rs485Resources {
+        uart: UART0,
+        tx: PIN_16,
+        rx: PIN_17,
+        de: PIN_18,
+    },
 }
 
 #[cfg(not(feature = "panic-probe"))]
@@ -77,6 +118,7 @@ fn panic(_info: &core::panic::PanicInfo) -> ! {
 static mut CORE1_STACK: Stack<4096> = Stack::new();
 static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
 static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
+static FLASH: StaticCell<SharedFlash> = StaticCell::new();
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
@@ -85,6 +127,16 @@ async fn main(_spawner: Spawner) {
 
     info!("Version: {}", env!("VERSION"));
 
+    let mut flash = Flash::new_blocking(r.storage.flash);
+    let mut unique_id = [0; 8];
+    unwrap!(flash.blocking_unique_id(&mut unique_id));
+    let config_store = crate::config::load(&mut flash);
+    let runtime_store = crate::runtime::load(&mut flash);
+    let schedule_store = crate::schedule::load(&mut flash);
+    let flash: &'static SharedFlash = FLASH.init(Mutex::new(flash));
+
+    crate::rtc::init(r.rtc);
+
     spawn_core1(
         p.CORE1,
         unsafe { &mut *core::ptr::addr_of_mut!(CORE1_STACK) },
@@ -103,6 +155,12 @@ async fn main(_spawner: Spawner) {
     executor0.run(|spawner| {
         unwrap!(spawner.spawn(crate::temperature_sensors::task(r.onewire)));
         unwrap!(spawner.spawn(crate::display::task(r.display)));
+        unwrap!(spawner.spawn(crate::config::task(flash, config_store)));
+        unwrap!(spawner.spawn(crate::runtime::task(flash, runtime_store)));
+        unwrap!(spawner.spawn(crate::schedule::task(flash, schedule_store)));
+        unwrap!(spawner.spawn(crate::console::task(r.usb)));
+        unwrap!(spawner.spawn(crate::network::task(r.ethernet, unique_id)));
+        unwrap!(spawner.spawn(crate::modbus::task(r.rs485)));
     });
 }
 
@@ -0,0 +1,169 @@
+//! A Modbus RTU slave on an RS-485 transceiver, so that a building management system can monitor
+//! and control the air filter.
+//!
+//! The registers are described in `ms_air_filter_core::modbus::registers`.
+
+use crate::{
+    run_logic::{CONTROL_COMMANDS, STATE},
+    temperature_sensors::TEMPERATURE_READINGS,
+};
+use defmt::{info, warn};
+use embassy_rp::{
+    bind_interrupts,
+    gpio::{Level, Output},
+    peripherals::UART0,
+    uart::{self, BufferedInterruptHandler, BufferedUart, Parity},
+};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_io_async::{Read, Write};
+use ms_air_filter_core::{
+    config::Config,
+    modbus::{frame::MAX_FRAME_SIZE, handle, registers::Status},
+};
+
+bind_interrupts!(struct Irqs {
+    UART0_IRQ => BufferedInterruptHandler<UART0>;
+});
+
+/// How often the configuration is checked for the Modbus settings having changed.
+const SETTINGS_POLL: Duration = Duration::from_secs(5);
+
+#[embassy_executor::task]
+pub(super) async fn task(mut r: crate::Rs485Resources) {
+    let mut tx_buffer = [0; MAX_FRAME_SIZE];
+    let mut rx_buffer = [0; MAX_FRAME_SIZE];
+    let mut driver_enable = Output::new(r.de, Level::Low);
+
+    loop {
+        let config = crate::config::get();
+
+        if config.modbus_address == 0 {
+            Timer::after(SETTINGS_POLL).await;
+            continue;
+        }
+
+        let mut uart_config = uart::Config::default();
+        uart_config.baudrate = config.modbus_baud;
+        uart_config.parity = Parity::ParityEven;
+        let mut uart = BufferedUart::new(
+            &mut r.uart,
+            Irqs,
+            &mut r.tx,
+            &mut r.rx,
+            &mut tx_buffer,
+            &mut rx_buffer,
+            uart_config,
+        );
+        info!(
+            "Modbus slave {} at {} baud",
+            config.modbus_address, config.modbus_baud
+        );
+
+        // 3.5 characters of 11 bits, fixed at 1.75ms above 19200 baud by the specification
+        let silence = Duration::from_micros((3_500_000 * 11 / config.modbus_baud as u64).max(1750));
+
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let mut reply = [0; MAX_FRAME_SIZE];
+
+        loop {
+            match with_timeout(SETTINGS_POLL, receive(&mut uart, &mut frame, silence)).await {
+                Ok(Ok(len)) => {
+                    let state = STATE.try_get().unwrap_or_default();
+                    let fan = crate::fan::status();
+                    let meter = crate::runtime::get();
+                    let temperatures = TEMPERATURE_READINGS.try_get().unwrap_or_default();
+                    let current_config = crate::config::get();
+                    let status = Status {
+                        state: &state,
+                        fan: &fan,
+                        meter: &meter,
+                        temperatures: &temperatures,
+                        config: &current_config,
+                    };
+
+                    let (reply_len, changes) =
+                        handle(config.modbus_address, &frame[..len], &status, &mut reply);
+
+                    if let Some(command) = changes.command {
+                        info!("Modbus command: {:?}", command);
+                        if CONTROL_COMMANDS
+                            .publisher()
+                            .unwrap()
+                            .try_publish(command)
+                            .is_err()
+                        {
+                            warn!("Run logic busy, Modbus command dropped");
+                        }
+                    }
+                    if let Some(new_config) = changes.config {
+                        info!("Config changed over Modbus");
+                        if let Err(e) = crate::config::set(new_config) {
+                            warn!("Failed to set config: {:?}", e);
+                        }
+                    }
+
+                    if let Some(reply_len) = reply_len {
+                        // The transceiver only drives the bus while replying
+                        driver_enable.set_high();
+                        let result = uart.write_all(&reply[..reply_len]).await;
+                        let result = match result {
+                            Ok(()) => uart.flush().await,
+                            Err(e) => Err(e),
+                        };
+                        driver_enable.set_low();
+
+                        if let Err(e) = result {
+                            warn!("Failed to send Modbus reply: {:?}", e);
+                        }
+                    }
+                }
+                Ok(Err(e)) => warn!("Modbus receive error: {:?}", e),
+                // Nothing received, which is a chance to check the settings
+                Err(_) => {}
+            }
+
+            if modbus_settings(&crate::config::get()) != modbus_settings(&config) {
+                info!("Modbus config changed");
+                break;
+            }
+        }
+    }
+}
+
+/// Receives a frame, which ends with 3.5 characters of silence on the line.
+///
+/// A frame too long to be valid is returned as empty, so that it is ignored.
+async fn receive(
+    uart: &mut BufferedUart<'_, UART0>,
+    frame: &mut [u8; MAX_FRAME_SIZE],
+    silence: Duration,
+) -> Result<usize, uart::Error> {
+    let mut len = uart.read(frame).await?;
+    let mut overlong = false;
+    let mut discard = [0; 16];
+
+    loop {
+        let buf = if len < MAX_FRAME_SIZE {
+            &mut frame[len..]
+        } else {
+            &mut discard[..]
+        };
+
+        match with_timeout(silence, uart.read(buf)).await {
+            Ok(n) => {
+                let n = n?;
+                if len < MAX_FRAME_SIZE {
+                    len += n;
+                } else {
+                    overlong = true;
+                }
+            }
+            Err(_) => return Ok(if overlong { 0 } else { len }),
+        }
+    }
+}
+
+/// The settings that the UART is set up with.
+fn modbus_settings(config: &Config) -> (u8, u32) {
+    (config.modbus_address, config.modbus_baud)
+}
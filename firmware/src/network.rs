@@ -0,0 +1,297 @@
+//! Publishes telemetry to, and takes commands from, an MQTT broker through a W5500 Ethernet
+//! module.
+//!
+//! The air filter works just the same without the module fitted or a broker configured.
+
+use crate::{
+    config::Config,
+    fan::FAN_STATUS,
+    run_logic::{CONTROL_COMMANDS, STATE},
+    temperature_sensors::TEMPERATURE_READINGS,
+};
+use core::fmt::Write;
+use defmt::{error, info, unwrap, warn, Display2Format};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, select3, Either3};
+use embassy_net::{
+    tcp::{self, TcpSocket},
+    Ipv4Address, StackResources,
+};
+use embassy_net_wiznet::{chip::W5500, Device, Runner, State as WiznetState};
+use embassy_rp::{
+    clocks::RoscRng,
+    gpio::{Input, Level, Output, Pull},
+    peripherals::SPI1,
+    spi::{self, Async, Spi},
+};
+use embassy_time::{Delay, Duration, Ticker, Timer};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use ms_air_filter_core::{
+    mqtt::{
+        discovery::{Discovery, Entity},
+        telemetry::{
+            parse_command, temperature_payload, CommandError, Telemetry, Topics, AVAILABILITY,
+            COMMAND, COMMAND_OK, COMMAND_RESULT, OFFLINE, ONLINE,
+        },
+        Client, Connect, Error, Will,
+    },
+    temperature::MAX_SENSORS,
+};
+use portable_atomic::{AtomicBool, Ordering};
+use rand_core::RngCore;
+use static_cell::StaticCell;
+
+/// Whether there is currently a connection to the MQTT broker.
+pub(crate) static MQTT_CONNECTED: AtomicBool = AtomicBool::new(false);
+
+/// How long to wait after failing to connect (or losing the connection) before trying again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long to wait for the broker to acknowledge anything that is sent.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+type EthernetSpi = ExclusiveDevice<Spi<'static, SPI1, Async>, Output<'static>, Delay>;
+
+#[embassy_executor::task]
+async fn ethernet_task(
+    runner: Runner<'static, W5500, EthernetSpi, Input<'static>, Output<'static>>,
+) -> ! {
+    runner.run().await
+}
+
+#[embassy_executor::task]
+async fn net_task(mut runner: embassy_net::Runner<'static, Device<'static>>) -> ! {
+    runner.run().await
+}
+
+#[embassy_executor::task]
+pub(super) async fn task(r: crate::EthernetResources, unique_id: [u8; 8]) {
+    let spawner = Spawner::for_current_executor().await;
+
+    let mac = mac_address(&unique_id);
+    let mut device_id = heapless::String::<6>::new();
+    let _ = write!(device_id, "{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]);
+    info!("MAC address: {:02x}, device ID: {}", mac, device_id);
+
+    let mut config = spi::Config::default();
+    config.frequency = 50_000_000;
+    let spi = Spi::new(r.spi, r.clk, r.mosi, r.miso, r.tx_dma, r.rx_dma, config);
+    let cs = Output::new(r.cs, Level::High);
+    let int = Input::new(r.int, Pull::Up);
+    let reset = Output::new(r.reset, Level::High);
+
+    static WIZNET_STATE: StaticCell<WiznetState<4, 4>> = StaticCell::new();
+    let (device, runner) = match embassy_net_wiznet::new(
+        mac,
+        WIZNET_STATE.init(WiznetState::new()),
+        unwrap!(ExclusiveDevice::new(spi, cs, Delay)),
+        int,
+        reset,
+    )
+    .await
+    {
+        Ok(w5500) => w5500,
+        Err(_) => {
+            error!("No W5500 Ethernet module found, MQTT is unavailable");
+            return;
+        }
+    };
+    unwrap!(spawner.spawn(ethernet_task(runner)));
+
+    static RESOURCES: StaticCell<StackResources<2>> = StaticCell::new();
+    let (stack, runner) = embassy_net::new(
+        device,
+        embassy_net::Config::dhcpv4(Default::default()),
+        RESOURCES.init(StackResources::new()),
+        RoscRng.next_u64(),
+    );
+    unwrap!(spawner.spawn(net_task(runner)));
+
+    let topics = Topics::new(&device_id);
+
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+
+    loop {
+        let config = crate::config::get();
+
+        if config.mqtt_broker != [0; 4] {
+            stack.wait_config_up().await;
+
+            let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+            socket.set_timeout(Some(SOCKET_TIMEOUT));
+
+            let broker = Ipv4Address::from(config.mqtt_broker);
+            let broker_display = Display2Format(&broker);
+            match socket.connect((broker, config.mqtt_port)).await {
+                Ok(()) => {
+                    info!("Connected to MQTT broker at {}", broker_display);
+                    let result =
+                        session(Client::new(&mut socket), &topics, &device_id, &config).await;
+                    MQTT_CONNECTED.store(false, Ordering::Relaxed);
+
+                    if let Err(e) = result {
+                        warn!("MQTT connection lost: {:?}", e);
+                    }
+                }
+                Err(e) => warn!(
+                    "Failed to connect to MQTT broker at {}: {:?}",
+                    broker_display, e
+                ),
+            }
+        }
+
+        Timer::after(RECONNECT_DELAY).await;
+    }
+}
+
+/// Publishes telemetry (and the Home Assistant discovery configs) and passes on commands until
+/// the connection is lost, or the broker settings are changed.
+async fn session(
+    mut client: Client<&mut TcpSocket<'_>>,
+    topics: &Topics,
+    device_id: &str,
+    config: &Config,
+) -> Result<(), Error<tcp::Error>> {
+    let availability = topics.get(AVAILABILITY);
+    client
+        .connect(&Connect {
+            client_id: topics.prefix(),
+            keep_alive_secs: config.mqtt_keep_alive_secs(),
+            will: Some(Will {
+                topic: &availability,
+                message: OFFLINE.as_bytes(),
+                retain: true,
+            }),
+            username: None,
+            password: None,
+        })
+        .await?;
+    client.subscribe(&topics.get(COMMAND)).await?;
+    client
+        .publish(&availability, ONLINE.as_bytes(), true)
+        .await?;
+    MQTT_CONNECTED.store(true, Ordering::Relaxed);
+
+    let discovery = Discovery::new(topics, device_id, env!("VERSION"));
+    for entity in Entity::FIXED.iter() {
+        client
+            .publish(
+                &discovery.topic(entity),
+                discovery.config(entity).as_bytes(),
+                true,
+            )
+            .await?;
+    }
+    // Sensors are announced as they are found
+    let mut announced = heapless::Vec::<u64, MAX_SENSORS>::new();
+
+    let mut state_rx = unwrap!(STATE.receiver());
+    let mut fan_rx = unwrap!(FAN_STATUS.receiver());
+    let mut ticker = Ticker::every(config.mqtt_interval);
+    let mut last: Option<Telemetry> = None;
+    let mut publish_all = true;
+
+    loop {
+        if publish_all {
+            publish_all = false;
+
+            // Everything is published again, which also keeps the connection open
+            last = None;
+            let readings = TEMPERATURE_READINGS.try_get().unwrap_or_default();
+            for sensor in readings.iter() {
+                if !announced.contains(&sensor.address) {
+                    let entity = Entity::Temperature(sensor.address);
+                    client
+                        .publish(
+                            &discovery.topic(&entity),
+                            discovery.config(&entity).as_bytes(),
+                            true,
+                        )
+                        .await?;
+                    let _ = announced.push(sensor.address);
+                }
+
+                client
+                    .publish(
+                        &topics.temperature(sensor.address),
+                        temperature_payload(sensor).as_bytes(),
+                        true,
+                    )
+                    .await?;
+            }
+        }
+
+        let telemetry = Telemetry::new(
+            &STATE.try_get().unwrap_or_default(),
+            &crate::fan::status(),
+            &crate::runtime::get(),
+            config,
+        );
+        for (name, value) in telemetry.changes(last.as_ref()) {
+            client
+                .publish(&topics.get(name), value.as_bytes(), true)
+                .await?;
+        }
+        last = Some(telemetry);
+
+        match select3(
+            client.receive(),
+            select(state_rx.changed(), fan_rx.changed()),
+            ticker.next(),
+        )
+        .await
+        {
+            Either3::First(message) => {
+                let result = parse_command(message?.payload).and_then(|command| {
+                    info!("MQTT command: {:?}", command);
+                    // Not waited for, as the connection has to be kept serviced
+                    CONTROL_COMMANDS
+                        .publisher()
+                        .unwrap()
+                        .try_publish(command)
+                        .map_err(|_| CommandError::Busy)
+                });
+                let outcome = match result {
+                    Ok(()) => COMMAND_OK,
+                    Err(e) => {
+                        warn!("MQTT command not carried out: {:?}", e);
+                        e.description()
+                    }
+                };
+                client
+                    .publish(&topics.get(COMMAND_RESULT), outcome.as_bytes(), false)
+                    .await?;
+            }
+            // Whatever changed is published at the top of the loop
+            Either3::Second(_) => {}
+            Either3::Third(()) => {
+                if mqtt_settings(&crate::config::get()) != mqtt_settings(config) {
+                    info!("MQTT config changed, reconnecting");
+                    // A clean disconnect does not publish the will
+                    client
+                        .publish(&availability, OFFLINE.as_bytes(), true)
+                        .await?;
+                    return client.disconnect().await;
+                }
+
+                publish_all = true;
+            }
+        }
+    }
+}
+
+/// The settings that a connection to the broker is made with.
+fn mqtt_settings(config: &Config) -> ([u8; 4], u16, Duration) {
+    (config.mqtt_broker, config.mqtt_port, config.mqtt_interval)
+}
+
+/// A locally administered MAC address that is unique to the board, taken from the flash chip's
+/// unique ID.
+fn mac_address(unique_id: &[u8; 8]) -> [u8; 6] {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&unique_id[2..]);
+    // Locally administered, unicast
+    mac[0] = (mac[0] | 0x02) & !0x01;
+    mac
+}
@@ -0,0 +1,77 @@
+//! The date and time of day, kept by the RP2040 real time clock.
+//!
+//! The clock is not battery backed, so it has to be set again (over the console) after the power
+//! has been off.
+
+use core::cell::RefCell;
+use defmt::{info, warn, Debug2Format};
+use embassy_rp::{
+    peripherals::RTC,
+    rtc::{DateTime as RtcDateTime, DayOfWeek, Rtc},
+};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use ms_air_filter_core::time::Weekday;
+
+pub(crate) use ms_air_filter_core::time::DateTime;
+
+static CLOCK: Mutex<CriticalSectionRawMutex, RefCell<Option<Rtc<'static, RTC>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Starts using the real time clock, this should be called before anything asks for the time.
+pub(crate) fn init(r: crate::RtcResources) {
+    let rtc = Rtc::new(r.rtc);
+    if !rtc.is_running() {
+        warn!("Clock is not set, the schedule will not be followed until it is");
+    }
+    CLOCK.lock(|clock| clock.replace(Some(rtc)));
+}
+
+/// The current date and time, `None` if the clock has not been set.
+pub(crate) fn now() -> Option<DateTime> {
+    CLOCK.lock(|clock| {
+        let now = clock.borrow().as_ref()?.now().ok()?;
+        Some(DateTime {
+            year: now.year,
+            month: now.month,
+            day: now.day,
+            hour: now.hour,
+            minute: now.minute,
+            second: now.second,
+        })
+    })
+}
+
+/// Sets the clock.
+pub(crate) fn set(datetime: &DateTime) -> Result<(), ()> {
+    let day_of_week = match datetime.weekday() {
+        Weekday::Monday => DayOfWeek::Monday,
+        Weekday::Tuesday => DayOfWeek::Tuesday,
+        Weekday::Wednesday => DayOfWeek::Wednesday,
+        Weekday::Thursday => DayOfWeek::Thursday,
+        Weekday::Friday => DayOfWeek::Friday,
+        Weekday::Saturday => DayOfWeek::Saturday,
+        Weekday::Sunday => DayOfWeek::Sunday,
+    };
+
+    let result = CLOCK.lock(|clock| {
+        clock
+            .borrow_mut()
+            .as_mut()
+            .ok_or(())?
+            .set_datetime(RtcDateTime {
+                year: datetime.year,
+                month: datetime.month,
+                day: datetime.day,
+                day_of_week,
+                hour: datetime.hour,
+                minute: datetime.minute,
+                second: datetime.second,
+            })
+            .map_err(|e| warn!("Failed to set clock: {:?}", Debug2Format(&e)))
+    });
+
+    if result.is_ok() {
+        info!("Clock set to {}", datetime);
+    }
+    result
+}
@@ -0,0 +1,97 @@
+use crate::{
+    display::FAN_BUTTON_EVENTS,
+    fan::{FanStatus, FAN_COMMAND, FAN_STATUS},
+    temperature_sensors::TEMPERATURE_READINGS,
+};
+use defmt::{info, warn};
+use embassy_futures::select::{select, select4, Either, Either4};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    pubsub::{PubSubChannel, WaitResult},
+    watch::Watch,
+};
+use embassy_time::{Duration, Ticker, Timer};
+use ms_air_filter_core::run_logic::Triggers;
+
+pub(crate) use ms_air_filter_core::run_logic::{ControlCommand, State};
+
+/// The latest state of the run logic, anything that only needs to show it can just look at the
+/// newest one.
+pub(crate) static STATE: Watch<CriticalSectionRawMutex, State, 2> = Watch::new();
+
+/// Requests to control the fan from anywhere other than the buttons.
+/// Deep enough for a burst of remote commands (e.g. a home automation scene) to be queued while
+/// the run logic is busy, as they are not waited for.
+pub(crate) static CONTROL_COMMANDS: PubSubChannel<
+    CriticalSectionRawMutex,
+    ControlCommand,
+    16,
+    1,
+    3,
+> = PubSubChannel::new();
+
+#[embassy_executor::task]
+pub(super) async fn task() {
+    let mut triggers = Triggers::default();
+
+    let mut tick_1hz = Ticker::every(Duration::from_hz(1));
+    let mut button_sub = FAN_BUTTON_EVENTS.subscriber().unwrap();
+    let mut temperature_rx = TEMPERATURE_READINGS.receiver().unwrap();
+    let mut control_sub = CONTROL_COMMANDS.subscriber().unwrap();
+    let mut fan_status_rx = FAN_STATUS.receiver().unwrap();
+    let state_tx = STATE.sender();
+    let fan_tx = FAN_COMMAND.sender();
+
+    // Send an empty state initially (this should be sent while the splash screen is on display)
+    Timer::after_millis(500).await;
+    state_tx.send(triggers.resolve());
+
+    loop {
+        let changed = match select4(
+            tick_1hz.next(),
+            button_sub.next_message(),
+            temperature_rx.changed(),
+            select(control_sub.next_message(), fan_status_rx.changed()),
+        )
+        .await
+        {
+            Either4::First(_) => {
+                // Not short circuited, every trigger has to keep up with the time
+                let now = crate::rtc::now().map(|now| now.week_time());
+                triggers.button.handle_tick(&crate::config::get())
+                    | triggers.schedule.handle_time(now, &crate::schedule::get())
+            }
+            Either4::Second(event) => match event {
+                WaitResult::Lagged(count) => {
+                    warn!("Subscriber lagged, lost {} messages", count);
+                    false
+                }
+                WaitResult::Message(event) => triggers.handle_button(event, &crate::config::get()),
+            },
+            Either4::Third(readings) => triggers.handle_readings(&readings, &crate::config::get()),
+            Either4::Fourth(Either::First(command)) => match command {
+                WaitResult::Lagged(count) => {
+                    warn!("Subscriber lagged, lost {} messages", count);
+                    false
+                }
+                WaitResult::Message(command) => {
+                    info!("Control command: {:?}", command);
+                    triggers
+                        .button
+                        .handle_command(command, &crate::config::get())
+                }
+            },
+            Either4::Fourth(Either::Second(FanStatus::Fault(_))) => {
+                triggers.handle_contactor_fault()
+            }
+            Either4::Fourth(Either::Second(_)) => false,
+        };
+
+        if changed {
+            let state = triggers.resolve();
+            info!("New state: {:?}", state);
+            fan_tx.send(state.fan_command());
+            state_tx.send(state);
+        }
+    }
+}
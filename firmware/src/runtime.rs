@@ -0,0 +1,131 @@
+use crate::{
+    fan::{FanSpeed, FAN_STATUS},
+    storage::{RecordStore, SharedFlash, StorageFlash, MAX_RECORD_SIZE, RUNTIME_STORE},
+};
+use core::cell::RefCell;
+use defmt::{info, warn};
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Ticker};
+use ms_air_filter_core::runtime::{ENCODED_SIZE, RUNTIME_VERSION};
+
+pub(crate) use ms_air_filter_core::runtime::RuntimeMeter;
+
+const _: () = assert!(ENCODED_SIZE <= MAX_RECORD_SIZE);
+
+/// How often the run time is updated while the fan is running.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the run time is saved while the fan is running.
+/// This is the most run time that can be lost when the power is cut.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+static METER: Mutex<CriticalSectionRawMutex, RefCell<RuntimeMeter>> =
+    Mutex::new(RefCell::new(RuntimeMeter::new()));
+
+static FILTER_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Returns a copy of the current run time.
+pub(crate) fn get() -> RuntimeMeter {
+    METER.lock(|meter| meter.borrow().clone())
+}
+
+/// Records that the filters have been changed, this will be saved to flash shortly after.
+pub(crate) fn filter_changed() {
+    FILTER_CHANGED.signal(());
+}
+
+/// Loads the run time from flash, starting from zero if there is nothing usable stored.
+///
+/// The returned store should be given to [`task`] to save future changes.
+pub(crate) fn load(flash: &mut StorageFlash) -> RecordStore {
+    let mut store = RUNTIME_STORE;
+    let mut buf = [0_u8; MAX_RECORD_SIZE];
+
+    let meter = match store.load(flash, &mut buf) {
+        Some((RUNTIME_VERSION, len)) => RuntimeMeter::decode(&buf[..len]),
+        Some((version, _)) => {
+            warn!("Stored run time has unsupported version {}", version);
+            None
+        }
+        None => None,
+    };
+
+    match meter {
+        Some(meter) => {
+            info!("Loaded run time: {:?}", meter);
+            METER.lock(|m| m.replace(meter));
+        }
+        None => warn!("No run time stored, starting from zero"),
+    }
+
+    store
+}
+
+#[embassy_executor::task]
+pub(super) async fn task(flash: &'static SharedFlash, mut store: RecordStore) {
+    let mut fan_rx = FAN_STATUS.receiver().unwrap();
+    let mut update_ticker = Ticker::every(UPDATE_INTERVAL);
+
+    // Only time spent actually running counts, not time spent switching the contactors
+    let mut running: Option<FanSpeed> = None;
+    let mut last_update = Instant::now();
+    let mut last_save = Instant::now();
+
+    loop {
+        let save = match select3(
+            fan_rx.changed(),
+            update_ticker.next(),
+            FILTER_CHANGED.wait(),
+        )
+        .await
+        {
+            Either3::First(status) => {
+                update(running.as_ref(), &mut last_update);
+
+                // Save as soon as the fan stops, so that nothing is lost while it is stopped
+                let new_running = status.running_speed().cloned();
+                let stopped = new_running.is_none() && running.is_some();
+                running = new_running;
+                stopped
+            }
+            Either3::Second(_) => {
+                update(running.as_ref(), &mut last_update);
+                running.is_some() && last_save.elapsed() >= SAVE_INTERVAL
+            }
+            Either3::Third(_) => {
+                update(running.as_ref(), &mut last_update);
+                info!("Filters changed");
+                METER.lock(|meter| meter.borrow_mut().filter_changed());
+                true
+            }
+        };
+
+        if save {
+            let mut buf = [0_u8; ENCODED_SIZE];
+            let len = get().encode(&mut buf);
+
+            let result = store.save(&mut *flash.lock().await, RUNTIME_VERSION, &buf[..len]);
+            match result {
+                Ok(()) => info!("Run time saved"),
+                Err(e) => warn!("Failed to save run time: {:?}", e),
+            }
+
+            last_save = Instant::now();
+        }
+    }
+}
+
+/// Adds the time the fan has been running at `speed` (if it has been) since the last update.
+fn update(running: Option<&FanSpeed>, last_update: &mut Instant) {
+    let now = Instant::now();
+
+    if let Some(speed) = running {
+        METER.lock(|meter| meter.borrow_mut().add(speed, now - *last_update));
+    }
+
+    *last_update = now;
+}
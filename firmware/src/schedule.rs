@@ -0,0 +1,74 @@
+use crate::storage::{RecordStore, SharedFlash, StorageFlash, MAX_RECORD_SIZE, SCHEDULE_STORE};
+use core::cell::RefCell;
+use defmt::{info, warn};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use ms_air_filter_core::schedule::{ENCODED_SIZE, SCHEDULE_VERSION};
+
+pub(crate) use ms_air_filter_core::schedule::Schedule;
+
+const _: () = assert!(ENCODED_SIZE <= MAX_RECORD_SIZE);
+
+static SCHEDULE: Mutex<CriticalSectionRawMutex, RefCell<Schedule>> =
+    Mutex::new(RefCell::new(Schedule::new()));
+
+static SAVE_REQUESTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Returns a copy of the current schedule.
+pub(crate) fn get() -> Schedule {
+    SCHEDULE.lock(|schedule| schedule.borrow().clone())
+}
+
+/// Replaces the current schedule, it will be saved to flash shortly after.
+pub(crate) fn set(schedule: Schedule) {
+    SCHEDULE.lock(|s| s.replace(schedule));
+    SAVE_REQUESTED.signal(());
+}
+
+/// Loads the schedule from flash, starting with an empty schedule if there is nothing usable
+/// stored.
+///
+/// The returned store should be given to [`task`] to save future changes.
+pub(crate) fn load(flash: &mut StorageFlash) -> RecordStore {
+    let mut store = SCHEDULE_STORE;
+    let mut buf = [0_u8; MAX_RECORD_SIZE];
+
+    let schedule = match store.load(flash, &mut buf) {
+        Some((version, len)) => {
+            let schedule = Schedule::decode(version, &buf[..len]);
+            if schedule.is_none() {
+                warn!("Stored schedule (version {}) could not be read", version);
+            }
+            schedule
+        }
+        None => None,
+    };
+
+    match schedule {
+        Some(schedule) => {
+            info!("Loaded schedule: {:?}", schedule);
+            SCHEDULE.lock(|s| s.replace(schedule));
+        }
+        None => warn!("No schedule stored, starting with an empty schedule"),
+    }
+
+    store
+}
+
+#[embassy_executor::task]
+pub(super) async fn task(flash: &'static SharedFlash, mut store: RecordStore) {
+    loop {
+        SAVE_REQUESTED.wait().await;
+
+        let mut buf = [0_u8; ENCODED_SIZE];
+        let len = get().encode(&mut buf);
+
+        let result = store.save(&mut *flash.lock().await, SCHEDULE_VERSION, &buf[..len]);
+        match result {
+            Ok(()) => info!("Schedule saved"),
+            Err(e) => warn!("Failed to save schedule: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Wear levelled storage of small records in the flash region reserved by `memory.x`.
+//!
+//! A store occupies a number of erase sectors which are divided into page sized slots.
+//! Each save writes the record to the slot following the previous one (erasing sectors only
+//! when they are reached), so every slot is written once before any is erased again.
+//! The record with the highest sequence number and a valid CRC is the current one.
+
+use defmt::{warn, Format};
+use embassy_rp::{
+    flash::{Blocking, Flash, ERASE_SIZE, PAGE_SIZE},
+    peripherals::FLASH,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embedded_storage::nor_flash::NorFlash;
+
+pub(crate) const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// The start of the flash region that is not used for the program (see `memory.x`).
+const STORAGE_OFFSET: u32 = (FLASH_SIZE - 64 * 1024) as u32;
+
+/// Where configuration is stored (two sectors).
+pub(crate) const CONFIG_STORE: RecordStore = RecordStore::new(STORAGE_OFFSET, 2, 0x4346_4731);
+
+/// Where the fan run time is stored (four sectors, as it is saved much more often than the
+/// configuration).
+pub(crate) const RUNTIME_STORE: RecordStore =
+    RecordStore::new(STORAGE_OFFSET + 2 * ERASE_SIZE as u32, 4, 0x5254_4D31);
+
+/// Where the weekly schedule is stored (two sectors).
+pub(crate) const SCHEDULE_STORE: RecordStore =
+    RecordStore::new(STORAGE_OFFSET + 6 * ERASE_SIZE as u32, 2, 0x5343_4831);
+
+pub(crate) type StorageFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+/// Flash shared between the tasks that save to it.
+///
+/// This is an async mutex so that no critical section is held while a sector is erased or
+/// written. Core 1 is paused for the duration of a flash operation, if it was waiting on a
+/// critical section held by core 0 at that point neither core could continue.
+pub(crate) type SharedFlash = Mutex<CriticalSectionRawMutex, StorageFlash>;
+
+const SLOT_SIZE: usize = PAGE_SIZE;
+const HEADER_SIZE: usize = 12;
+const CRC_SIZE: usize = 4;
+
+/// The largest record that can be kept in a store.
+pub(crate) const MAX_RECORD_SIZE: usize = SLOT_SIZE - HEADER_SIZE - CRC_SIZE;
+
+const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+#[derive(Debug, Format)]
+pub(crate) enum Error {
+    TooLarge,
+    Flash,
+}
+
+pub(crate) struct RecordStore {
+    offset: u32,
+    sectors: u32,
+    magic: u32,
+
+    /// Slot index and sequence number of the current record
+    latest: Option<(u32, u32)>,
+}
+
+impl RecordStore {
+    pub(crate) const fn new(offset: u32, sectors: u32, magic: u32) -> Self {
+        Self {
+            offset,
+            sectors,
+            magic,
+            latest: None,
+        }
+    }
+
+    fn slots_per_sector(&self) -> u32 {
+        (ERASE_SIZE / SLOT_SIZE) as u32
+    }
+
+    fn slot_count(&self) -> u32 {
+        self.sectors * self.slots_per_sector()
+    }
+
+    fn slot_offset(&self, slot: u32) -> u32 {
+        self.offset + slot * SLOT_SIZE as u32
+    }
+
+    /// Finds the latest valid record, copying it into `buf`.
+    /// Returns the record version and length.
+    ///
+    /// This must be called before the first call to [`RecordStore::save`].
+    pub(crate) fn load<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        buf: &mut [u8; MAX_RECORD_SIZE],
+    ) -> Option<(u16, usize)> {
+        let mut slot_buf = [0_u8; SLOT_SIZE];
+        let mut found = None;
+
+        for slot in 0..self.slot_count() {
+            if flash.read(self.slot_offset(slot), &mut slot_buf).is_err() {
+                warn!("Failed to read storage slot {}", slot);
+                continue;
+            }
+
+            if let Some(record) = self.parse_slot(&slot_buf) {
+                if found.is_none_or(|(_, sequence, _, _)| record.sequence > sequence) {
+                    buf[..record.payload.len()].copy_from_slice(record.payload);
+                    found = Some((slot, record.sequence, record.version, record.payload.len()));
+                }
+            }
+        }
+
+        self.latest = found.map(|(slot, sequence, _, _)| (slot, sequence));
+        found.map(|(_, _, version, len)| (version, len))
+    }
+
+    /// Writes a new record, superseding the current one.
+    pub(crate) fn save<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        version: u16,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        if payload.len() > MAX_RECORD_SIZE {
+            return Err(Error::TooLarge);
+        }
+
+        let (mut slot, sequence) = match self.latest {
+            Some((slot, sequence)) => ((slot + 1) % self.slot_count(), sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        // Anything left in a slot that is not the start of a sector is the remains of an
+        // interrupted write, which cannot be written over without erasing the whole sector.
+        // Skip to the next sector instead so that the current record is kept.
+        let mut slot_buf = [0_u8; SLOT_SIZE];
+        if slot % self.slots_per_sector() != 0 {
+            flash
+                .read(self.slot_offset(slot), &mut slot_buf)
+                .map_err(|_| Error::Flash)?;
+            if slot_buf.iter().any(|b| *b != 0xFF) {
+                warn!(
+                    "Storage slot {} is not blank, skipping to next sector",
+                    slot
+                );
+                slot =
+                    (slot / self.slots_per_sector() + 1) % self.sectors * self.slots_per_sector();
+            }
+        }
+
+        if slot % self.slots_per_sector() == 0 {
+            let sector_start = self.slot_offset(slot);
+            flash
+                .erase(sector_start, sector_start + ERASE_SIZE as u32)
+                .map_err(|_| Error::Flash)?;
+        }
+
+        slot_buf.fill(0xFF);
+        slot_buf[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        slot_buf[4..8].copy_from_slice(&sequence.to_le_bytes());
+        slot_buf[8..10].copy_from_slice(&version.to_le_bytes());
+        slot_buf[10..12].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        let crc_offset = HEADER_SIZE + payload.len();
+        slot_buf[HEADER_SIZE..crc_offset].copy_from_slice(payload);
+        let crc = CRC.checksum(&slot_buf[..crc_offset]);
+        slot_buf[crc_offset..crc_offset + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+        flash
+            .write(self.slot_offset(slot), &slot_buf)
+            .map_err(|_| Error::Flash)?;
+
+        self.latest = Some((slot, sequence));
+        Ok(())
+    }
+
+    fn parse_slot<'a>(&self, slot: &'a [u8; SLOT_SIZE]) -> Option<StoredRecord<'a>> {
+        let magic = u32::from_le_bytes(slot[0..4].try_into().unwrap());
+        if magic != self.magic {
+            return None;
+        }
+
+        let len = u16::from_le_bytes(slot[10..12].try_into().unwrap()) as usize;
+        if len > MAX_RECORD_SIZE {
+            return None;
+        }
+
+        let crc_offset = HEADER_SIZE + len;
+        let crc = u32::from_le_bytes(slot[crc_offset..crc_offset + CRC_SIZE].try_into().unwrap());
+        if crc != CRC.checksum(&slot[..crc_offset]) {
+            return None;
+        }
+
+        Some(StoredRecord {
+            sequence: u32::from_le_bytes(slot[4..8].try_into().unwrap()),
+            version: u16::from_le_bytes(slot[8..10].try_into().unwrap()),
+            payload: &slot[HEADER_SIZE..crc_offset],
+        })
+    }
+}
+
+struct StoredRecord<'a> {
+    sequence: u32,
+    version: u16,
+    payload: &'a [u8],
+}
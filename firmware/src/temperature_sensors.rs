@@ -1,18 +1,33 @@
 use defmt::{debug, info, warn};
 use ds18b20::Resolution;
-use embassy_time::{Delay, Duration, Ticker, Timer};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::{Delay, Instant, Timer};
+
+pub(crate) use ms_air_filter_core::temperature::TemperatureReadings;
+
+/// The readings from the latest poll of the sensors, which include everything from the polls
+/// before them.
+pub(crate) static TEMPERATURE_READINGS: Watch<CriticalSectionRawMutex, TemperatureReadings, 2> =
+    Watch::new();
 
 #[embassy_executor::task]
 pub(super) async fn task(r: crate::OnewireResources) {
     let mut bus = pico_plc_bsp::onewire::new(r.data).unwrap();
 
-    let mut ticker = Ticker::every(Duration::from_secs(10));
+    let tx = TEMPERATURE_READINGS.sender();
+
+    let mut readings = TemperatureReadings::default();
 
     loop {
+        let poll_start = Instant::now();
+
         ds18b20::start_simultaneous_temp_measurement(&mut bus, &mut Delay).unwrap();
 
         Timer::after_millis(Resolution::Bits12.max_measurement_time_millis() as u64).await;
 
+        // Anything not found in this search is reported as stale
+        readings.mark_all_stale();
+
         let mut search_state = None;
         while let Some((device_address, state)) = bus
             .device_search(search_state.as_ref(), false, &mut Delay)
@@ -24,16 +39,22 @@ pub(super) async fn task(r: crate::OnewireResources) {
                 debug!("Found DS18B20 at address: {}", device_address.0);
 
                 let sensor = ds18b20::Ds18b20::new::<()>(device_address).unwrap();
-                match sensor.read_data(&mut bus, &mut Delay) {
+                let recorded = match sensor.read_data(&mut bus, &mut Delay) {
                     Ok(sensor_data) => {
                         info!(
                             "DS18B20 {} is {}°C",
                             device_address.0, sensor_data.temperature
                         );
+                        readings.record(device_address.0, sensor_data.temperature, Instant::now())
                     }
                     Err(_) => {
                         warn!("Failed to read DS18B20 at {}", device_address.0);
+                        readings.record_error(device_address.0)
                     }
+                };
+
+                if !recorded {
+                    warn!("Too many DS18B20 sensors, ignoring {}", device_address.0);
                 }
             } else {
                 info!(
@@ -43,6 +64,12 @@ pub(super) async fn task(r: crate::OnewireResources) {
             }
         }
 
-        ticker.next().await;
+        for sensor in readings.iter().filter(|s| s.stale) {
+            warn!("DS18B20 at {} is stale", sensor.address);
+        }
+
+        tx.send(readings.clone());
+
+        Timer::at(poll_start + crate::config::get().temperature_poll_interval).await;
     }
 }
@@ -0,0 +1,77 @@
+//! Runs the controller's run logic and screens on the host.
+//!
+//! By default a script of inputs (see [`script`]) is read from a file or stdin and the display is
+//! rendered to an in-memory framebuffer, with `snapshot` commands saving it as PNG images.
+//! When built with the `window` feature the display can also be shown in a window and controlled
+//! with the keyboard.
+
+mod script;
+mod simulation;
+#[cfg(feature = "window")]
+mod window;
+
+use anyhow::Context;
+use clap::Parser;
+use ms_air_filter_core::config::Config;
+use script::Action;
+use simulation::Simulation;
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Cli {
+    /// Script of inputs to run, read from stdin if not given
+    script: Option<PathBuf>,
+
+    /// Directory that snapshots are saved in
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+
+    /// Show the display in a window and take input from the keyboard instead of a script
+    /// (requires the `window` feature)
+    #[arg(short, long, conflicts_with = "script")]
+    window: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let mut simulation = Simulation::new(Config::DEFAULT);
+
+    if cli.window {
+        #[cfg(feature = "window")]
+        return window::run(&mut simulation, &cli.output);
+        #[cfg(not(feature = "window"))]
+        anyhow::bail!("the simulator was built without the window feature");
+    }
+
+    let input: Box<dyn BufRead> = match &cli.script {
+        Some(path) => Box::new(BufReader::new(
+            std::fs::File::open(path)
+                .with_context(|| format!("failed to open {}", path.display()))?,
+        )),
+        None => Box::new(std::io::stdin().lock()),
+    };
+
+    for (number, line) in input.lines().enumerate() {
+        let action = script::parse_line(&line?).with_context(|| format!("line {}", number + 1))?;
+
+        match action {
+            Some(Action::Press { button, long }) => simulation.press(button, long),
+            Some(Action::Wait(duration)) => simulation.advance(duration),
+            Some(Action::Temperature { probe, temperature }) => {
+                simulation.set_probe(probe, temperature)
+            }
+            Some(Action::Snapshot(path)) => simulation.snapshot(&cli.output.join(path))?,
+            Some(Action::SetConfig { key, value }) => simulation
+                .set_config(&key, &value)
+                .with_context(|| format!("line {}", number + 1))?,
+            None => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Scripts of inputs for running the simulator without a window.
+//!
+//! Each line is one of the following, blank lines and anything after a `#` are ignored:
+//!
+//! ```text
+//! press <demand|speed> [long]   press and release a button
+//! wait <seconds>                let time pass
+//! temp <probe> <celsius|off>    set the temperature of a virtual probe, or disconnect it
+//! set <key> <value>             change a configuration value, as with the console
+//! snapshot <file>               save the display as a PNG image
+//! ```
+//!
+//! As with the real buttons, a press straight after the previous release of the same button is
+//! ignored, so leave some time between them.
+
+use anyhow::{anyhow, bail, Context};
+use embassy_time::Duration;
+use ms_air_filter_core::buttons::Button;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Action {
+    Press {
+        button: Button,
+        long: bool,
+    },
+    Wait(Duration),
+    Temperature {
+        probe: usize,
+        temperature: Option<f32>,
+    },
+    Snapshot(PathBuf),
+    SetConfig {
+        key: String,
+        value: String,
+    },
+}
+
+/// Parses a single line of a script, returning `None` for lines with nothing to do.
+pub(crate) fn parse_line(line: &str) -> anyhow::Result<Option<Action>> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut args = line.split_whitespace();
+
+    let Some(command) = args.next() else {
+        return Ok(None);
+    };
+
+    let action = match command {
+        "press" => {
+            let button = match next_arg(&mut args)? {
+                "demand" => Button::Demand,
+                "speed" => Button::Speed,
+                "both" => Button::Both,
+                other => bail!("unknown button \"{other}\""),
+            };
+            let long = match args.next() {
+                None => false,
+                Some("long") => true,
+                Some(other) => bail!("unknown push \"{other}\""),
+            };
+            Action::Press { button, long }
+        }
+        "wait" => {
+            let seconds: f64 = next_arg(&mut args)?
+                .parse()
+                .context("invalid number of seconds")?;
+            if !seconds.is_finite() || seconds < 0.0 {
+                bail!("invalid number of seconds");
+            }
+            Action::Wait(Duration::from_micros((seconds * 1_000_000.0) as u64))
+        }
+        "temp" => {
+            let probe = next_arg(&mut args)?
+                .parse()
+                .context("invalid probe number")?;
+            let temperature = match next_arg(&mut args)? {
+                "off" => None,
+                t => Some(t.parse().context("invalid temperature")?),
+            };
+            Action::Temperature { probe, temperature }
+        }
+        "snapshot" => Action::Snapshot(next_arg(&mut args)?.into()),
+        "set" => Action::SetConfig {
+            key: next_arg(&mut args)?.into(),
+            value: next_arg(&mut args)?.into(),
+        },
+        other => bail!("unknown command \"{other}\""),
+    };
+
+    if args.next().is_some() {
+        bail!("too many arguments");
+    }
+
+    Ok(Some(action))
+}
+
+fn next_arg<'a>(args: &mut impl Iterator<Item = &'a str>) -> anyhow::Result<&'a str> {
+    args.next().ok_or_else(|| anyhow!("missing argument"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            parse_line("press demand").unwrap(),
+            Some(Action::Press {
+                button: Button::Demand,
+                long: false
+            })
+        );
+        assert_eq!(
+            parse_line("press speed long").unwrap(),
+            Some(Action::Press {
+                button: Button::Speed,
+                long: true
+            })
+        );
+        assert_eq!(
+            parse_line("press both").unwrap(),
+            Some(Action::Press {
+                button: Button::Both,
+                long: false
+            })
+        );
+        assert_eq!(
+            parse_line("wait 1.5").unwrap(),
+            Some(Action::Wait(Duration::from_millis(1500)))
+        );
+        assert_eq!(
+            parse_line("temp 2 31.5").unwrap(),
+            Some(Action::Temperature {
+                probe: 2,
+                temperature: Some(31.5)
+            })
+        );
+        assert_eq!(
+            parse_line("temp 0 off").unwrap(),
+            Some(Action::Temperature {
+                probe: 0,
+                temperature: None
+            })
+        );
+        assert_eq!(
+            parse_line("snapshot running.png").unwrap(),
+            Some(Action::Snapshot("running.png".into()))
+        );
+        assert_eq!(
+            parse_line("set interlock_limit_c 60").unwrap(),
+            Some(Action::SetConfig {
+                key: "interlock_limit_c".into(),
+                value: "60".into()
+            })
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert_eq!(parse_line("").unwrap(), None);
+        assert_eq!(parse_line("   # nothing to see here").unwrap(), None);
+        assert_eq!(
+            parse_line("wait 10 # let the timer run").unwrap(),
+            Some(Action::Wait(Duration::from_secs(10)))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_line("jump").is_err());
+        assert!(parse_line("press").is_err());
+        assert!(parse_line("press start").is_err());
+        assert!(parse_line("press demand short").is_err());
+        assert!(parse_line("wait -1").is_err());
+        assert!(parse_line("wait 1 2").is_err());
+        assert!(parse_line("temp x 20").is_err());
+        assert!(parse_line("temp 0 warm").is_err());
+        assert!(parse_line("set run_minutes").is_err());
+    }
+}
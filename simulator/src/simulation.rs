@@ -0,0 +1,396 @@
+use embassy_time::{Duration, Instant};
+use embedded_graphics::Drawable;
+use embedded_graphics_simulator::{OutputSettings, SimulatorDisplay};
+use ms_air_filter_core::{
+    buttons::{Button, Buttons},
+    clock::Clock,
+    config::{Config, ConfigError},
+    fan::{FanCommand, FanController, FanStatus},
+    run_logic::{State, Triggers},
+    runtime::RuntimeMeter,
+    temperature::TemperatureReadings,
+    time::format_minutes_seconds,
+};
+use ms_air_filter_ui::{Action, BootScreen, Color, Diagnostics, Router, DISPLAY_SIZE};
+use std::path::Path;
+
+const VERSION: &str = concat!("simulator ", env!("CARGO_PKG_VERSION"));
+
+/// How long the boot screen is shown for, as in the firmware.
+const BOOT_SCREEN_TIME: Duration = Duration::from_secs(3);
+
+/// How long a button is held for by [`Simulation::press`].
+const SHORT_PUSH_TIME: Duration = Duration::from_millis(200);
+
+/// Simulated time, starting from zero when the simulation starts.
+#[derive(Clone, Copy)]
+struct SimulatedClock {
+    now: Instant,
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+}
+
+/// The run logic and display of the controller, driven by simulated time and inputs.
+pub(crate) struct Simulation {
+    clock: SimulatedClock,
+    config: Config,
+
+    triggers: Triggers,
+    buttons: Buttons,
+    demand_pressed: bool,
+    speed_pressed: bool,
+
+    /// Temperatures of the virtual probes, `None` when a probe is disconnected
+    probes: Vec<Option<f32>>,
+    readings: TemperatureReadings,
+
+    next_tick: Instant,
+    next_temperature_poll: Instant,
+
+    /// What the fan has most recently been told to do
+    fan_command: FanCommand,
+    fan: FanController,
+    /// What the fan is actually doing
+    fan_status: FanStatus,
+    /// The command being carried out, and when the contactors will have finished switching for it
+    switching: Option<(FanCommand, Instant)>,
+    /// When the fan task will stop holding the last speed and look at the newest command
+    hold_until: Option<Instant>,
+    meter: RuntimeMeter,
+
+    /// The latest state that has not yet been drawn
+    pending_state: Option<State>,
+    router: Router,
+    display: SimulatorDisplay<Color>,
+}
+
+impl Simulation {
+    pub(crate) fn new(config: Config) -> Self {
+        let clock = SimulatedClock {
+            now: Instant::from_ticks(0),
+        };
+        let triggers = Triggers::default();
+
+        let mut display = SimulatorDisplay::new(DISPLAY_SIZE);
+        BootScreen { version: VERSION }.draw(&mut display).unwrap();
+
+        Self {
+            clock,
+            buttons: Buttons::new(&clock),
+            demand_pressed: false,
+            speed_pressed: false,
+            next_tick: clock.now + Duration::from_secs(1),
+            next_temperature_poll: clock.now,
+            pending_state: Some(triggers.resolve()),
+            triggers,
+            probes: Vec::new(),
+            readings: TemperatureReadings::default(),
+            fan_command: FanCommand::Stop,
+            fan: FanController::default(),
+            fan_status: FanStatus::Idle,
+            switching: None,
+            hold_until: None,
+            meter: RuntimeMeter::new(),
+            router: Router::default(),
+            display,
+            config,
+        }
+    }
+
+    #[cfg(feature = "window")]
+    pub(crate) fn display(&self) -> &SimulatorDisplay<Color> {
+        &self.display
+    }
+
+    /// Time since the simulation started.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.clock.now - Instant::from_ticks(0)
+    }
+
+    /// Moves simulated time forward, handling everything that would have happened in that time.
+    pub(crate) fn advance(&mut self, duration: Duration) {
+        let end = self.clock.now + duration;
+
+        loop {
+            // Held buttons repeat without their inputs changing
+            let button_deadline = self
+                .buttons
+                .next_deadline(&self.config)
+                .map(|deadline| deadline.max(self.clock.now));
+
+            let next = end
+                .min(self.next_tick)
+                .min(self.next_temperature_poll)
+                .min(button_deadline.unwrap_or(end))
+                .min(self.switching.as_ref().map_or(end, |(_, at)| *at))
+                .min(self.hold_until.unwrap_or(end));
+
+            if let Some(speed) = self.fan_status.running_speed() {
+                self.meter.add(speed, next - self.clock.now);
+            }
+            self.clock.now = next;
+
+            if let Some((command, _)) = self.switching.take_if(|(_, at)| *at == next) {
+                self.fan_status = self.fan.switched(command, Ok(()));
+                println!("[{}] applied: {}", self.timestamp(), self.fan_status);
+                self.hold_until = Some(next + self.config.minimum_speed_hold_time);
+            }
+            if Some(next) == self.hold_until {
+                self.hold_until = None;
+                self.update_fan();
+            }
+
+            let mut changed = false;
+
+            if next == self.next_tick {
+                changed |= self.triggers.button.handle_tick(&self.config);
+                self.next_tick += Duration::from_secs(1);
+            }
+
+            if next == self.next_temperature_poll {
+                self.poll_temperatures();
+                changed |= self.triggers.handle_readings(&self.readings, &self.config);
+                self.next_temperature_poll += self.config.temperature_poll_interval;
+            }
+
+            if changed {
+                self.publish();
+            }
+
+            if Some(next) == button_deadline {
+                self.update_buttons();
+            }
+
+            self.redraw();
+
+            if next == end {
+                break;
+            }
+        }
+    }
+
+    /// Sets the state of a button (or both buttons), as if its input had changed.
+    pub(crate) fn set_button(&mut self, button: Button, pressed: bool) {
+        match button {
+            Button::Demand => self.demand_pressed = pressed,
+            Button::Speed => self.speed_pressed = pressed,
+            Button::Both => {
+                self.demand_pressed = pressed;
+                self.speed_pressed = pressed;
+            }
+        }
+
+        self.update_buttons();
+    }
+
+    fn update_buttons(&mut self) {
+        let events = self.buttons.update(
+            self.demand_pressed,
+            self.speed_pressed,
+            &self.clock,
+            &self.config,
+        );
+
+        for event in events {
+            println!("[{}] button: {:?}", self.timestamp(), event);
+
+            // The buttons operate the UI instead of the fan while another screen is open
+            let captured = self.router.buttons_captured();
+
+            match self.router.handle_button(&event, self.clock.now) {
+                Some(Action::FilterChanged) => {
+                    println!("[{}] filters changed", self.timestamp());
+                    self.meter.filter_changed();
+                }
+                Some(Action::SaveConfig(config)) => {
+                    println!("[{}] settings saved", self.timestamp());
+                    self.config = config;
+                }
+                None => {}
+            }
+
+            if !captured && self.triggers.handle_button(event, &self.config) {
+                self.publish();
+            }
+            self.redraw();
+        }
+    }
+
+    /// Presses and releases a button, holding it for long enough to count as a long push if
+    /// `long` is set.
+    pub(crate) fn press(&mut self, button: Button, long: bool) {
+        let hold_time = if long {
+            self.config.long_push_threshold + SHORT_PUSH_TIME
+        } else {
+            SHORT_PUSH_TIME
+        };
+
+        self.set_button(button.clone(), true);
+        self.advance(hold_time);
+        self.set_button(button, false);
+    }
+
+    /// Sets the temperature of a virtual probe, or disconnects it with `None`.
+    /// This is picked up on the next temperature poll.
+    pub(crate) fn set_probe(&mut self, probe: usize, temperature: Option<f32>) {
+        if probe >= self.probes.len() {
+            self.probes.resize(probe + 1, None);
+        }
+        self.probes[probe] = temperature;
+
+        match temperature {
+            Some(t) => println!("[{}] probe {probe}: {t:.1}C", self.timestamp()),
+            None => println!("[{}] probe {probe}: disconnected", self.timestamp()),
+        }
+    }
+
+    /// Changes a single configuration value, as if it had been set with the console.
+    pub(crate) fn set_config(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let mut config = self.config.clone();
+        config
+            .set_value(key, value)
+            .map_err(|e| anyhow::anyhow!("{key}: {}", e.description()))?;
+        if !config.is_valid() {
+            anyhow::bail!("{key}: {}", ConfigError::Invalid.description());
+        }
+
+        println!("[{}] {key} = {value}", self.timestamp());
+        self.config = config;
+        Ok(())
+    }
+
+    /// Saves the current contents of the display as a PNG image.
+    pub(crate) fn snapshot(&self, path: &Path) -> anyhow::Result<()> {
+        self.display
+            .to_rgb_output_image(&OutputSettings::default())
+            .save_png(path)?;
+        println!("[{}] saved {}", self.timestamp(), path.display());
+        Ok(())
+    }
+
+    fn poll_temperatures(&mut self) {
+        self.readings.mark_all_stale();
+
+        for (probe, temperature) in self.probes.iter().enumerate() {
+            if let Some(temperature) = temperature {
+                self.readings
+                    .record(probe_address(probe), *temperature, self.clock.now);
+            }
+        }
+    }
+
+    fn publish(&mut self) {
+        let state = self.triggers.resolve();
+
+        let fan = match state.fan_command() {
+            FanCommand::Stop => "off",
+            FanCommand::Run(speed) => speed.name(),
+        };
+        let reason = state.reason().map_or("-", |r| r.description());
+        let remaining = match state.time_remaining() {
+            Some(remaining) => format_minutes_seconds(remaining),
+            None if state.continuous() => "continuous".try_into().unwrap(),
+            None => Default::default(),
+        };
+        println!(
+            "[{}] fan: {fan}, reason: {reason}, remaining: {remaining}",
+            self.timestamp()
+        );
+        if let Some(fault) = state.fault() {
+            let peak = fault
+                .peak_temperature
+                .map_or("-".to_string(), |t| format!("{t:.1}C"));
+            println!(
+                "[{}] fault: {fault}, peak {peak}, {}",
+                self.timestamp(),
+                if fault.over_limit {
+                    "over limit"
+                } else if fault.sensor_lost {
+                    "sensor lost"
+                } else {
+                    "can be reset"
+                }
+            );
+        }
+
+        self.fan_command = state.fan_command();
+        self.update_fan();
+        self.pending_state = Some(state);
+    }
+
+    /// Starts carrying out the newest command, unless the fan task is still busy with the last one.
+    fn update_fan(&mut self) {
+        if self.switching.is_some() || self.hold_until.is_some() {
+            return;
+        }
+
+        if let Some(command) = self.fan.pending(&self.fan_command) {
+            let at = self.clock.now + self.switching_time(&command);
+            self.fan_status = FanStatus::Switching(command.clone());
+            self.switching = Some((command, at));
+        }
+    }
+
+    /// Roughly how long the fan task takes to switch the contactors for `command`.
+    fn switching_time(&self, command: &FanCommand) -> Duration {
+        let config = &self.config;
+        let open = config.contactor_switch_delay;
+
+        match command {
+            FanCommand::Stop => open,
+            FanCommand::Run(_) => {
+                // Changing from one speed to another waits for the motor to coast down
+                let dead_time = match self.fan_status {
+                    FanStatus::Idle | FanStatus::Fault(_) => Duration::from_secs(0),
+                    _ => config.contactor_dead_time,
+                };
+                open + dead_time + config.contactor_switch_delay + config.contactor_pull_in_time
+            }
+        }
+    }
+
+    /// Draws the current screen once the boot screen has been shown for long enough.
+    fn redraw(&mut self) {
+        if self.elapsed() < BOOT_SCREEN_TIME {
+            return;
+        }
+
+        let uptime = self.elapsed();
+        let service_interval = self.config.filter_service_interval;
+        let router = &mut self.router;
+
+        if let Some(state) = self.pending_state.take() {
+            router.update_state(state);
+        }
+        router.main.update_fan_status(self.fan_status.clone());
+        router
+            .main
+            .update_filter_status(self.meter.filter_status(service_interval));
+        router.temperatures.update_readings(self.readings.clone());
+        router
+            .statistics
+            .update(self.meter.clone(), service_interval);
+        router.settings.update_config(self.config.clone());
+        router.diagnostics.update_diagnostics(Diagnostics {
+            version: VERSION,
+            uptime,
+            ..Default::default()
+        });
+
+        router.handle_tick(self.clock.now);
+        router.draw(&mut self.display).unwrap();
+    }
+
+    fn timestamp(&self) -> String {
+        format!("{:>8.1}s", self.elapsed().as_millis() as f64 / 1000.0)
+    }
+}
+
+/// A made up DS18B20 address for a virtual probe.
+fn probe_address(probe: usize) -> u64 {
+    ((probe as u64 + 1) << 8) | 0x28
+}
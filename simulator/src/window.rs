@@ -0,0 +1,82 @@
+use crate::simulation::Simulation;
+use embassy_time::Duration;
+use embedded_graphics_simulator::{sdl2::Keycode, OutputSettingsBuilder, SimulatorEvent, Window};
+use ms_air_filter_core::buttons::Button;
+use std::path::Path;
+
+const PROBE_COUNT: usize = 4;
+
+/// The temperature a probe starts at when it is first adjusted.
+const INITIAL_PROBE_TEMPERATURE: f32 = 20.0;
+
+const HELP: &str = "\
+d               demand button
+s               speed button
+1-4             select temperature probe
+up/down         raise/lower the selected probe temperature by 0.5C
+x               disconnect the selected probe
+f               skip forward one minute
+p               save a snapshot
+q/escape        quit";
+
+/// Runs the simulation in real time, showing the display in a window.
+pub(crate) fn run(simulation: &mut Simulation, output: &Path) -> anyhow::Result<()> {
+    println!("{HELP}");
+
+    let mut window = Window::new(
+        "Air Filter Controller",
+        &OutputSettingsBuilder::new().scale(2).build(),
+    );
+
+    let mut probes = [INITIAL_PROBE_TEMPERATURE; PROBE_COUNT];
+    let mut selected_probe = 0;
+    let mut snapshot_count = 0;
+    let mut last_update = std::time::Instant::now();
+
+    loop {
+        window.update(simulation.display());
+
+        for event in window.events() {
+            match event {
+                SimulatorEvent::Quit => return Ok(()),
+                SimulatorEvent::KeyDown {
+                    keycode,
+                    repeat: false,
+                    ..
+                } => match keycode {
+                    Keycode::D => simulation.set_button(Button::Demand, true),
+                    Keycode::S => simulation.set_button(Button::Speed, true),
+                    Keycode::NUM_1 | Keycode::NUM_2 | Keycode::NUM_3 | Keycode::NUM_4 => {
+                        selected_probe = (keycode.into_i32() - Keycode::NUM_1.into_i32()) as usize;
+                        println!("Selected probe {selected_probe}");
+                    }
+                    Keycode::UP | Keycode::DOWN => {
+                        probes[selected_probe] += if keycode == Keycode::UP { 0.5 } else { -0.5 };
+                        simulation.set_probe(selected_probe, Some(probes[selected_probe]));
+                    }
+                    Keycode::X => simulation.set_probe(selected_probe, None),
+                    Keycode::F => simulation.advance(Duration::from_secs(60)),
+                    Keycode::P => {
+                        snapshot_count += 1;
+                        simulation
+                            .snapshot(&output.join(format!("snapshot-{snapshot_count:03}.png")))?;
+                    }
+                    Keycode::Q | Keycode::ESCAPE => return Ok(()),
+                    _ => {}
+                },
+                SimulatorEvent::KeyUp { keycode, .. } => match keycode {
+                    Keycode::D => simulation.set_button(Button::Demand, false),
+                    Keycode::S => simulation.set_button(Button::Speed, false),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let now = std::time::Instant::now();
+        simulation.advance(Duration::from_micros((now - last_update).as_micros() as u64));
+        last_update = now;
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Color, Screen};
+use core::{cell::RefCell, fmt::Write};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, Point, WebColors},
+    text::{Alignment, Text},
+    Drawable,
+};
+use ms_air_filter_core::run_logic::{Fault, FaultCause, InterlockAction};
+use u8g2_fonts::U8g2TextStyle;
+
+/// Fills the display while the overtemperature interlock has tripped (or lost its sensor), saying
+/// what it has done to the fan and how to reset it.
+#[derive(Default)]
+pub(crate) struct AlarmScreen {
+    fault: Option<Fault>,
+
+    redraw: RefCell<bool>,
+}
+
+impl AlarmScreen {
+    pub(crate) fn update_fault(&mut self, fault: Option<Fault>) {
+        if fault != self.fault {
+            *self.redraw.get_mut() = true;
+        }
+
+        self.fault = fault;
+    }
+
+    /// Whether there is a fault to show.
+    pub(crate) fn active(&self) -> bool {
+        self.fault.is_some()
+    }
+}
+
+impl Screen for AlarmScreen {
+    fn invalidate(&mut self) {
+        *self.redraw.get_mut() = true;
+    }
+}
+
+impl Drawable for AlarmScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Ok(mut redraw) = self.redraw.try_borrow_mut() else {
+            return Ok(());
+        };
+        let Some(fault) = &self.fault else {
+            return Ok(());
+        };
+        if !*redraw {
+            return Ok(());
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Redrawing alarm screen");
+
+        target.clear(Color::CSS_DARK_RED)?;
+
+        let x = target.bounding_box().center().x;
+        let style = MonoTextStyle::new(&FONT_10X20, Color::CSS_WHITE);
+
+        Text::with_alignment(
+            match fault.cause {
+                FaultCause::Overtemperature => "HOT",
+                FaultCause::SensorLost => "LOST",
+            },
+            Point::new(x, 70),
+            U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_inb53_mr, Color::CSS_WHITE),
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        let action = match fault.action {
+            InterlockAction::Stop => "Fan stopped",
+            InterlockAction::Boost => "Fan forced to high",
+        };
+        Text::with_alignment(action, Point::new(x, 110), style, Alignment::Center).draw(target)?;
+
+        let mut sensor = heapless::String::<16>::new();
+        let _ = sensor.write_fmt(format_args!("{:016x}", fault.sensor));
+        Text::with_alignment(&sensor, Point::new(x, 140), style, Alignment::Center).draw(target)?;
+
+        let mut peak = heapless::String::<16>::new();
+        let _ = match fault.peak_temperature {
+            Some(t) => peak.write_fmt(format_args!("Peak {t:.1}C")),
+            None => peak.write_str("Not responding"),
+        };
+        Text::with_alignment(&peak, Point::new(x, 162), style, Alignment::Center).draw(target)?;
+
+        // Can only be reset once it has cooled down and can be read
+        let (hint, color) = if fault.over_limit {
+            ("Wait for it\nto cool down", Color::CSS_LIGHT_GRAY)
+        } else if fault.sensor_lost {
+            ("Check the sensor", Color::CSS_LIGHT_GRAY)
+        } else {
+            ("Hold both buttons\nto reset", Color::CSS_YELLOW)
+        };
+        Text::with_alignment(
+            hint,
+            Point::new(x, 200),
+            MonoTextStyle::new(&FONT_10X20, color),
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        *redraw = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+
+    fn fault(over_limit: bool) -> Fault {
+        Fault {
+            action: InterlockAction::Stop,
+            sensor: 0x28ff_0000_0000_0001,
+            cause: FaultCause::Overtemperature,
+            peak_temperature: Some(72.5),
+            over_limit,
+            sensor_lost: false,
+        }
+    }
+
+    #[test]
+    fn over_limit() {
+        let mut frame = Framebuffer::default();
+        let mut screen = AlarmScreen::default();
+        screen.update_fault(Some(fault(true)));
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("alarm_screen", &frame);
+    }
+
+    #[test]
+    fn can_be_reset() {
+        let mut frame = Framebuffer::default();
+        let mut screen = AlarmScreen::default();
+        screen.update_fault(Some(fault(false)));
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("alarm_screen_reset", &frame);
+    }
+
+    #[test]
+    fn sensor_lost() {
+        let mut frame = Framebuffer::default();
+        let mut screen = AlarmScreen::default();
+        screen.update_fault(Some(Fault {
+            action: InterlockAction::Boost,
+            sensor: 0x28ff_0000_0000_0001,
+            cause: FaultCause::SensorLost,
+            peak_temperature: None,
+            over_limit: false,
+            sensor_lost: true,
+        }));
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("alarm_screen_sensor_lost", &frame);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::display::Color;
+use crate::Color;
 use embedded_graphics::{
     mono_font::{ascii::FONT_10X20, MonoTextStyle},
     prelude::{DrawTarget, Point, Primitive, WebColors},
@@ -7,9 +7,13 @@ use embedded_graphics::{
     Drawable,
 };
 
-pub(crate) struct BootScreen {}
+/// The splash screen shown while starting up.
+pub struct BootScreen<'a> {
+    /// The firmware version
+    pub version: &'a str,
+}
 
-impl Drawable for BootScreen {
+impl Drawable for BootScreen<'_> {
     type Output = ();
     type Color = Color;
 
@@ -54,7 +58,7 @@ impl Drawable for BootScreen {
 
         // Show the firmware version
         Text::with_alignment(
-            env!("VERSION"),
+            self.version,
             display_box.center() + Point::new(0, 75),
             text_style,
             Alignment::Center,
@@ -64,3 +68,20 @@ impl Drawable for BootScreen {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+
+    #[test]
+    fn boot_screen() {
+        let mut frame = Framebuffer::default();
+        BootScreen {
+            version: "v1.2.3-4-gabcdef0",
+        }
+        .draw(&mut frame)
+        .unwrap();
+        assert_matches_golden("boot_screen", &frame);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{layout, Color, Screen};
+use core::{cell::RefCell, fmt::Write};
+use embassy_time::Duration;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, Point, WebColors},
+    text::{Alignment, Text},
+    Drawable,
+};
+
+/// Information about the health of the controller.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Diagnostics {
+    /// The firmware version
+    pub version: &'static str,
+    /// Time since the controller started
+    pub uptime: Duration,
+    /// The number of times the display has been reinitialised after it stopped working
+    pub display_recoveries: u32,
+    /// The total number of failed reads from the temperature sensors
+    pub sensor_read_errors: u32,
+}
+
+/// Shows information that helps find faults with the controller.
+#[derive(Default)]
+pub struct DiagnosticsScreen {
+    diagnostics: Diagnostics,
+
+    redraw: RefCell<bool>,
+}
+
+impl DiagnosticsScreen {
+    pub fn update_diagnostics(&mut self, mut diagnostics: Diagnostics) {
+        // Uptime is only shown to the minute
+        diagnostics.uptime = Duration::from_secs(diagnostics.uptime.as_secs() / 60 * 60);
+
+        if diagnostics != self.diagnostics {
+            *self.redraw.get_mut() = true;
+        }
+
+        self.diagnostics = diagnostics;
+    }
+}
+
+impl Screen for DiagnosticsScreen {
+    fn invalidate(&mut self) {
+        *self.redraw.get_mut() = true;
+    }
+}
+
+impl Drawable for DiagnosticsScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Ok(mut redraw) = self.redraw.try_borrow_mut() else {
+            return Ok(());
+        };
+        if !*redraw {
+            return Ok(());
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Redrawing diagnostics screen");
+
+        layout::title(target, "Diagnostics")?;
+
+        // The version can be too long to fit alongside a label
+        Text::with_alignment(
+            self.diagnostics.version,
+            Point::new(target.bounding_box().center().x, layout::FIRST_ROW),
+            MonoTextStyle::new(&FONT_10X20, Color::CSS_WHITE),
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        let minutes = self.diagnostics.uptime.as_secs() / 60;
+        let mut uptime = heapless::String::<16>::new();
+        uptime
+            .write_fmt(format_args!(
+                "{}d {:02}:{:02}",
+                minutes / (24 * 60),
+                minutes / 60 % 24,
+                minutes % 60
+            ))
+            .unwrap();
+
+        let mut display_recoveries = heapless::String::<16>::new();
+        display_recoveries
+            .write_fmt(format_args!("{}", self.diagnostics.display_recoveries))
+            .unwrap();
+
+        let mut sensor_read_errors = heapless::String::<16>::new();
+        sensor_read_errors
+            .write_fmt(format_args!("{}", self.diagnostics.sensor_read_errors))
+            .unwrap();
+
+        for (i, (label, value)) in [
+            ("Uptime", uptime),
+            ("Display resets", display_recoveries),
+            ("Sensor errors", sensor_read_errors),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let y = layout::FIRST_ROW + layout::ROW_HEIGHT * (i as i32 + 2);
+            layout::row(target, y, label, value, Color::CSS_WHITE)?;
+        }
+
+        layout::navigation_hint(target, None)?;
+
+        *redraw = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+
+    #[test]
+    fn diagnostics() {
+        let mut frame = Framebuffer::default();
+        let mut screen = DiagnosticsScreen::default();
+        screen.update_diagnostics(Diagnostics {
+            version: "v1.2.3-4-gabcdef0",
+            uptime: Duration::from_secs(((3 * 24 + 4) * 60 + 5) * 60 + 6),
+            display_recoveries: 2,
+            sensor_read_errors: 17,
+        });
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("diagnostics_screen", &frame);
+    }
+
+    #[test]
+    fn uptime_redraws_each_minute() {
+        let mut screen = DiagnosticsScreen::default();
+        let mut diagnostics = Diagnostics::default();
+
+        for (secs, redraw) in [(59, false), (60, true), (119, false), (120, true)] {
+            diagnostics.uptime = Duration::from_secs(secs);
+            screen.update_diagnostics(diagnostics.clone());
+            assert_eq!(*screen.redraw.borrow(), redraw, "after {secs}s");
+            screen.draw(&mut Framebuffer::default()).unwrap();
+        }
+    }
+}
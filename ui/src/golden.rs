@@ -0,0 +1,158 @@
+//! Comparison of rendered screens against reference images.
+//!
+//! Reference images are kept in `golden/`. When a rendering does not match its reference image,
+//! the rendering and a diff (with differing pixels in red) are written to `target/golden/` and the
+//! test fails.
+//!
+//! Set `UPDATE_GOLDEN=1` when running the tests to create or update the reference images from the
+//! current renderings, then review the changes before committing them.
+
+use crate::{Color, DISPLAY_SIZE};
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, OriginDimensions, Pixel, RgbColor, Size},
+};
+use std::{fs::File, io::BufWriter, path::Path};
+
+/// An in-memory framebuffer the size of the display.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Framebuffer {
+    pixels: Vec<Color>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            pixels: vec![Color::BLACK; (DISPLAY_SIZE.width * DISPLAY_SIZE.height) as usize],
+        }
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        DISPLAY_SIZE
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Color;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if (0..DISPLAY_SIZE.width as i32).contains(&point.x)
+                && (0..DISPLAY_SIZE.height as i32).contains(&point.y)
+            {
+                self.pixels[(point.y as u32 * DISPLAY_SIZE.width + point.x as u32) as usize] =
+                    color;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Framebuffer {
+    /// The contents as 8 bit RGB, as stored in the reference images.
+    fn to_rgb888(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|c| {
+                let c: Rgb888 = (*c).into();
+                [c.r(), c.g(), c.b()]
+            })
+            .collect()
+    }
+}
+
+/// Fails the test if `frame` does not match the reference image called `name`.
+pub(crate) fn assert_matches_golden(name: &str, frame: &Framebuffer) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = manifest_dir.join("golden").join(format!("{name}.png"));
+
+    let actual = frame.to_rgb888();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        write_png(&reference_path, &actual);
+        return;
+    }
+
+    let Some(expected) = read_png(&reference_path) else {
+        panic!(
+            "there is no reference image at {}, run the tests with UPDATE_GOLDEN=1 to create it",
+            reference_path.display()
+        );
+    };
+
+    if expected == actual {
+        return;
+    }
+
+    let output_dir = manifest_dir.join("target").join("golden");
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let actual_path = output_dir.join(format!("{name}.png"));
+    write_png(&actual_path, &actual);
+
+    let mut differing = 0;
+    let diff: Vec<u8> = expected
+        .chunks_exact(3)
+        .zip(actual.chunks_exact(3))
+        .flat_map(|(e, a)| {
+            if e == a {
+                // Faded version of the expected image for context
+                let luma = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 3 / 4) as u8;
+                [luma, luma, luma]
+            } else {
+                differing += 1;
+                [255, 0, 0]
+            }
+        })
+        .collect();
+    let diff_path = output_dir.join(format!("{name}-diff.png"));
+    write_png(&diff_path, &diff);
+
+    panic!(
+        "{name} differs from {} in {differing} pixels, see {} and {}",
+        reference_path.display(),
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+fn read_png(path: &Path) -> Option<Vec<u8>> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().unwrap();
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!(
+        (info.width, info.height, info.color_type, info.bit_depth),
+        (
+            DISPLAY_SIZE.width,
+            DISPLAY_SIZE.height,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight
+        ),
+        "{} is not an 8 bit RGB image the size of the display",
+        path.display()
+    );
+    buf.truncate(info.buffer_size());
+
+    Some(buf)
+}
+
+fn write_png(path: &Path, rgb: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+
+    let mut encoder = png::Encoder::new(file, DISPLAY_SIZE.width, DISPLAY_SIZE.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(rgb).unwrap();
+}
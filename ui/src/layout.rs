@@ -0,0 +1,107 @@
+//! Drawing shared by the screens that present a list of values.
+
+use crate::Color;
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    prelude::{DrawTarget, Point, WebColors},
+    text::{Alignment, Text},
+    Drawable,
+};
+
+/// Space between the edges of the display and any text.
+const MARGIN: i32 = 10;
+
+/// The baseline of the first row below the title.
+pub(crate) const FIRST_ROW: i32 = 56;
+
+/// Distance between the baselines of consecutive rows.
+pub(crate) const ROW_HEIGHT: i32 = 22;
+
+/// Clears the display and draws a title across the top of it.
+pub(crate) fn title<D>(target: &mut D, title: &str) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Color>,
+{
+    target.clear(Color::CSS_BLACK)?;
+
+    Text::with_alignment(
+        title,
+        Point::new(target.bounding_box().center().x, 24),
+        MonoTextStyle::new(&FONT_10X20, Color::CSS_WHITE),
+        Alignment::Center,
+    )
+    .draw(target)?;
+
+    Ok(())
+}
+
+/// Draws a label on the left and its value on the right, with the given baseline.
+pub(crate) fn row<D>(
+    target: &mut D,
+    y: i32,
+    label: &str,
+    value: &str,
+    value_color: Color,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Color>,
+{
+    let display_box = target.bounding_box();
+    let left = display_box.top_left.x + MARGIN;
+    let right = display_box.top_left.x + display_box.size.width as i32 - MARGIN;
+
+    Text::with_alignment(
+        label,
+        Point::new(left, y),
+        MonoTextStyle::new(&FONT_10X20, Color::CSS_GRAY),
+        Alignment::Left,
+    )
+    .draw(target)?;
+
+    Text::with_alignment(
+        value,
+        Point::new(right, y),
+        MonoTextStyle::new(&FONT_10X20, value_color),
+        Alignment::Right,
+    )
+    .draw(target)?;
+
+    Ok(())
+}
+
+/// Draws how to move between screens along the bottom of the display, below a description of
+/// what else the buttons do on this screen (if anything).
+pub(crate) fn navigation_hint<D>(target: &mut D, actions: Option<&str>) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Color>,
+{
+    let display_box = target.bounding_box();
+    let style = MonoTextStyle::new(&FONT_6X10, Color::CSS_GRAY);
+    let bottom = Point::new(
+        display_box.center().x,
+        display_box.top_left.y + display_box.size.height as i32 - 6,
+    );
+
+    if let Some(actions) = actions {
+        Text::with_alignment(
+            actions,
+            bottom - Point::new(0, 10),
+            style,
+            Alignment::Center,
+        )
+        .draw(target)?;
+    }
+
+    Text::with_alignment(
+        "Hold Speed for the next screen",
+        bottom,
+        style,
+        Alignment::Center,
+    )
+    .draw(target)?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! The screens shown on the display.
+//!
+//! These only depend on `embedded-graphics` so that they can be drawn to the real display by the
+//! firmware, or to a framebuffer on the host by the simulator.
+
+#![cfg_attr(not(test), no_std)]
+
+mod alarm_screen;
+mod boot_screen;
+mod diagnostics_screen;
+#[cfg(test)]
+mod golden;
+mod layout;
+mod main_screen;
+mod router;
+mod service_screen;
+mod settings_screen;
+mod temperatures_screen;
+
+pub use boot_screen::BootScreen;
+pub use diagnostics_screen::{Diagnostics, DiagnosticsScreen};
+pub use main_screen::MainScreen;
+pub use router::{Action, Router, Screen, ScreenId, INACTIVITY_TIMEOUT};
+pub use service_screen::ServiceScreen;
+pub use settings_screen::SettingsScreen;
+pub use temperatures_screen::TemperaturesScreen;
+
+/// The colour format of the display.
+pub type Color = embedded_graphics::pixelcolor::Rgb565;
+
+/// The size of the display in pixels.
+pub const DISPLAY_SIZE: embedded_graphics::geometry::Size =
+    embedded_graphics::geometry::Size::new(240, 240);
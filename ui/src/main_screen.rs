@@ -0,0 +1,604 @@
+use crate::{Color, Screen};
+use core::cell::RefCell;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, Point, Primitive, Size, WebColors},
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use ms_air_filter_core::{
+    display::MainScreenRedraw,
+    fan::{FanSpeed, FanStatus},
+    run_logic::{Reason, RunTimeChange, State},
+    runtime::FilterStatus,
+    time::format_minutes_seconds,
+};
+use u8g2_fonts::U8g2TextStyle;
+
+/// The height of the warning banner at the top of the screen.
+const BANNER_HEIGHT: u32 = 24;
+
+/// Shows what the fan is doing and for how long, with a banner across the top when the filters
+/// need changing or the clock needs setting.
+///
+/// The fan speed shown is what the contactors have actually been switched to (see
+/// [`MainScreen::update_fan_status`]), rather than what the fan has been told to do.
+///
+/// Only the areas of the screen that have changed since the last draw are redrawn.
+#[derive(Default)]
+pub struct MainScreen {
+    state: Option<State>,
+    fan_status: FanStatus,
+    filter_status: FilterStatus,
+
+    redraw_cmd: RefCell<bool>,
+    redraw_time: RefCell<bool>,
+    redraw_banner: RefCell<bool>,
+}
+
+impl MainScreen {
+    pub fn update_state(&mut self, state: State) {
+        let redraw = MainScreenRedraw::between(self.state.as_ref(), &state);
+        if redraw.command {
+            *self.redraw_cmd.borrow_mut() = true;
+        }
+        if redraw.time {
+            *self.redraw_time.borrow_mut() = true;
+        }
+        if redraw.banner {
+            *self.redraw_banner.borrow_mut() = true;
+        }
+
+        self.state = Some(state);
+    }
+
+    pub fn update_fan_status(&mut self, status: FanStatus) {
+        if status != self.fan_status && self.state.is_some() {
+            *self.redraw_cmd.get_mut() = true;
+        }
+
+        self.fan_status = status;
+    }
+
+    pub fn update_filter_status(&mut self, status: FilterStatus) {
+        if status != self.filter_status {
+            *self.redraw_banner.get_mut() = true;
+        }
+
+        self.filter_status = status;
+    }
+}
+
+impl Screen for MainScreen {
+    fn invalidate(&mut self) {
+        if self.state.is_some() {
+            *self.redraw_cmd.get_mut() = true;
+            *self.redraw_time.get_mut() = true;
+        }
+        *self.redraw_banner.get_mut() = true;
+    }
+}
+
+impl Drawable for MainScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let display_box = target.bounding_box();
+
+        let half_height = display_box.size.height / 2;
+        let box_size = Size::new(display_box.size.width, half_height);
+
+        let top = Rectangle::new(display_box.top_left, box_size);
+        let bottom = Rectangle::new(
+            display_box.top_left + Point::new(0, half_height as i32),
+            box_size,
+        );
+
+        let box_style = PrimitiveStyleBuilder::new()
+            .fill_color(Color::CSS_BLACK)
+            .build();
+
+        if let Ok(mut redraw) = self.redraw_cmd.try_borrow_mut() {
+            if *redraw {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Redrawing fan command");
+
+                let state = self
+                    .state
+                    .as_ref()
+                    .expect("should have a state if the redraw flag was set");
+
+                top.into_styled(box_style).draw(target)?;
+
+                // Display what the fan is actually doing, while switching this is the speed
+                // being switched to
+                let (speed, speed_color) = match &self.fan_status {
+                    FanStatus::Idle => (None, Color::CSS_GRAY),
+                    FanStatus::Switching(command) => (
+                        FanStatus::applied(command).running_speed().cloned(),
+                        Color::CSS_ORANGE,
+                    ),
+                    FanStatus::Running(speed) => (Some(speed.clone()), Color::CSS_WHITE),
+                    FanStatus::Fault(_) => (None, Color::CSS_RED),
+                };
+                Text::with_alignment(
+                    match speed {
+                        None => "Off",
+                        Some(FanSpeed::Low) => "Low",
+                        Some(FanSpeed::Medium) => "Mid",
+                        Some(FanSpeed::High) => "High",
+                    },
+                    top.center() + Point::new(0, 53 / 2),
+                    U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_inb53_mr, speed_color),
+                    Alignment::Center,
+                )
+                .draw(target)?;
+
+                // Display why the fan is doing what it is doing, unless it isn't doing it yet
+                let description = match &self.fan_status {
+                    FanStatus::Switching(_) => Some("Switching..."),
+                    FanStatus::Fault(_) => Some("Contactor fault"),
+                    _ => state.reason().map(|reason| reason.description()),
+                };
+                if let Some(description) = description {
+                    Text::with_alignment(
+                        description,
+                        top.center() + Point::new(0, 50),
+                        MonoTextStyle::new(&FONT_10X20, Color::CSS_GRAY),
+                        Alignment::Center,
+                    )
+                    .draw(target)?;
+                }
+
+                // Clearing the top half also cleared the banner
+                *self.redraw_banner.borrow_mut() = true;
+
+                *redraw = false;
+            }
+        }
+
+        if let Ok(mut redraw) = self.redraw_time.try_borrow_mut() {
+            if *redraw {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Redrawing time");
+
+                let state = self
+                    .state
+                    .as_ref()
+                    .expect("should have a state if the redraw flag was set");
+                let time_remaining = state.time_remaining();
+
+                let time_str = match time_remaining {
+                    Some(time_remaining) => format_minutes_seconds(time_remaining),
+                    None => "--:--".try_into().unwrap(),
+                };
+
+                bottom.into_styled(box_style).draw(target)?;
+
+                // Display the remaining run time, 100 minutes or more needs a smaller font to
+                // fit across the screen. The purge after a run is counted down in a different
+                // colour so it isn't mistaken for the run itself.
+                let time_color = match (time_remaining, state.reason()) {
+                    (Some(_), Some(Reason::Purge)) => Color::CSS_DEEP_SKY_BLUE,
+                    (Some(_), _) => Color::CSS_WHITE,
+                    (None, _) if state.continuous() => Color::CSS_WHITE,
+                    (None, _) => Color::CSS_GRAY,
+                };
+                let time_position = bottom.center() + Point::new(0, 78 / 2);
+
+                if state.continuous() {
+                    // There is no time to show, and the time fonts only have digits
+                    Text::with_alignment(
+                        "Cont.",
+                        bottom.center() + Point::new(0, 53 / 2),
+                        U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_inb53_mr, time_color),
+                        Alignment::Center,
+                    )
+                    .draw(target)?;
+                } else if time_str.len() > 5 {
+                    Text::with_alignment(
+                        &time_str,
+                        time_position,
+                        U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_logisoso62_tn, time_color),
+                        Alignment::Center,
+                    )
+                    .draw(target)?;
+                } else {
+                    Text::with_alignment(
+                        &time_str,
+                        time_position,
+                        U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_logisoso78_tn, time_color),
+                        Alignment::Center,
+                    )
+                    .draw(target)?;
+                }
+
+                // Display the change just made to the run time above it
+                if let Some(change) = state.run_time_change() {
+                    let (sign, amount, color) = match change {
+                        RunTimeChange::Added(amount) => ('+', amount, Color::CSS_LIME_GREEN),
+                        RunTimeChange::Subtracted(amount) => ('-', amount, Color::CSS_ORANGE),
+                    };
+
+                    let mut change_str = heapless::String::<16>::new();
+                    change_str.push(sign).unwrap();
+                    change_str
+                        .push_str(&format_minutes_seconds(amount))
+                        .unwrap();
+
+                    Text::with_alignment(
+                        &change_str,
+                        Point::new(bottom.center().x, bottom.top_left.y + 18),
+                        MonoTextStyle::new(&FONT_10X20, color),
+                        Alignment::Center,
+                    )
+                    .draw(target)?;
+                }
+
+                *redraw = false;
+            }
+        }
+
+        if let Ok(mut redraw) = self.redraw_banner.try_borrow_mut() {
+            if *redraw {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Redrawing banner");
+
+                let banner = Rectangle::new(
+                    display_box.top_left,
+                    Size::new(display_box.size.width, BANNER_HEIGHT),
+                );
+
+                // The filters are more pressing, the clock only matters to the schedule
+                let clock_not_set = self.state.as_ref().is_some_and(State::clock_not_set);
+                let (text, color) = match self.filter_status {
+                    FilterStatus::Ok if clock_not_set => ("Clock not set", Color::CSS_YELLOW),
+                    FilterStatus::Ok => ("", Color::CSS_BLACK),
+                    FilterStatus::DueSoon => ("Change filters soon", Color::CSS_ORANGE),
+                    FilterStatus::Overdue => ("Change filters now", Color::CSS_RED),
+                };
+
+                banner
+                    .into_styled(PrimitiveStyleBuilder::new().fill_color(color).build())
+                    .draw(target)?;
+
+                // Display the filter warning
+                Text::with_text_style(
+                    text,
+                    banner.center(),
+                    MonoTextStyle::new(&FONT_10X20, Color::CSS_BLACK),
+                    TextStyleBuilder::new()
+                        .alignment(Alignment::Center)
+                        .baseline(Baseline::Middle)
+                        .build(),
+                )
+                .draw(target)?;
+
+                *redraw = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+    use embassy_time::{Duration, Instant};
+    use ms_air_filter_core::{
+        buttons::{Button, ButtonEvent, ButtonPushDuration},
+        config::Config,
+        contactors::ContactorFault,
+        run_logic::{ControlCommand, Triggers},
+        temperature::TemperatureReadings,
+    };
+
+    fn stopped() -> State {
+        Triggers::default().resolve()
+    }
+
+    fn running(speed: FanSpeed, time_remaining: Duration) -> State {
+        let mut triggers = Triggers::default();
+        triggers.button.handle_command(
+            ControlCommand::Start {
+                duration: Some(time_remaining),
+                speed: Some(speed),
+            },
+            &Config::DEFAULT,
+        );
+        triggers.resolve()
+    }
+
+    /// Draws each state in turn, as the display task does.
+    fn render(states: &[State]) -> Framebuffer {
+        render_with_filter(states, FilterStatus::Ok)
+    }
+
+    fn render_with_filter(states: &[State], filter_status: FilterStatus) -> Framebuffer {
+        let mut frame = Framebuffer::default();
+        let mut screen = MainScreen::default();
+        screen.update_filter_status(filter_status);
+
+        for state in states {
+            // As if the contactors had been switched straight away
+            screen.update_fan_status(FanStatus::applied(&state.fan_command()));
+            screen.update_state(state.clone());
+            screen.draw(&mut frame).unwrap();
+        }
+
+        frame
+    }
+
+    #[test]
+    fn fan_stopped() {
+        assert_matches_golden("main_screen_stopped", &render(&[stopped()]));
+    }
+
+    #[test]
+    fn fan_speeds() {
+        for speed in [FanSpeed::Low, FanSpeed::Medium, FanSpeed::High] {
+            let name = format!("main_screen_{}", speed.name());
+            let state = running(speed, Duration::from_secs(20 * 60));
+            assert_matches_golden(&name, &render(&[state]));
+        }
+    }
+
+    #[test]
+    fn time_remaining() {
+        for seconds in [1, 59, 5 * 60 + 30, 20 * 60, 99 * 60 + 59, 240 * 60] {
+            let name = format!("main_screen_time_{seconds}s");
+            let state = running(FanSpeed::Low, Duration::from_secs(seconds));
+            assert_matches_golden(&name, &render(&[state]));
+        }
+    }
+
+    #[test]
+    fn run_time_changes() {
+        let mut config = Config::DEFAULT;
+        config.run_increment = Duration::from_secs(10 * 60);
+
+        let mut triggers = Triggers::default();
+        for clicks in [1, 1] {
+            triggers.button.handle_button(
+                ButtonEvent {
+                    button: Button::Demand,
+                    push_duration: ButtonPushDuration::Short,
+                    clicks,
+                },
+                &config,
+            );
+        }
+        let added = triggers.resolve();
+        assert_matches_golden("main_screen_time_added", &render(&[added.clone()]));
+
+        triggers.button.handle_button(
+            ButtonEvent {
+                button: Button::Demand,
+                push_duration: ButtonPushDuration::Short,
+                clicks: 2,
+            },
+            &config,
+        );
+        let subtracted = triggers.resolve();
+        assert_matches_golden(
+            "main_screen_time_subtracted",
+            &render(&[added, subtracted.clone()]),
+        );
+
+        // The change is only shown for a few seconds
+        for _ in 0..3 {
+            triggers.button.handle_tick(&config);
+        }
+        assert_eq!(
+            render(&[subtracted, triggers.resolve()]),
+            render(&[triggers.resolve()])
+        );
+    }
+
+    #[test]
+    fn started_by_button() {
+        let mut triggers = Triggers::default();
+        triggers.button.handle_button(
+            ButtonEvent {
+                button: Button::Demand,
+                push_duration: ButtonPushDuration::Short,
+                clicks: 1,
+            },
+            &Config::DEFAULT,
+        );
+        assert_matches_golden("main_screen_button", &render(&[triggers.resolve()]));
+    }
+
+    #[test]
+    fn purging() {
+        let mut config = Config::DEFAULT;
+        config.purge_duration = Duration::from_secs(2 * 60);
+
+        let mut triggers = Triggers::default();
+        triggers.button.handle_command(
+            ControlCommand::Start {
+                duration: None,
+                speed: Some(FanSpeed::High),
+            },
+            &config,
+        );
+        let running = triggers.resolve();
+        triggers
+            .button
+            .handle_command(ControlCommand::Stop, &config);
+        assert_matches_golden("main_screen_purge", &render(&[running, triggers.resolve()]));
+    }
+
+    #[test]
+    fn continuous() {
+        let mut triggers = Triggers::default();
+        triggers.button.handle_button(
+            ButtonEvent {
+                button: Button::Both,
+                push_duration: ButtonPushDuration::Short,
+                clicks: 1,
+            },
+            &Config::DEFAULT,
+        );
+        assert_matches_golden("main_screen_continuous", &render(&[triggers.resolve()]));
+    }
+
+    #[test]
+    fn started_by_temperature() {
+        let mut readings = TemperatureReadings::default();
+        readings.record(0x28, 35.0, Instant::from_ticks(0));
+
+        let mut triggers = Triggers::default();
+        triggers
+            .temperature
+            .handle_readings(&readings, &Config::DEFAULT);
+        assert_matches_golden("main_screen_temperature", &render(&[triggers.resolve()]));
+    }
+
+    #[test]
+    fn filter_warnings() {
+        let states = [
+            stopped(),
+            running(FanSpeed::High, Duration::from_secs(20 * 60)),
+        ];
+        for (status, name) in [
+            (FilterStatus::DueSoon, "main_screen_filter_due"),
+            (FilterStatus::Overdue, "main_screen_filter_overdue"),
+        ] {
+            for (state, state_name) in states.iter().zip(["stopped", "running"]) {
+                let name = format!("{name}_{state_name}");
+                let frame = render_with_filter(&[state.clone()], status);
+                assert_matches_golden(&name, &frame);
+            }
+        }
+    }
+
+    #[test]
+    fn filter_warning_comes_and_goes() {
+        let state = running(FanSpeed::Low, Duration::from_secs(60));
+
+        let mut frame = Framebuffer::default();
+        let mut screen = MainScreen::default();
+        screen.update_fan_status(FanStatus::applied(&state.fan_command()));
+        screen.update_state(state.clone());
+        screen.draw(&mut frame).unwrap();
+
+        for status in [
+            FilterStatus::DueSoon,
+            FilterStatus::Overdue,
+            FilterStatus::Ok,
+            FilterStatus::Overdue,
+        ] {
+            screen.update_filter_status(status);
+            screen.draw(&mut frame).unwrap();
+            assert_eq!(frame, render_with_filter(&[state.clone()], status));
+        }
+    }
+
+    #[test]
+    fn clock_not_set() {
+        let mut schedule = ms_air_filter_core::schedule::Schedule::default();
+        schedule
+            .add("daily 08:00-09:00 low".parse().unwrap())
+            .unwrap();
+        let mut triggers = Triggers::default();
+        let stopped = triggers.resolve();
+        triggers.schedule.handle_time(None, &schedule);
+        let clock_not_set = triggers.resolve();
+
+        assert_matches_golden(
+            "main_screen_clock_not_set",
+            &render(&[stopped.clone(), clock_not_set.clone()]),
+        );
+
+        // The filters take over the banner
+        let frame = render_with_filter(&[clock_not_set], FilterStatus::Overdue);
+        assert_matches_golden("main_screen_filter_overdue_stopped", &frame);
+
+        // And it goes once the clock has been set
+        assert_eq!(
+            render(&[triggers.resolve(), stopped.clone()]),
+            render(&[stopped])
+        );
+    }
+
+    #[test]
+    fn invalidate_redraws_everything() {
+        let state = running(FanSpeed::Medium, Duration::from_secs(90));
+
+        let mut frame = Framebuffer::default();
+        let mut screen = MainScreen::default();
+        screen.update_fan_status(FanStatus::applied(&state.fan_command()));
+        screen.update_state(state.clone());
+        screen.draw(&mut frame).unwrap();
+
+        // As if the display had been reset
+        frame.clear(Color::CSS_HOT_PINK).unwrap();
+        screen.invalidate();
+        screen.draw(&mut frame).unwrap();
+
+        assert_eq!(frame, render(&[state]));
+    }
+
+    /// Only the areas that have changed are redrawn, which must leave the screen looking the same
+    /// as if it had been drawn from scratch.
+    #[test]
+    fn partial_redraw_matches_full_redraw() {
+        let states = [
+            stopped(),
+            running(FanSpeed::Low, Duration::from_secs(20 * 60)),
+            running(FanSpeed::Low, Duration::from_secs(20 * 60 - 1)),
+            running(FanSpeed::High, Duration::from_secs(20 * 60 - 1)),
+            running(FanSpeed::High, Duration::from_secs(9)),
+            stopped(),
+            running(FanSpeed::Medium, Duration::from_secs(5 * 60)),
+        ];
+
+        for i in 1..states.len() {
+            assert_eq!(
+                render(&states[..=i]),
+                render(&states[i..=i]),
+                "redrawing after state {i} does not match drawing it from scratch"
+            );
+        }
+    }
+
+    #[test]
+    fn switching() {
+        let state = running(FanSpeed::High, Duration::from_secs(20 * 60));
+
+        let mut frame = Framebuffer::default();
+        let mut screen = MainScreen::default();
+        screen.update_state(stopped());
+        screen.draw(&mut frame).unwrap();
+
+        // The fan has been told to run, but the contactors have not been switched yet
+        screen.update_state(state.clone());
+        screen.update_fan_status(FanStatus::Switching(state.fan_command()));
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("main_screen_switching", &frame);
+
+        screen.update_fan_status(FanStatus::applied(&state.fan_command()));
+        screen.draw(&mut frame).unwrap();
+        assert_eq!(frame, render(&[state]));
+    }
+
+    #[test]
+    fn contactor_fault() {
+        let mut frame = Framebuffer::default();
+        let mut screen = MainScreen::default();
+        screen.update_state(running(FanSpeed::Medium, Duration::from_secs(20 * 60)));
+        screen.update_fan_status(FanStatus::Fault(ContactorFault::DidNotClose(
+            FanSpeed::Medium,
+        )));
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("main_screen_contactor_fault", &frame);
+    }
+}
@@ -0,0 +1,372 @@
+use crate::{
+    alarm_screen::AlarmScreen, Color, DiagnosticsScreen, MainScreen, ServiceScreen, SettingsScreen,
+    TemperaturesScreen,
+};
+use embassy_time::{Duration, Instant};
+use embedded_graphics::{prelude::DrawTarget, Drawable};
+use ms_air_filter_core::{
+    buttons::{Button, ButtonEvent, ButtonPushDuration},
+    config::Config,
+    run_logic::State,
+};
+
+/// How long a screen other than the main screen stays open without a button being pushed.
+pub const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A screen that can be shown by the [`Router`].
+pub trait Screen: Drawable<Color = Color, Output = ()> {
+    /// Makes the next draw redraw everything, e.g. after switching to this screen.
+    fn invalidate(&mut self);
+
+    /// Handles a button push while this screen is shown.
+    ///
+    /// This only sees the pushes that are not used to move between screens.
+    fn handle_button(&mut self, _event: &ButtonEvent) -> Option<Action> {
+        None
+    }
+
+    /// Called when another screen is about to be shown instead of this one.
+    fn leave(&mut self) {}
+}
+
+/// Something a screen asks for that has to be done outside of the UI.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// The filters have been changed
+    FilterChanged,
+    /// The configuration has been edited and should be saved
+    SaveConfig(Config),
+}
+
+/// The screens in the order they are cycled through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScreenId {
+    #[default]
+    Main,
+    Temperatures,
+    Statistics,
+    Settings,
+    Diagnostics,
+}
+
+impl ScreenId {
+    fn next(self) -> Self {
+        match self {
+            Self::Main => Self::Temperatures,
+            Self::Temperatures => Self::Statistics,
+            Self::Statistics => Self::Settings,
+            Self::Settings => Self::Diagnostics,
+            Self::Diagnostics => Self::Main,
+        }
+    }
+}
+
+/// Owns every screen and decides which one is shown.
+///
+/// A long push of the speed button moves on to the next screen, eventually coming back around to
+/// the main screen. On the main screen any other push controls the fan, on any other screen the
+/// buttons operate that screen instead (see [`Router::buttons_captured`]).
+///
+/// Every push has to come here before the run logic, which should only be given it if
+/// [`Router::buttons_captured`] was false before it was handled. Only pushes that the run logic
+/// ignores move between screens, so leaving the main screen passes nothing unwanted on.
+///
+/// While the overtemperature interlock has tripped an alarm covers every screen, and the buttons
+/// are left to the run logic so that the fault can be acknowledged.
+#[derive(Default)]
+pub struct Router {
+    current: ScreenId,
+    last_push: Option<Instant>,
+    alarm: AlarmScreen,
+
+    pub main: MainScreen,
+    pub temperatures: TemperaturesScreen,
+    pub statistics: ServiceScreen,
+    pub settings: SettingsScreen,
+    pub diagnostics: DiagnosticsScreen,
+}
+
+impl Router {
+    pub fn current(&self) -> ScreenId {
+        self.current
+    }
+
+    /// Whether the buttons are operating the UI rather than the fan.
+    pub fn buttons_captured(&self) -> bool {
+        self.current != ScreenId::Main
+    }
+
+    /// Whether the overtemperature alarm is being shown instead of the current screen.
+    pub fn alarm_active(&self) -> bool {
+        self.alarm.active()
+    }
+
+    /// Passes a new state to the main screen, and shows or hides the overtemperature alarm.
+    pub fn update_state(&mut self, state: State) {
+        let was_active = self.alarm.active();
+        self.alarm.update_fault(state.fault().cloned());
+        self.main.update_state(state);
+
+        match (was_active, self.alarm.active()) {
+            (false, true) => {
+                // Anything being done on another screen is abandoned
+                self.show(ScreenId::Main);
+                Screen::invalidate(&mut self.alarm);
+            }
+            (true, false) => Screen::invalidate(&mut self.main),
+            _ => {}
+        }
+    }
+
+    /// Handles a button push, returning anything the current screen needs doing as a result.
+    pub fn handle_button(&mut self, event: &ButtonEvent, now: Instant) -> Option<Action> {
+        self.last_push = Some(now);
+
+        if self.alarm.active() {
+            return None;
+        }
+
+        match (self.current, &event.button, &event.push_duration) {
+            (current, Button::Speed, ButtonPushDuration::Long) => {
+                self.show(current.next());
+                None
+            }
+            (ScreenId::Main, _, _) => None,
+            (_, _, _) => self.screen_mut().handle_button(event),
+        }
+    }
+
+    /// Goes back to the main screen if no button has been pushed for a while.
+    pub fn handle_tick(&mut self, now: Instant) {
+        let inactive = self
+            .last_push
+            .is_none_or(|last_push| now - last_push >= INACTIVITY_TIMEOUT);
+
+        if self.current != ScreenId::Main && inactive {
+            self.show(ScreenId::Main);
+        }
+    }
+
+    /// Makes the next draw redraw everything, e.g. after the display has been reset.
+    pub fn invalidate(&mut self) {
+        if self.alarm.active() {
+            Screen::invalidate(&mut self.alarm);
+        }
+        self.screen_mut().invalidate();
+    }
+
+    fn show(&mut self, screen: ScreenId) {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Showing screen {}", screen);
+
+        self.screen_mut().leave();
+        self.current = screen;
+        self.invalidate();
+    }
+
+    fn screen_mut(&mut self) -> &mut dyn ScreenMut {
+        match self.current {
+            ScreenId::Main => &mut self.main,
+            ScreenId::Temperatures => &mut self.temperatures,
+            ScreenId::Statistics => &mut self.statistics,
+            ScreenId::Settings => &mut self.settings,
+            ScreenId::Diagnostics => &mut self.diagnostics,
+        }
+    }
+}
+
+/// The object safe part of [`Screen`], as `Drawable` is not.
+trait ScreenMut {
+    fn invalidate(&mut self);
+    fn handle_button(&mut self, event: &ButtonEvent) -> Option<Action>;
+    fn leave(&mut self);
+}
+
+impl<S: Screen> ScreenMut for S {
+    fn invalidate(&mut self) {
+        Screen::invalidate(self);
+    }
+
+    fn handle_button(&mut self, event: &ButtonEvent) -> Option<Action> {
+        Screen::handle_button(self, event)
+    }
+
+    fn leave(&mut self) {
+        Screen::leave(self);
+    }
+}
+
+impl Drawable for Router {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        if self.alarm.active() {
+            return self.alarm.draw(target);
+        }
+
+        match self.current {
+            ScreenId::Main => self.main.draw(target),
+            ScreenId::Temperatures => self.temperatures.draw(target),
+            ScreenId::Statistics => self.statistics.draw(target),
+            ScreenId::Settings => self.settings.draw(target),
+            ScreenId::Diagnostics => self.diagnostics.draw(target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ms_air_filter_core::{
+        run_logic::Triggers, settings::SETTINGS, temperature::TemperatureReadings,
+    };
+
+    fn push(button: Button, push_duration: ButtonPushDuration) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            push_duration,
+            clicks: 1,
+        }
+    }
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    #[test]
+    fn long_speed_push_cycles_screens() {
+        let mut router = Router::default();
+        let next = push(Button::Speed, ButtonPushDuration::Long);
+
+        for expected in [
+            ScreenId::Temperatures,
+            ScreenId::Statistics,
+            ScreenId::Settings,
+            ScreenId::Diagnostics,
+            ScreenId::Main,
+        ] {
+            assert_eq!(router.handle_button(&next, at(0)), None);
+            assert_eq!(router.current(), expected);
+            assert_eq!(router.buttons_captured(), expected != ScreenId::Main);
+        }
+    }
+
+    #[test]
+    fn speed_button_operates_screens() {
+        let mut router = Router::default();
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(0));
+        assert_eq!(router.current(), ScreenId::Temperatures);
+
+        // Only a long push moves between screens
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Short), at(1));
+        assert_eq!(router.current(), ScreenId::Temperatures);
+    }
+
+    #[test]
+    fn demand_button_operates_screens() {
+        let mut router = Router::default();
+        let filter_changed = push(Button::Demand, ButtonPushDuration::Long);
+
+        // Stops the fan rather than doing anything to the UI
+        assert_eq!(router.handle_button(&filter_changed, at(0)), None);
+
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(1));
+        assert_eq!(router.handle_button(&filter_changed, at(2)), None);
+
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(3));
+        assert_eq!(router.current(), ScreenId::Statistics);
+        assert_eq!(
+            router.handle_button(&filter_changed, at(4)),
+            Some(Action::FilterChanged)
+        );
+        assert_eq!(
+            router.handle_button(&push(Button::Demand, ButtonPushDuration::Short), at(5)),
+            None
+        );
+        assert_eq!(router.current(), ScreenId::Statistics);
+    }
+
+    #[test]
+    fn returns_to_main_screen_when_inactive() {
+        let mut router = Router::default();
+        router.handle_tick(at(100));
+        assert_eq!(router.current(), ScreenId::Main);
+
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(100));
+        router.handle_tick(at(110));
+        assert_eq!(router.current(), ScreenId::Temperatures);
+
+        // Pushing a button keeps the screen open for longer
+        router.handle_button(&push(Button::Demand, ButtonPushDuration::Short), at(120));
+        router.handle_tick(at(149));
+        assert_eq!(router.current(), ScreenId::Temperatures);
+
+        router.handle_tick(at(150));
+        assert_eq!(router.current(), ScreenId::Main);
+        assert!(!router.buttons_captured());
+    }
+
+    #[test]
+    fn unsaved_settings_are_discarded_when_inactive() {
+        let mut router = Router::default();
+        let next_screen = push(Button::Speed, ButtonPushDuration::Long);
+        let next_setting = push(Button::Speed, ButtonPushDuration::Short);
+        let change = push(Button::Demand, ButtonPushDuration::Short);
+
+        while router.current() != ScreenId::Settings {
+            router.handle_button(&next_screen, at(0));
+        }
+        router.handle_button(&change, at(1));
+
+        router.handle_tick(at(100));
+        assert_eq!(router.current(), ScreenId::Main);
+
+        // Nothing left to save after coming back
+        while router.current() != ScreenId::Settings {
+            router.handle_button(&next_screen, at(101));
+        }
+        for _ in 0..SETTINGS.len() {
+            router.handle_button(&next_setting, at(102));
+        }
+        assert_eq!(router.handle_button(&change, at(103)), None);
+    }
+
+    #[test]
+    fn alarm_covers_every_screen() {
+        let mut config = Config::DEFAULT;
+        config.interlock_sensor = 1;
+        let mut readings = TemperatureReadings::default();
+        let mut triggers = Triggers::default();
+
+        let mut router = Router::default();
+        while router.current() != ScreenId::Settings {
+            router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(0));
+        }
+
+        readings.record(1, 80.0, at(1));
+        triggers.handle_readings(&readings, &config);
+        router.update_state(triggers.resolve());
+        assert!(router.alarm_active());
+        assert_eq!(router.current(), ScreenId::Main);
+
+        // The buttons are left for acknowledging the fault
+        assert!(!router.buttons_captured());
+        router.handle_button(&push(Button::Speed, ButtonPushDuration::Long), at(2));
+        assert_eq!(router.current(), ScreenId::Main);
+
+        readings.record(1, 20.0, at(3));
+        triggers.handle_readings(&readings, &config);
+        router.update_state(triggers.resolve());
+        assert!(router.alarm_active());
+
+        triggers.handle_button(push(Button::Both, ButtonPushDuration::Long), &config);
+        router.update_state(triggers.resolve());
+        assert!(!router.alarm_active());
+    }
+}
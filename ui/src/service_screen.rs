@@ -0,0 +1,193 @@
+use crate::{layout, Action, Color, Screen};
+use core::{cell::RefCell, fmt::Write};
+use embassy_time::Duration;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, Point, WebColors},
+    text::{Alignment, Text},
+    Drawable,
+};
+use ms_air_filter_core::{
+    buttons::{Button, ButtonEvent, ButtonPushDuration},
+    runtime::{FilterStatus, RuntimeMeter},
+    time::format_hours,
+};
+use u8g2_fonts::U8g2TextStyle;
+
+/// Shows how worn the filters are and how long the fan has been run for.
+///
+/// A long push of the demand button records that the filters have been changed.
+#[derive(Default)]
+pub struct ServiceScreen {
+    meter: RuntimeMeter,
+    service_interval: Duration,
+
+    redraw: RefCell<bool>,
+}
+
+impl ServiceScreen {
+    pub fn update(&mut self, meter: RuntimeMeter, service_interval: Duration) {
+        if meter != self.meter || service_interval != self.service_interval {
+            *self.redraw.get_mut() = true;
+        }
+
+        self.meter = meter;
+        self.service_interval = service_interval;
+    }
+}
+
+impl Screen for ServiceScreen {
+    fn invalidate(&mut self) {
+        *self.redraw.get_mut() = true;
+    }
+
+    fn handle_button(&mut self, event: &ButtonEvent) -> Option<Action> {
+        match (&event.button, &event.push_duration) {
+            (Button::Demand, ButtonPushDuration::Long) => Some(Action::FilterChanged),
+            _ => None,
+        }
+    }
+}
+
+impl Drawable for ServiceScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Ok(mut redraw) = self.redraw.try_borrow_mut() else {
+            return Ok(());
+        };
+        if !*redraw {
+            return Ok(());
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Redrawing service screen");
+
+        let center_x = target.bounding_box().center().x;
+
+        layout::title(target, "Filter service")?;
+
+        // Display the filter life remaining, coloured by how urgently they need changing
+        let life = self
+            .meter
+            .filter_life_remaining_percent(self.service_interval);
+
+        let mut life_str = heapless::String::<8>::new();
+        life_str.write_fmt(format_args!("{}%", life)).unwrap();
+
+        Text::with_alignment(
+            &life_str,
+            Point::new(center_x, 88),
+            U8g2TextStyle::new(
+                u8g2_fonts::fonts::u8g2_font_inb42_mr,
+                match FilterStatus::from_life_remaining(life) {
+                    FilterStatus::Ok => Color::CSS_LIME_GREEN,
+                    FilterStatus::DueSoon => Color::CSS_ORANGE,
+                    FilterStatus::Overdue => Color::CSS_RED,
+                },
+            ),
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        Text::with_alignment(
+            "life remaining",
+            Point::new(center_x, 112),
+            MonoTextStyle::new(&FONT_10X20, Color::CSS_GRAY),
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        // Display the run time details
+        let last_change = match self.meter.filter_changes.last() {
+            Some(change) => format_hours(change.at),
+            None => "never".try_into().unwrap(),
+        };
+
+        for (i, (label, value)) in [
+            (
+                "Filter age",
+                format_hours(self.meter.since_filter_change.total()),
+            ),
+            ("Fan run time", format_hours(self.meter.lifetime.total())),
+            ("Changed at", last_change),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let y = 144 + layout::ROW_HEIGHT * i as i32;
+            layout::row(target, y, label, value, Color::CSS_WHITE)?;
+        }
+
+        // Display how to use the screen
+        layout::navigation_hint(target, Some("Hold Demand after changing filters"))?;
+
+        *redraw = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+    use ms_air_filter_core::{config::Config, fan::FanSpeed};
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn render(meter: &RuntimeMeter) -> Framebuffer {
+        let mut frame = Framebuffer::default();
+        let mut screen = ServiceScreen::default();
+        screen.update(meter.clone(), Config::DEFAULT.filter_service_interval);
+        screen.draw(&mut frame).unwrap();
+        frame
+    }
+
+    #[test]
+    fn new_filters() {
+        assert_matches_golden("service_screen_new", &render(&RuntimeMeter::new()));
+    }
+
+    #[test]
+    fn filter_states() {
+        let mut meter = RuntimeMeter::new();
+        meter.add(&FanSpeed::High, HOUR * 320);
+        meter.filter_changed();
+
+        for (hours, name) in [
+            (150, "service_screen_ok"),
+            (460, "service_screen_due"),
+            (520, "service_screen_overdue"),
+        ] {
+            let mut meter = meter.clone();
+            meter.add(&FanSpeed::High, HOUR * hours);
+            assert_matches_golden(name, &render(&meter));
+        }
+    }
+
+    #[test]
+    fn only_redrawn_when_changed() {
+        let mut frame = Framebuffer::default();
+        let mut screen = ServiceScreen::default();
+        let interval = Config::DEFAULT.filter_service_interval;
+
+        let mut meter = RuntimeMeter::new();
+        screen.update(meter.clone(), interval);
+        screen.draw(&mut frame).unwrap();
+
+        // Anything drawn over the screen is left alone until something changes
+        frame.clear(Color::CSS_HOT_PINK).unwrap();
+        screen.update(meter.clone(), interval);
+        screen.draw(&mut frame).unwrap();
+        assert_ne!(frame, render(&meter));
+
+        meter.add(&FanSpeed::Low, HOUR);
+        screen.update(meter.clone(), interval);
+        screen.draw(&mut frame).unwrap();
+        assert_eq!(frame, render(&meter));
+    }
+}
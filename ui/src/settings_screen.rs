@@ -0,0 +1,244 @@
+use crate::{layout, Action, Color, Screen};
+use core::{cell::RefCell, fmt::Write};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, Point, Primitive, Size, WebColors},
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Text},
+    Drawable,
+};
+use ms_air_filter_core::{
+    buttons::{ButtonEvent, ButtonPushDuration},
+    config::Config,
+    settings::{EditStatus, Setting, SettingsEditor, SETTINGS},
+};
+
+/// The number of items that fit on the screen at once, the list scrolls to keep the selected
+/// item in view.
+const VISIBLE_ROWS: usize = 7;
+
+/// Shows the settings that can be changed with the buttons, and lets them be edited.
+///
+/// Edits are only made to a copy of the configuration until they are saved, and are thrown away
+/// if another screen is shown (including going back to the main screen when no button has been
+/// pushed for a while).
+pub struct SettingsScreen {
+    config: Config,
+    editor: SettingsEditor,
+
+    redraw: RefCell<bool>,
+}
+
+impl Default for SettingsScreen {
+    fn default() -> Self {
+        Self {
+            config: Config::DEFAULT,
+            editor: SettingsEditor::default(),
+            redraw: RefCell::new(false),
+        }
+    }
+}
+
+impl SettingsScreen {
+    pub fn update_config(&mut self, config: Config) {
+        if config != self.config {
+            *self.redraw.get_mut() = true;
+        }
+
+        self.config = config;
+    }
+}
+
+impl Screen for SettingsScreen {
+    fn invalidate(&mut self) {
+        *self.redraw.get_mut() = true;
+    }
+
+    fn handle_button(&mut self, event: &ButtonEvent) -> Option<Action> {
+        // Holding a button does nothing until it is released
+        if event.push_duration == ButtonPushDuration::Held {
+            return None;
+        }
+
+        *self.redraw.get_mut() = true;
+
+        let config = self.editor.handle_button(event, &self.config)?;
+
+        // Show the saved values straight away, rather than once the new configuration comes back
+        self.config = config.clone();
+        Some(Action::SaveConfig(config))
+    }
+
+    fn leave(&mut self) {
+        self.editor.discard();
+    }
+}
+
+/// Formats the value of a setting as it is shown on screen.
+fn format_value(setting: &Setting, config: &Config) -> heapless::String<16> {
+    let mut value = heapless::String::new();
+    if let Some(v) = setting.value(config) {
+        let _ = value.write_fmt(format_args!("{v}{}", setting.unit));
+    }
+    value
+}
+
+impl Drawable for SettingsScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Ok(mut redraw) = self.redraw.try_borrow_mut() else {
+            return Ok(());
+        };
+        if !*redraw {
+            return Ok(());
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Redrawing settings screen");
+
+        layout::title(target, "Settings")?;
+
+        let display_box = target.bounding_box();
+        let draft = self.editor.draft(&self.config);
+        let selected = self.editor.selected_index();
+        let first = selected.saturating_sub(VISIBLE_ROWS - 1);
+
+        // Every setting followed by the option to save
+        let items = SETTINGS.iter().map(Some).chain([None]);
+
+        for (i, item) in items.enumerate().skip(first).take(VISIBLE_ROWS) {
+            let y = layout::FIRST_ROW + layout::ROW_HEIGHT * (i - first) as i32;
+
+            if i == selected {
+                Rectangle::new(
+                    Point::new(display_box.top_left.x, y - 17),
+                    Size::new(display_box.size.width, layout::ROW_HEIGHT as u32),
+                )
+                .into_styled(PrimitiveStyle::with_fill(Color::CSS_MIDNIGHT_BLUE))
+                .draw(target)?;
+            }
+
+            match item {
+                Some(setting) => {
+                    let value = format_value(setting, draft);
+
+                    // Values that will change when saved stand out
+                    let color = if value == format_value(setting, &self.config) {
+                        Color::CSS_WHITE
+                    } else {
+                        Color::CSS_ORANGE
+                    };
+
+                    layout::row(target, y, setting.label, &value, color)?;
+                }
+                None => layout::row(target, y, "Save", "", Color::CSS_WHITE)?,
+            }
+        }
+
+        let status = match self.editor.status() {
+            EditStatus::Unchanged => None,
+            EditStatus::Modified => Some(("Not saved yet", Color::CSS_ORANGE)),
+            EditStatus::Saved => Some(("Saved", Color::CSS_LIME_GREEN)),
+            EditStatus::Invalid => Some(("Not valid, check values", Color::CSS_RED)),
+        };
+
+        if let Some((status, color)) = status {
+            Text::with_alignment(
+                status,
+                Point::new(
+                    display_box.center().x,
+                    layout::FIRST_ROW + layout::ROW_HEIGHT * VISIBLE_ROWS as i32,
+                ),
+                MonoTextStyle::new(&FONT_10X20, color),
+                Alignment::Center,
+            )
+            .draw(target)?;
+        }
+
+        layout::navigation_hint(
+            target,
+            Some(match self.editor.selected() {
+                Some(_) => "Speed: next, Demand: +, hold Demand: -",
+                None => "Demand: save, hold Demand: undo all",
+            }),
+        )?;
+
+        *redraw = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+    use ms_air_filter_core::buttons::Button;
+
+    fn push(button: Button, push_duration: ButtonPushDuration) -> ButtonEvent {
+        ButtonEvent {
+            button,
+            push_duration,
+            clicks: 1,
+        }
+    }
+
+    #[test]
+    fn default_settings() {
+        let mut frame = Framebuffer::default();
+        let mut screen = SettingsScreen::default();
+        screen.invalidate();
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("settings_screen", &frame);
+    }
+
+    #[test]
+    fn editing() {
+        let mut frame = Framebuffer::default();
+        let mut screen = SettingsScreen::default();
+
+        // Change the run time then scroll down to the last setting
+        screen.handle_button(&push(Button::Demand, ButtonPushDuration::Short));
+        for _ in 0..SETTINGS.len() - 1 {
+            screen.handle_button(&push(Button::Speed, ButtonPushDuration::Short));
+        }
+        screen.draw(&mut frame).unwrap();
+        assert_matches_golden("settings_screen_editing", &frame);
+    }
+
+    #[test]
+    fn saves_edits() {
+        let mut screen = SettingsScreen::default();
+
+        screen.handle_button(&push(Button::Demand, ButtonPushDuration::Short));
+        for _ in 0..SETTINGS.len() {
+            assert_eq!(
+                screen.handle_button(&push(Button::Speed, ButtonPushDuration::Short)),
+                None
+            );
+        }
+
+        let Some(Action::SaveConfig(config)) =
+            screen.handle_button(&push(Button::Demand, ButtonPushDuration::Short))
+        else {
+            panic!("nothing saved");
+        };
+        assert_ne!(config, Config::DEFAULT);
+        assert_eq!(screen.config, config);
+    }
+
+    #[test]
+    fn leaving_discards_edits() {
+        let mut screen = SettingsScreen::default();
+
+        screen.handle_button(&push(Button::Demand, ButtonPushDuration::Short));
+        screen.leave();
+
+        assert_eq!(screen.editor.draft(&screen.config), &Config::DEFAULT);
+        assert_eq!(screen.editor.status(), EditStatus::Unchanged);
+    }
+}
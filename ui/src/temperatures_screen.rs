@@ -0,0 +1,186 @@
+use crate::{layout, Color, Screen};
+use core::{cell::RefCell, fmt::Write};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    prelude::{DrawTarget, WebColors},
+    text::{Alignment, Text},
+    Drawable,
+};
+use ms_air_filter_core::temperature::{SensorReading, TemperatureReadings};
+
+/// Shows the latest reading from every temperature sensor.
+#[derive(Default)]
+pub struct TemperaturesScreen {
+    readings: TemperatureReadings,
+
+    redraw: RefCell<bool>,
+}
+
+impl TemperaturesScreen {
+    pub fn update_readings(&mut self, readings: TemperatureReadings) {
+        // New readings arrive often, only redraw if they look any different
+        if !self
+            .readings
+            .iter()
+            .map(Row::from)
+            .eq(readings.iter().map(Row::from))
+        {
+            *self.redraw.get_mut() = true;
+        }
+
+        self.readings = readings;
+    }
+}
+
+impl Screen for TemperaturesScreen {
+    fn invalidate(&mut self) {
+        *self.redraw.get_mut() = true;
+    }
+}
+
+/// A sensor reading as it is shown on screen.
+#[derive(PartialEq)]
+struct Row {
+    address: heapless::String<16>,
+    temperature: heapless::String<8>,
+    stale: bool,
+}
+
+impl From<&SensorReading> for Row {
+    fn from(sensor: &SensorReading) -> Self {
+        let mut address = heapless::String::new();
+        address
+            .write_fmt(format_args!("{:016x}", sensor.address))
+            .unwrap();
+
+        let mut temperature = heapless::String::new();
+        match sensor.temperature {
+            Some(t) => temperature.write_fmt(format_args!("{t:.1}C")).unwrap(),
+            None => temperature.push('-').unwrap(),
+        }
+
+        Self {
+            address,
+            temperature,
+            stale: sensor.stale,
+        }
+    }
+}
+
+impl Drawable for TemperaturesScreen {
+    type Output = ();
+    type Color = Color;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Ok(mut redraw) = self.redraw.try_borrow_mut() else {
+            return Ok(());
+        };
+        if !*redraw {
+            return Ok(());
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Redrawing temperatures screen");
+
+        layout::title(target, "Temperatures")?;
+
+        let mut any = false;
+
+        for (i, row) in self.readings.iter().map(Row::from).enumerate() {
+            any = true;
+
+            // Readings from sensors that have gone missing are the last known value
+            let color = if row.stale {
+                Color::CSS_DIM_GRAY
+            } else {
+                Color::CSS_WHITE
+            };
+
+            let y = layout::FIRST_ROW + layout::ROW_HEIGHT * i as i32;
+            layout::row(target, y, &row.address, &row.temperature, color)?;
+        }
+
+        if !any {
+            Text::with_alignment(
+                "No sensors found",
+                target.bounding_box().center(),
+                MonoTextStyle::new(&FONT_10X20, Color::CSS_GRAY),
+                Alignment::Center,
+            )
+            .draw(target)?;
+        }
+
+        layout::navigation_hint(target, None)?;
+
+        *redraw = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::{assert_matches_golden, Framebuffer};
+    use embassy_time::Instant;
+    use ms_air_filter_core::temperature::MAX_SENSORS;
+
+    fn render(readings: &TemperatureReadings) -> Framebuffer {
+        let mut frame = Framebuffer::default();
+        let mut screen = TemperaturesScreen::default();
+        screen.update_readings(readings.clone());
+        screen.invalidate();
+        screen.draw(&mut frame).unwrap();
+        frame
+    }
+
+    #[test]
+    fn no_sensors() {
+        assert_matches_golden(
+            "temperatures_screen_none",
+            &render(&TemperatureReadings::default()),
+        );
+    }
+
+    #[test]
+    fn sensors() {
+        let mut readings = TemperatureReadings::default();
+        readings.record(0x5a00_0000_1234_5628, 21.25, Instant::from_ticks(0));
+        readings.record(0x0300_0000_abcd_ef28, 35.0, Instant::from_ticks(0));
+        readings.record_error(0x8800_0000_0000_0128);
+        assert_matches_golden("temperatures_screen_sensors", &render(&readings));
+
+        readings.mark_all_stale();
+        readings.record(0x0300_0000_abcd_ef28, -4.5, Instant::from_ticks(0));
+        assert_matches_golden("temperatures_screen_stale", &render(&readings));
+    }
+
+    #[test]
+    fn all_sensors_fit() {
+        let mut readings = TemperatureReadings::default();
+        for i in 0..MAX_SENSORS as u64 {
+            readings.record((i << 8) | 0x28, 100.0 - i as f32, Instant::from_ticks(0));
+        }
+        assert_matches_golden("temperatures_screen_full", &render(&readings));
+    }
+
+    #[test]
+    fn only_redrawn_when_readings_look_different() {
+        let mut screen = TemperaturesScreen::default();
+        let mut readings = TemperatureReadings::default();
+        readings.record(0x28, 21.0, Instant::from_ticks(0));
+        screen.update_readings(readings.clone());
+        assert!(*screen.redraw.borrow());
+        screen.draw(&mut Framebuffer::default()).unwrap();
+
+        readings.record(0x28, 21.01, Instant::from_ticks(1000));
+        screen.update_readings(readings.clone());
+        assert!(!*screen.redraw.borrow());
+
+        readings.record(0x28, 21.1, Instant::from_ticks(2000));
+        screen.update_readings(readings);
+        assert!(*screen.redraw.borrow());
+    }
+}